@@ -1,17 +1,53 @@
-use crate::llm_client::LlmClient;
-use crate::session::Session;
+use crate::changes::tool_failed;
+use crate::llm_client::{LlmClient, is_context_length_error};
+use crate::session::{ResultRetention, Session};
+use crate::style::{BRIGHT_GREEN, BRIGHT_RED, YELLOW, paint};
+use crate::tool_cache::ToolCache;
 use crate::tool_registry::ToolRegistry;
-use crate::types::Message;
-use crate::utils::{clip, display_diff_side_by_side};
+use crate::tools::{ApprovalOverrides, LineEndings, TextFormat};
+use crate::types::{Message, ToolCall, WriteStats};
+use crate::utils::clip_observation;
 use async_trait::async_trait;
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
-use std::collections::HashSet;
+use std::sync::Arc;
 use tokio::time::{Duration, timeout};
+use tokio_util::sync::CancellationToken;
+
+/// One streamed piece of an assistant reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamDelta<'a> {
+    Content(&'a str),
+    // Chain-of-thought from providers that send `reasoning_content`
+    Reasoning(&'a str),
+}
+
+/// Callback receiving streamed deltas.
+pub type OnContent<'a> = dyn FnMut(StreamDelta<'_>) + Send + 'a;
 
 #[async_trait]
 pub trait LlmClientTrait {
     async fn chat_once(&self, messages: &[Message], tools: &Value) -> anyhow::Result<Message>;
+
+    // Like chat_once, but reports content deltas as they arrive. Clients
+    // that can't stream deliver the whole content in one chunk.
+    async fn chat_once_streaming(
+        &self,
+        messages: &[Message],
+        tools: &Value,
+        on_content: &mut OnContent<'_>,
+    ) -> anyhow::Result<Message> {
+        let msg = self.chat_once(messages, tools).await?;
+        if let Some(content) = msg.content.as_deref()
+            && !content.is_empty()
+        {
+            on_content(StreamDelta::Content(content));
+        }
+        Ok(msg)
+    }
+
+    #[allow(dead_code)]
     async fn chat_once_no_stream(
         &self,
         messages: &[Message],
@@ -26,11 +62,64 @@ impl LlmClientTrait for LlmClient {
         self.chat_once(messages, tools).await
     }
 
+    async fn chat_once_streaming(
+        &self,
+        messages: &[Message],
+        tools: &Value,
+        on_content: &mut OnContent<'_>,
+    ) -> anyhow::Result<Message> {
+        self.chat_once_streaming(messages, tools, on_content).await
+    }
+
     async fn chat_once_no_stream(&self, messages: &[Message]) -> anyhow::Result<Message> {
         self.chat_once_no_stream(messages).await
     }
 }
 
+/// Receives agent progress as it happens (console printer, test recorder, …).
+pub trait AgentStreamHandler: Send {
+    // Bracket each LLM request, e.g. to show a progress indicator
+    fn on_llm_start(&mut self) {}
+    fn on_llm_end(&mut self) {}
+    // The only place a reply's text is shown; nothing prints it again later
+    fn on_content(&mut self, chunk: &str);
+    fn on_reasoning(&mut self, _chunk: &str) {}
+    // Every call of a multi-call step, before any of them is shown or run
+    fn on_tool_batch(&mut self, _calls: &[ToolCall]) {}
+    fn on_tool_call(&mut self, name: &str, args: &Value);
+    fn on_tool_result(&mut self, name: &str, result: &str);
+    // Decide what to keep of a large tool result (non-yolo runs only)
+    fn review_tool_result(&mut self, _name: &str, _chars: usize) -> ResultRetention {
+        ResultRetention::Full
+    }
+}
+
+// How many assistant steps in a row may send unparseable or incomplete
+// tool arguments before the turn is abandoned.
+const MAX_ARG_REPAIRS: usize = 3;
+
+// Steps left when the model is told to wrap up
+const DEFAULT_WRAP_UP_THRESHOLD: usize = 1;
+
+// Tool results longer than this (after clipping) are offered for review
+const LARGE_RESULT_CHARS: usize = 2000;
+
+// Failed tool calls in a row before the model is told to step back
+const DEFAULT_ERROR_THRESHOLD: usize = 3;
+
+// Prefix of the observations sent back for unparseable arguments, which
+// have their own limit (MAX_ARG_REPAIRS)
+const BAD_ARGS_PREFIX: &str = "Error: invalid JSON arguments";
+// Same, for calls that left out required arguments
+const MISSING_ARGS_PREFIX: &str = "Error: missing required arguments";
+
+// Characters of each pinned file kept in context
+const PINNED_FILE_BUDGET: usize = 8000;
+
+// After a context-length error, tool results are cut to this fraction of
+// observation_clip before the one retry
+const CONTEXT_RETRY_CLIP_DIVISOR: usize = 4;
+
 #[derive(Clone)]
 pub struct AgentOptions {
     pub max_steps: usize,
@@ -39,10 +128,75 @@ pub struct AgentOptions {
     pub observation_clip: usize, // chars per tool output
 }
 
+/// Outcome of one `run_agent_loop` call.
+#[derive(Debug, Clone)]
+pub struct AgentRunResult {
+    pub final_text: Option<String>, // already streamed through on_content
+    pub steps: usize,               // LLM calls made
+    pub hit_step_limit: bool,
+    // The model called task_complete; false when it stopped with a reply,
+    // e.g. a question, and is waiting for the user
+    pub completed: bool,
+    // Every tool call the run made, in order. Kept here because the session
+    // may drop older messages mid-run to fit the context window.
+    pub tool_calls: Vec<RunToolCall>,
+}
+
+/// A tool call made during a run and the result the model was given.
+#[derive(Debug, Clone)]
+pub struct RunToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+    pub result: Option<String>,
+}
+
+/// Session-wide caps on file mutations, a backstop for autonomous runs.
+/// `None` means unlimited.
+#[derive(Clone, Debug, Default)]
+pub struct WriteLimits {
+    pub max_files_written: Option<usize>,
+    pub max_write_bytes: Option<usize>,
+}
+
+// Tools that modify a single file given by their `path` argument, with the
+// argument holding the bytes they write
+const MUTATING_TOOLS: &[(&str, &str)] = &[
+    ("write_file", "content"),
+    ("edit_file", "new_str"),
+    ("edit_file_batch", "edits"), // array of {old_str, new_str}
+    ("insert_in_file", "content"),
+    ("insert_at_line", "content"),
+    ("replace_lines", "new_content"),
+    ("replace_between", "new_content"),
+];
+
+// Tools that write files only named in their result: replace_in_files
+// ("src/a.rs: 2 replacement(s)"), format_code ("  - src/a.rs") and
+// restore_file ("Restored src/a.rs")
+const RESULT_PATH_WRITERS: &[&str] = &["replace_in_files", "format_code", "restore_file"];
+
+// Every tool that changes files on disk, i.e. everything the write limits cover
+fn writes_files(name: &str) -> bool {
+    matches!(name, "apply_changes" | "delete_file")
+        || MUTATING_TOOLS.iter().any(|(tool, _)| *tool == name)
+        || RESULT_PATH_WRITERS.contains(&name)
+}
+
 pub struct Agent {
     llm: Box<dyn LlmClientTrait + Send + Sync>,
     tools: ToolRegistry,
     opts: AgentOptions,
+    write_limits: WriteLimits,
+    wrap_up_threshold: usize,
+    read_window: Option<usize>, // read_file lines when no end_line is given
+    max_read_bytes: Option<u64>, // largest file read_file will load
+    text_format: TextFormat,    // write_file defaults; calls may override
+    error_threshold: usize,
+    verify_after_edit: bool, // append a re-read of the edited region
+    approvals: Arc<ApprovalOverrides>,
+    approval_prompts: bool, // false: refuse gated tools instead of asking
+    tool_cache: Arc<ToolCache>,
 }
 
 impl Agent {
@@ -51,108 +205,376 @@ impl Agent {
         tools: ToolRegistry,
         opts: AgentOptions,
     ) -> Self {
-        Self { llm, tools, opts }
-    }
-
-    // Convenience constructor for real LlmClient
-    pub fn with_real_client(llm: LlmClient, tools: ToolRegistry, opts: AgentOptions) -> Self {
         Self {
-            llm: Box::new(llm),
+            llm,
             tools,
             opts,
+            write_limits: WriteLimits::default(),
+            wrap_up_threshold: DEFAULT_WRAP_UP_THRESHOLD,
+            read_window: None,
+            max_read_bytes: None,
+            text_format: TextFormat::default(),
+            error_threshold: DEFAULT_ERROR_THRESHOLD,
+            verify_after_edit: false,
+            approvals: Arc::new(ApprovalOverrides::default()),
+            approval_prompts: true,
+            tool_cache: Arc::new(ToolCache::default()),
+        }
+    }
+
+    pub fn options(&self) -> &AgentOptions {
+        &self.opts
+    }
+
+    // Overrides `max_steps` for subsequent runs (e.g. `/steps N`)
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.opts.max_steps = max_steps;
+    }
+
+    pub fn tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+
+    pub fn approvals(&self) -> &ApprovalOverrides {
+        &self.approvals
+    }
+
+    // Read-only tool calls that actually executed in the current turn
+    #[cfg(test)]
+    pub fn tool_cache_misses(&self) -> usize {
+        self.tool_cache.misses()
+    }
+
+    // With this many steps left, tell the model to stop and answer. 0 disables.
+    pub fn with_wrap_up_threshold(mut self, steps_left: usize) -> Self {
+        self.wrap_up_threshold = steps_left;
+        self
+    }
+
+    // After this many failed tool calls in a row the model is told to
+    // re-read before retrying; if it trips again the run stops. 0 disables.
+    pub fn with_error_threshold(mut self, failures: usize) -> Self {
+        self.error_threshold = failures;
+        self
+    }
+
+    // Follow each successful file mutation with the edited region as it now
+    // reads on disk, so the model can confirm the change landed correctly
+    pub fn with_verify_after_edit(mut self, verify: bool) -> Self {
+        self.verify_after_edit = verify;
+        self
+    }
+
+    // Tools to ask about beyond the built-in set, or to run without asking
+    pub fn with_approval_overrides(mut self, approvals: ApprovalOverrides) -> Self {
+        self.approvals = Arc::new(approvals);
+        self
+    }
+
+    // Off when nobody can answer a prompt (e.g. --json output for a
+    // pipeline): tools that need approval are refused instead of asked about
+    pub fn with_approval_prompts(mut self, prompts: bool) -> Self {
+        self.approval_prompts = prompts;
+        self
+    }
+
+    // Lines read_file returns when the model doesn't give an end_line
+    pub fn with_default_read_lines(mut self, lines: usize) -> Self {
+        self.read_window = Some(lines);
+        self
+    }
+
+    // Size cap for read_file; raising it lets big data files be read at the
+    // cost of loading them whole into memory
+    pub fn with_max_read_bytes(mut self, bytes: u64) -> Self {
+        self.max_read_bytes = Some(bytes);
+        self
+    }
+
+    // Trailing newline and line endings for write_file when the call
+    // doesn't say
+    pub fn with_text_format(mut self, format: TextFormat) -> Self {
+        self.text_format = format;
+        self
+    }
+
+    pub fn with_write_limits(mut self, limits: WriteLimits) -> Self {
+        self.write_limits = limits;
+        self
+    }
+
+    // Why a tool call can't be dispatched as sent: arguments that aren't
+    // JSON, or required fields left out. None when it's fine to run.
+    fn argument_problem(&self, tc: &ToolCall) -> Option<String> {
+        let args = match serde_json::from_str::<Value>(&tc.function.arguments) {
+            Ok(args) => args,
+            Err(e) => {
+                return Some(format!(
+                    "{} for '{}': {}. Raw arguments: {}. \
+                     Resend the call with valid JSON arguments.",
+                    BAD_ARGS_PREFIX, tc.function.name, e, tc.function.arguments
+                ));
+            }
+        };
+        let missing = missing_required(&args, &self.tools.required_params(&tc.function.name));
+        if missing.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{} for '{}': {}. Resend the call with these fields filled in.",
+            MISSING_ARGS_PREFIX,
+            tc.function.name,
+            missing.join(", ")
+        ))
+    }
+
+    // The call's arguments with wrongly typed values fixed to match the
+    // tool's schema, re-serialized, plus a note per fix
+    fn normalized_arguments(&self, name: &str, raw: &str) -> (String, Vec<String>) {
+        let Ok(mut args) = serde_json::from_str::<Value>(raw) else {
+            return (raw.to_string(), Vec::new());
+        };
+        let notes = self.tools.normalize_args(name, &mut args);
+        let reformatted = name == "write_file" && self.format_written_text(&mut args);
+        if notes.is_empty() && !reformatted {
+            return (raw.to_string(), notes);
+        }
+        (args.to_string(), notes)
+    }
+
+    // Apply the trailing-newline and line-ending settings to write_file's
+    // content, so approval diffs and write limits see what will be written.
+    // Returns whether the content changed.
+    fn format_written_text(&self, args: &mut Value) -> bool {
+        let mut format = self.text_format;
+        if let Some(ensure) = args["ensure_trailing_newline"].as_bool() {
+            format.ensure_trailing_newline = ensure;
+        }
+        if let Some(endings) = args["line_endings"].as_str().and_then(LineEndings::parse) {
+            format.line_endings = endings;
+        }
+        let Some(content) = args["content"].as_str() else {
+            return false;
+        };
+        let formatted = format.apply(content);
+        if formatted == content {
+            return false;
         }
+        args["content"] = Value::String(formatted);
+        true
+    }
+
+    // Reserve a mutating call's writes against `reserved`, the session's
+    // stats plus the calls already admitted this step. Returns an error
+    // message if it would exceed a limit; once a limit is hit every further
+    // mutation in the turn is refused too. The session itself is charged
+    // only for writes that succeed (see `charge_writes`).
+    fn check_write_limits(
+        &self,
+        name: &str,
+        args_raw: &str,
+        stats: &mut WriteStats,
+        halted: &mut bool,
+    ) -> Option<String> {
+        if !writes_files(name) {
+            return None;
+        }
+        if *halted {
+            return Some("Error: write limit reached".to_string());
+        }
+        let args: Value = serde_json::from_str(args_raw).ok()?;
+        let targets = write_targets(name, &args);
+        let bytes: usize = targets.iter().map(|(_, n)| n).sum();
+
+        let new_files: HashSet<&str> = targets
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .filter(|path| !stats.files.contains(*path))
+            .collect();
+        let files = stats.files.len() + new_files.len();
+        // What these will touch is unknown until they run, so they need
+        // room left under both limits
+        let unknown = RESULT_PATH_WRITERS.contains(&name);
+        let over_files = self
+            .write_limits
+            .max_files_written
+            .is_some_and(|max| files > max || (unknown && files >= max));
+        let over_bytes = self
+            .write_limits
+            .max_write_bytes
+            .is_some_and(|max| stats.bytes + bytes > max || (unknown && stats.bytes >= max));
+        if over_files || over_bytes {
+            *halted = true;
+            return Some(format!(
+                "Error: write limit reached ({} file(s), {} bytes written this session)",
+                stats.files.len(),
+                stats.bytes
+            ));
+        }
+
+        stats
+            .files
+            .extend(targets.into_iter().map(|(path, _)| path));
+        stats.bytes += bytes;
+        None
+    }
+
+    // Convenience constructor for real LlmClient
+    pub fn with_real_client(llm: LlmClient, tools: ToolRegistry, opts: AgentOptions) -> Self {
+        Self::new(Box::new(llm), tools, opts)
     }
 
     // Compact older messages to keep context light. We do a simple heuristic:
     // - Keep the last N messages untouched.
-    // - For older "tool" messages, clip content to a budget.
+    // - For older "tool" messages, clip content to a budget (with a header
+    //   giving the original length).
     pub fn compact_history(&self, session: &mut Session) {
         // Example heuristic: clip any tool message content longer than budget.
         for m in session.messages.iter_mut() {
-            if m.role == "tool" {
-                if let Some(c) = &m.content {
-                    if c.len() > self.opts.observation_clip {
-                        let head = &c[..self.opts.observation_clip];
-                        m.content = Some(format!("{}… [truncated]", head));
-                    }
-                }
+            // Pinned files are refreshed, never compacted
+            if Session::is_pinned_context(m) {
+                continue;
+            }
+            if m.role == "tool"
+                && let Some(c) = &m.content
+            {
+                m.content = Some(clip_observation(c, self.opts.observation_clip));
             }
         }
         // You can also drop very old messages if they exceed some count/size.
     }
 
+    // Last resort when the provider rejects the request as too long: drop
+    // the older half of the conversation and cut every remaining tool result
+    // well below the usual clip. Returns how many messages were dropped.
+    pub fn shrink_for_context(&self, session: &mut Session) -> usize {
+        let dropped = session.drop_oldest_half();
+        let budget = (self.opts.observation_clip / CONTEXT_RETRY_CLIP_DIVISOR).max(1);
+        for m in session.messages.iter_mut().filter(|m| m.role == "tool") {
+            if let Some(c) = &m.content {
+                m.content = Some(clip_observation(c, budget));
+            }
+        }
+        dropped
+    }
+
+    // Single turn printed to the console
+    #[cfg(test)]
     pub async fn run_turn(&self, session: &mut Session) -> anyhow::Result<Option<String>> {
+        self.run_turn_with_streaming(session, &mut crate::console::ConsoleStreamHandler::new())
+            .await
+    }
+
+    pub async fn run_turn_with_streaming(
+        &self,
+        session: &mut Session,
+        handler: &mut dyn AgentStreamHandler,
+    ) -> anyhow::Result<Option<String>> {
         self.compact_history(session);
+        session.refresh_pinned_context(PINNED_FILE_BUDGET);
+        self.tool_cache.clear();
 
-        // Single LLM step
-        let llm_step = timeout(
-            self.opts.step_timeout,
-            self.llm.chat_once(&session.messages, self.tools.schemas()),
-        )
-        .await??;
+        // Single LLM step, streaming content to the handler. If the request
+        // doesn't fit the model's context, shrink the history and retry once.
+        let mut shrunk = false;
+        let llm_step = loop {
+            handler.on_llm_start();
+            let llm_step = timeout(
+                self.opts.step_timeout,
+                self.llm.chat_once_streaming(
+                    &session.messages,
+                    self.tools.schemas(),
+                    &mut |delta| match delta {
+                        StreamDelta::Content(chunk) => handler.on_content(chunk),
+                        StreamDelta::Reasoning(chunk) => handler.on_reasoning(chunk),
+                    },
+                ),
+            )
+            .await;
+            handler.on_llm_end();
+            match llm_step? {
+                Ok(message) => break message,
+                Err(e) if is_context_length_error(&format!("{:#}", e)) => {
+                    if shrunk {
+                        anyhow::bail!(
+                            "The conversation is still too long for the model's context window \
+                             after compacting it; start a new session or narrow the request ({:#})",
+                            e
+                        );
+                    }
+                    let dropped = self.shrink_for_context(session);
+                    eprintln!(
+                        "{}",
+                        paint(
+                            YELLOW,
+                            &format!(
+                                "Context window exceeded; dropped {} older messages and retrying",
+                                dropped
+                            )
+                        )
+                    );
+                    shrunk = true;
+                }
+                Err(e) => return Err(e),
+            }
+        };
 
         // Record assistant step
         session.add_message(llm_step.clone());
 
         if let Some(tcs) = &llm_step.tool_calls {
+            if tcs.len() > 1 {
+                handler.on_tool_batch(tcs);
+            }
+            // Show every call, even one whose arguments don't parse; the
+            // handler then gets the raw string so the attempt stays visible
             for tc in tcs {
-                let args: serde_json::Value = match serde_json::from_str(&tc.function.arguments) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        let error_msg = format!(
-                            "Failed to parse tool arguments for '{}': {}. Raw arguments: {}",
-                            tc.function.name, e, tc.function.arguments
-                        );
-                        // eprintln!("\u{001b}[91mWarning:\u{001b}[0m {}", error_msg);
-                        return Err(anyhow::anyhow!(
-                            "Tool argument parsing failed: {}",
-                            error_msg
-                        ));
-                    }
-                };
+                let args = serde_json::from_str(&tc.function.arguments)
+                    .unwrap_or_else(|_| Value::String(tc.function.arguments.clone()));
+                handler.on_tool_call(&tc.function.name, &args);
+            }
 
-                println!("\n\u{001b}[35m▌🔧 {}\u{001b}[0m", tc.function.name);
+            // Malformed or incomplete arguments go back to the model as
+            // observations so it can resend the call, instead of failing the
+            // whole turn or running the tool with empty defaults.
+            let bad_args: Vec<(usize, String)> = tcs
+                .iter()
+                .enumerate()
+                .filter_map(|(i, tc)| self.argument_problem(tc).map(|e| (i, e)))
+                .collect();
 
-                // Special handling for edit_file
-                if tc.function.name == "edit_file" {
-                    if let (Some(old_str), Some(new_str)) = (
-                        args.get("old_str").and_then(|v| v.as_str()),
-                        args.get("new_str").and_then(|v| v.as_str()),
-                    ) {
-                        display_diff_side_by_side(old_str, new_str);
+            if !bad_args.is_empty() {
+                let repairs = consecutive_bad_arg_steps(&session.messages, |tc| {
+                    self.argument_problem(tc).is_some()
+                });
+                if repairs > MAX_ARG_REPAIRS {
+                    let (i, e) = &bad_args[0];
+                    return Err(anyhow::anyhow!(
+                        "Tool arguments were invalid {} times in a row; last error for '{}': {}",
+                        repairs,
+                        tcs[*i].function.name,
+                        e
+                    ));
+                }
 
-                        if let Some(path) = args.get("path").and_then(|v| v.as_str()) {
-                            println!("\u{001b}[90m   Path: {}\u{001b}[0m", path);
-                        }
-                    }
-                } else if tc.function.name == "read_file" {
-                    let path = args
-                        .get("path")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("(missing path)");
-                    let start = args
-                        .get("start_line")
-                        .and_then(|v| v.as_u64())
-                        .map(|n| n.to_string());
-                    let end = args
-                        .get("end_line")
-                        .and_then(|v| v.as_u64())
-                        .map(|n| n.to_string());
-
-                    let range = match (start.as_deref(), end.as_deref()) {
-                        (Some(s), Some(e)) => format!(" (lines {}-{})", s, e),
-                        (Some(s), None) => format!(" (from line {})", s),
-                        (None, Some(e)) => format!(" (through line {})", e),
-                        (None, None) => String::new(),
+                for (i, tc) in tcs.iter().enumerate() {
+                    let observation = match bad_args.iter().find(|(j, _)| *j == i) {
+                        Some((_, e)) => e.clone(),
+                        None => "Not executed: another tool call in this step had invalid \
+                                 arguments. Resend it if still needed."
+                            .to_string(),
                     };
-
-                    println!("\u{001b}[90mRead File: {}{}\u{001b}[0m", path, range);
-                } else {
-                    // For other tools, show pretty JSON
-                    let pretty_args = serde_json::to_string_pretty(&args)
-                        .unwrap_or_else(|_| tc.function.arguments.clone());
-                    println!("\u{001b}[90m{}\u{001b}[0m", pretty_args);
+                    handler.on_tool_result(&tc.function.name, &observation);
+                    session.add_message(Message {
+                        role: "tool".into(),
+                        content: Some(observation),
+                        tool_calls: None,
+                        tool_call_id: Some(tc.id.clone()),
+                        name: Some(tc.function.name.clone()),
+                        created_at: None,
+                    });
                 }
+                return Ok(None);
             }
         }
 
@@ -176,181 +598,445 @@ impl Agent {
         for m in &session.messages {
             if let Some(calls) = &m.tool_calls {
                 for call in calls {
-                    if call.function.name == "read_file" {
-                        if let Ok(v) = serde_json::from_str::<Value>(&call.function.arguments) {
-                            if let Some(p) = v.get("path").and_then(|x| x.as_str()) {
-                                read_files_history.insert(p.to_string());
-                            }
-                        }
+                    if call.function.name == "read_file"
+                        && let Ok(v) = serde_json::from_str::<Value>(&call.function.arguments)
+                        && let Some(p) = v.get("path").and_then(|x| x.as_str())
+                    {
+                        read_files_history.insert(p.to_string());
                     }
                 }
             }
         }
         let mut tasks = vec![];
+        let mut writes_halted = false;
+        let mut reserved_writes = session.write_stats.clone();
+        // Shared by this step's run_shell calls; written back once they finish
+        let shell_cwd = Arc::new(std::sync::Mutex::new(
+            session.shell_cwd.as_ref().map(std::path::PathBuf::from),
+        ));
 
         for tool_call in tool_calls {
             let name = tool_call.function.name.clone();
             let id = tool_call.id.clone();
-            let args_raw = tool_call.function.arguments.clone();
+            let (args_raw, coerced) =
+                self.normalized_arguments(&name, &tool_call.function.arguments);
             let yolo = self.opts.yolo;
             let read_files_history = read_files_history.clone();
+            let cache = self.tool_cache.clone();
+            let read_window = self.read_window;
+            let max_read_bytes = self.max_read_bytes;
+            let verify = self.verify_after_edit;
+            let shell_cwd = shell_cwd.clone();
+            let approvals = self.approvals.clone();
+
+            let refusal = if !yolo && !self.approval_prompts && approvals.requires_approval(&name) {
+                Some(format!(
+                    "Error: {} needs approval and this run can't prompt for it; \
+                     list it in TERMX_SKIP_APPROVAL to allow it",
+                    name
+                ))
+            } else {
+                self.check_write_limits(&name, &args_raw, &mut reserved_writes, &mut writes_halted)
+            };
+            if let Some(refusal) = refusal {
+                tasks.push((
+                    name,
+                    id,
+                    tokio::spawn(async move { Ok::<String, anyhow::Error>(refusal) }),
+                ));
+                continue;
+            }
+
+            tasks.push((name.clone(), id, tokio::spawn(async move {
+                // Parse args safely
+                let args: Value = match serde_json::from_str(&args_raw) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        let error_msg = format!(
+                            "JSON parsing error for tool '{}': {}. Arguments received: {}",
+                            name, e, args_raw
+                        );
+                        eprintln!("{} {}", paint(BRIGHT_RED, "Error:"), error_msg);
+                        return Ok::<String, anyhow::Error>(format!(
+                            "Failed to parse tool arguments: {}",
+                            e
+                        ));
+                    }
+                };
 
-            tasks.push(tokio::spawn(async move {
                 // Approval (synchronous user prompt) unless YOLO
-                if !yolo && crate::tools::requires_approval(&name) {
+                if !yolo && approvals.requires_approval(&name) {
                     let approval_prompt = crate::tools::format_tool_approval();
                     print!("{}", approval_prompt);
+                    if let Some(details) = crate::tools::approval_details(&name, &args) {
+                        println!("{}", paint(YELLOW, &details));
+                    }
                     let _ = io::stdout().flush();
 
-                    match crate::tools::get_user_approval("Proceed") {
+                    let question = crate::tools::approval_question(&name, &args);
+                    match crate::tools::get_user_approval(&question) {
                         Ok(true) => {
-                            println!("\u{001b}[92m✓ Approved\u{001b}[0m");
+                            println!("{}", paint(BRIGHT_GREEN, "✓ Approved"));
                         }
                         Ok(false) => {
-                            println!("\u{001b}[91m✗ Denied by user\u{001b}[0m");
-                            return Ok::<(String, String), anyhow::Error>((
-                                id,
+                            println!("{}", paint(BRIGHT_RED, "✗ Denied by user"));
+                            return Ok::<String, anyhow::Error>(
                                 "User denied execution".to_string(),
-                            ));
+                            );
                         }
                         Err(e) => {
                             eprintln!("Approval error: {}", e);
-                            return Ok::<(String, String), anyhow::Error>((
-                                id,
-                                format!("Error: {}", e),
-                            ));
+                            return Ok::<String, anyhow::Error>(format!("Error: {}", e));
                         }
                     }
                 }
 
-                // Parse args safely
-                let args: Value = match serde_json::from_str(&args_raw) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        let error_msg = format!(
-                            "JSON parsing error for tool '{}': {}. Arguments received: {}",
-                            name, e, args_raw
-                        );
-                        eprintln!("\u{001b}[91mError:\u{001b}[0m {}", error_msg);
-                        return Ok::<(String, String), anyhow::Error>((
-                            id,
-                            format!("Failed to parse tool arguments: {}", e),
-                        ));
-                    }
-                };
-
-                // Dispatch
-                let obs = match name.as_str() {
-                    "list_dir" => {
-                        let path = args["path"].as_str().unwrap_or(".");
-                        let list = crate::tools::list_dir(path);
-                        if list.is_empty() {
-                            "Directory is empty".to_string()
-                        } else {
-                            list.join("\n")
+                // Dispatch (read-only calls are memoized for the turn)
+                let dispatch = async {
+                    match name.as_str() {
+                        "list_dir" => {
+                            let path = args["path"].as_str().unwrap_or(".");
+                            let list = crate::tools::list_dir(path);
+                            if list.is_empty() {
+                                "Directory is empty".to_string()
+                            } else {
+                                list.join("\n")
+                            }
                         }
-                    }
-                    "read_file" => {
-                        let path = args["path"].as_str().unwrap_or("");
-                        let start = args
-                            .get("start_line")
-                            .and_then(|v| v.as_u64())
-                            .map(|n| n as usize);
-                        let end = args
-                            .get("end_line")
-                            .and_then(|v| v.as_u64())
-                            .map(|n| n as usize);
-                        crate::tools::read_file(path, start, end)
-                            .unwrap_or_else(|e| format!("Error: {}", e))
-                    }
-                    "write_file" => {
-                        let path = args["path"].as_str().unwrap_or("");
-                        if !path.is_empty() && !read_files_history.contains(path) {
-                            // Enforce read-before-write policy and return feedback to the agent
-                            format!(
-                                "Policy: read the file before writing. Please call read_file on '{}' first.",
-                                path
+                        "tree" => {
+                            let path = args["path"].as_str().unwrap_or(".");
+                            let max_depth = args["max_depth"].as_u64().map(|n| n as usize);
+                            let max_entries = args["max_entries"].as_u64().map(|n| n as usize);
+                            crate::tools::tree(path, max_depth, max_entries)
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "file_stats" => {
+                            let path = args["path"].as_str().unwrap_or(".");
+                            crate::tools::file_stats(path).unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "path_exists" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            crate::tools::path_exists(path).unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "glob" => {
+                            let pattern = args["pattern"].as_str().unwrap_or("");
+                            let root = args["root"].as_str();
+                            match crate::tools::glob(pattern, root) {
+                                Ok(paths) if paths.is_empty() => {
+                                    format!("No files match '{}'", pattern)
+                                }
+                                Ok(paths) => paths.join("\n"),
+                                Err(e) => format!("Error: {}", e),
+                            }
+                        }
+                        "read_file" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            let start = args
+                                .get("start_line")
+                                .and_then(|v| v.as_u64())
+                                .map(|n| n as usize);
+                            let end = args
+                                .get("end_line")
+                                .and_then(|v| v.as_u64())
+                                .map(|n| n as usize);
+                            let result = if args["hexdump"].as_bool().unwrap_or(false) {
+                                let start_byte = args["start_byte"].as_u64();
+                                let len = args["len"].as_u64().map(|n| n as usize);
+                                crate::tools::read_file_hex(path, start_byte, len)
+                            } else {
+                                let strict = args["strict"].as_bool().unwrap_or(false);
+                                crate::tools::read_file(path, start, end, strict, read_window, max_read_bytes)
+                            };
+                            result.unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "write_file" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            let create_only = args["create_only"].as_bool().unwrap_or(false);
+                            // A file that must not exist yet has nothing to read first
+                            if !create_only && !path.is_empty() && !read_files_history.contains(path) {
+                                // Enforce read-before-write policy and return feedback to the agent
+                                format!(
+                                    "Policy: read the file before writing. Please call read_file on '{}' first.",
+                                    path
+                                )
+                            } else {
+                                let content = args["content"].as_str().unwrap_or("");
+                                crate::tools::write_file(path, content, create_only)
+                                    .unwrap_or_else(|e| format!("Error: {}", e))
+                            }
+                        }
+                        "apply_changes" => {
+                            match serde_json::from_value::<Vec<crate::tools::Change>>(args["changes"].clone()) {
+                                Ok(changes) => {
+                                    // Same read-before-write policy as write_file
+                                    let unread = changes.iter().find(|c| {
+                                        matches!(c, crate::tools::Change::Write { .. })
+                                            && std::path::Path::new(c.path()).exists()
+                                            && !read_files_history.contains(c.path())
+                                    });
+                                    match unread {
+                                        Some(c) => format!(
+                                            "Policy: read the file before writing. Please call read_file on '{}' first.",
+                                            c.path()
+                                        ),
+                                        None => crate::tools::apply_changes(&changes)
+                                            .unwrap_or_else(|e| format!("Error: {}", e)),
+                                    }
+                                }
+                                Err(e) => format!(
+                                    "Error: changes must be an array of {{op, path, ...}} objects: {}",
+                                    e
+                                ),
+                            }
+                        }
+                        "delete_file" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            let safe_delete = args["safe_delete"].as_bool().unwrap_or(true);
+                            crate::tools::delete_file(path, safe_delete, std::path::Path::new("."))
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        // Ends the run; the summary is taken from the call itself
+                        "task_complete" => "Task marked complete".to_string(),
+                        "restore_file" => {
+                            let root = std::path::Path::new(".");
+                            match args["id"].as_str() {
+                                Some(id) => crate::tools::restore_from_trash(id, root)
+                                    .unwrap_or_else(|e| format!("Error: {}", e)),
+                                None => match crate::tools::list_trash(root) {
+                                    Ok(ids) if ids.is_empty() => "Trash is empty".to_string(),
+                                    Ok(ids) => format!("Trashed files (newest first):\n{}", ids.join("\n")),
+                                    Err(e) => format!("Error: {}", e),
+                                },
+                            }
+                        }
+                        "run_shell" => {
+                            let cmd = args["command"].as_str().unwrap_or("");
+                            let env: HashMap<String, String> = args["env"]
+                                .as_object()
+                                .map(|vars| {
+                                    vars.iter()
+                                        .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                                        .collect()
+                                })
+                                .unwrap_or_default();
+                            let clear_env = args["clear_env"].as_bool().unwrap_or(false);
+                            let stdin = args["stdin"].as_str();
+                            let mut cwd = shell_cwd.lock().unwrap().clone();
+                            let result =
+                                crate::tools::run_shell(cmd, None, &env, clear_env, &mut cwd, stdin);
+                            *shell_cwd.lock().unwrap() = cwd;
+                            result.unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "run_tests" => {
+                            let cmd = args.get("command").and_then(|v| v.as_str());
+                            crate::tools::run_tests(cmd).unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "cargo_check" => {
+                            let all_targets = args["all_targets"].as_bool().unwrap_or(false);
+                            crate::tools::cargo_check(all_targets)
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "format_code" => {
+                            let path = args.get("path").and_then(|v| v.as_str());
+                            crate::tools::format_code(path).unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "search_in_files" => {
+                            let path = args["path"].as_str().unwrap_or(".");
+                            let case_sensitive = args.get("case_sensitive").and_then(|v| v.as_bool());
+                            let pattern = args["pattern"].as_str().unwrap_or("");
+                            let word_boundary = args["word_boundary"].as_bool().unwrap_or(false);
+                            let min_line_len = args["min_line_len"].as_u64().map(|n| n as usize);
+                            let multiline = args["multiline"].as_bool().unwrap_or(false);
+                            crate::tools::search_in_files(
+                                pattern,
+                                path,
+                                case_sensitive,
+                                word_boundary,
+                                min_line_len,
+                                multiline,
                             )
-                        } else {
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "edit_file" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            let old_str = args["old_str"].as_str().unwrap_or("");
+                            let new_str = args["new_str"].as_str().unwrap_or("");
+                            let occurrence = args["occurrence"].as_u64().map(|n| n as usize);
+                            let within_start = args["within_start"].as_u64().map(|n| n as usize);
+                            let within_end = args["within_end"].as_u64().map(|n| n as usize);
+                            // Either bound alone leaves the other side open
+                            let within = (within_start.is_some() || within_end.is_some()).then(|| {
+                                (within_start.unwrap_or(1), within_end.unwrap_or(usize::MAX))
+                            });
+                            crate::tools::edit_file(path, old_str, new_str, occurrence, within)
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "edit_file_batch" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            match serde_json::from_value::<Vec<crate::tools::Edit>>(args["edits"].clone()) {
+                                Ok(edits) => crate::tools::edit_file_batch(path, &edits)
+                                    .unwrap_or_else(|e| format!("Error: {}", e)),
+                                Err(e) => format!(
+                                    "Error: edits must be an array of {{old_str, new_str}} objects: {}",
+                                    e
+                                ),
+                            }
+                        }
+                        "insert_in_file" => {
+                            let path = args["path"].as_str().unwrap_or(".");
                             let content = args["content"].as_str().unwrap_or("");
-                            crate::tools::write_file(path, content)
+                            let anchor = args["anchor"].as_str().unwrap_or("");
+                            let position = args["position"].as_str().unwrap_or("");
+                            let fuzzy = args["fuzzy"].as_bool().unwrap_or(false);
+
+                            crate::tools::insert_in_file(path, anchor, content, position, fuzzy)
                                 .unwrap_or_else(|e| format!("Error: {}", e))
                         }
+                        "insert_at_line" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            let line = args["line"].as_u64().unwrap_or(0) as usize;
+                            let content = args["content"].as_str().unwrap_or("");
+                            let position = args["position"].as_str().unwrap_or("");
+
+                            crate::tools::insert_at_line(path, line, content, position)
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "replace_lines" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            let start = args["start_line"].as_u64().unwrap_or(0) as usize;
+                            let end = args["end_line"].as_u64().unwrap_or(0) as usize;
+                            let new_content = args["new_content"].as_str().unwrap_or("");
+
+                            crate::tools::replace_lines(path, start, end, new_content)
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "replace_between" => {
+                            let path = args["path"].as_str().unwrap_or("");
+                            let begin = args["begin_marker"].as_str().unwrap_or("");
+                            let end = args["end_marker"].as_str().unwrap_or("");
+                            let new_content = args["new_content"].as_str().unwrap_or("");
+
+                            crate::tools::replace_between(path, begin, end, new_content)
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "replace_in_files" => {
+                            let pattern = args["pattern"].as_str().unwrap_or("");
+                            let replacement = args["replacement"].as_str().unwrap_or("");
+                            let path = args["path"].as_str().unwrap_or(".");
+                            let regex = args["regex"].as_bool().unwrap_or(false);
+                            let dry_run = args["dry_run"].as_bool().unwrap_or(false);
+                            crate::tools::replace_in_files(pattern, replacement, path, regex, dry_run)
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        #[cfg(feature = "web-search")]
+                        "web_search" => {
+                            let query = args["query"].as_str().unwrap_or("");
+                            let max_results = args["max_results"].as_u64().map(|n| n as usize);
+                            crate::tools::web_search(query, max_results)
+                                .await
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "http_fetch" => {
+                            let url = args["url"].as_str().unwrap_or("");
+                            let max_bytes = args
+                                .get("max_bytes")
+                                .and_then(|v| v.as_u64())
+                                .map(|n| n as usize);
+                            crate::tools::http_fetch(url, max_bytes)
+                                .await
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        "ask_orackle" => {
+                            let query = args["query"].as_str().unwrap_or("");
+                            crate::tools::ask_orackle(query)
+                                .await
+                                .unwrap_or_else(|e| format!("Error: {}", e))
+                        }
+                        _ => "Error: unknown tool".to_string(),
                     }
-                    "run_shell" => {
-                        let cmd = args["command"].as_str().unwrap_or("");
-                        crate::tools::run_shell(cmd).unwrap_or_else(|e| format!("Error: {}", e))
-                    }
-                    "search_in_files" => {
-                        let path = args["path"].as_str().unwrap_or(".");
-                        let case_sensitive = args.get("case_sensitive").and_then(|v| v.as_bool());
-                        let pattern = args["pattern"].as_str().unwrap_or("");
-                        crate::tools::search_in_files(pattern, path, case_sensitive)
-                            .unwrap_or_else(|e| format!("Error: {}", e))
-                    }
-                    "edit_file" => {
-                        let path = args["path"].as_str().unwrap_or("");
-                        let old_str = args["old_str"].as_str().unwrap_or("");
-                        let new_str = args["new_str"].as_str().unwrap_or("");
-                        crate::tools::edit_file(path, old_str, new_str)
-                            .unwrap_or_else(|e| format!("Error: {}", e))
-                    }
-                    "insert_in_file" => {
-                        let path = args["path"].as_str().unwrap_or(".");
-                        let content = args["content"].as_str().unwrap_or("");
-                        let anchor = args["anchor"].as_str().unwrap_or("");
-                        let position = args["position"].as_str().unwrap_or("");
-
-                        crate::tools::insert_in_file(path, anchor, content, position)
-                            .unwrap_or_else(|e| format!("Error: {}", e))
-                    }
-                    "ask_orackle" => {
-                        let query = args["query"].as_str().unwrap_or("");
-                        crate::tools::ask_orackle(query)
-                            .await
-                            .unwrap_or_else(|e| format!("Error: {}", e))
-                    }
-                    _ => "Error: unknown tool".to_string(),
                 };
+                let mut obs = cache.get_or_run(&name, &args, dispatch).await;
+                if !coerced.is_empty() {
+                    obs.push_str(&format!("\n[arguments normalized: {}]", coerced.join(", ")));
+                }
+                if verify
+                    && mutation_succeeded(&name, &obs)
+                    && let Some(excerpt) = verification_excerpt(&name, &args)
+                {
+                    obs.push_str(&excerpt);
+                }
 
-                Ok::<(String, String), anyhow::Error>((id, obs))
-            }));
+                Ok::<String, anyhow::Error>(obs)
+            })));
         }
 
-        // Gather results and append as tool messages (Observations)
-        for t in tasks {
+        // Gather results and append as tool messages (Observations). Every
+        // one answers its call by id, even when the task failed, or the
+        // provider rejects the next request.
+        for (tool_name, tool_call_id, t) in tasks {
             match t.await {
-                Ok(Ok((tool_call_id, observation))) => {
+                Ok(Ok(observation)) => {
+                    handler.on_tool_result(&tool_name, &observation);
+                    if let Some(mut args) = call_args(&llm_step, &tool_call_id) {
+                        self.tools.normalize_args(&tool_name, &mut args);
+                        session.changes.record(&tool_name, &args, &observation);
+                        charge_writes(&mut session.write_stats, &tool_name, &args, &observation);
+                    }
                     // Clip observation to keep context small
-                    let clipped = clip(&observation, self.opts.observation_clip);
+                    let clipped = clip_observation(&observation, self.opts.observation_clip);
+                    let chars = clipped.chars().count();
                     session.add_message(Message {
                         role: "tool".to_string(),
                         content: Some(clipped),
                         tool_calls: None,
-                        tool_call_id: Some(tool_call_id),
+                        tool_call_id: Some(tool_call_id.clone()),
+                        name: Some(tool_name.clone()),
+                        created_at: None,
                     });
+                    if !self.opts.yolo && chars > LARGE_RESULT_CHARS {
+                        let retention = handler.review_tool_result(&tool_name, chars);
+                        session.retain_tool_result(&tool_call_id, retention);
+                    }
                 }
                 Ok(Err(e)) => {
+                    handler.on_tool_result(&tool_name, &format!("Error: {}", e));
                     session.add_message(Message {
                         role: "tool".to_string(),
                         content: Some(format!("Error: {}", e)),
                         tool_calls: None,
-                        tool_call_id: None,
+                        tool_call_id: Some(tool_call_id),
+                        name: Some(tool_name),
+                        created_at: None,
                     });
                 }
                 Err(join_err) => {
+                    handler.on_tool_result(&tool_name, &format!("Error: {}", join_err));
                     session.add_message(Message {
                         role: "tool".to_string(),
                         content: Some(format!("Join error: {}", join_err)),
                         tool_calls: None,
-                        tool_call_id: None,
+                        tool_call_id: Some(tool_call_id),
+                        name: Some(tool_name),
+                        created_at: None,
                     });
                 }
             }
         }
 
+        session.shell_cwd = shell_cwd
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|dir| dir.display().to_string());
+
+        // task_complete ends the run once the step's other calls are done
+        if let Some(summary) = completion_summary(&llm_step) {
+            handler.on_content(&summary);
+            return Ok(Some(summary));
+        }
+
         // After appending Observations, we do not return a final answer yet.
-        // The caller will call run_turn again, which lets the LLM continue.
+        // The caller will run another turn, which lets the LLM continue.
         Ok(None)
     }
 
@@ -358,27 +1044,408 @@ impl Agent {
         &self,
         initial_user_input: String,
         session: &mut Session,
-    ) -> anyhow::Result<()> {
+        handler: &mut dyn AgentStreamHandler,
+    ) -> anyhow::Result<AgentRunResult> {
         // Seed with user input
         session.add_message(Message {
             role: "user".into(),
             content: Some(initial_user_input),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
 
+        let mut breaker_tripped = false;
+        let mut tool_calls = Vec::new();
         for step in 0..self.opts.max_steps {
-            let final_text = self.run_turn(session).await?;
-            if let Some(_output) = final_text {
-                return Ok(());
+            let steps_left = self.opts.max_steps - step;
+            if steps_left == self.wrap_up_threshold && step > 0 {
+                session.add_message(Message {
+                    role: "user".into(),
+                    content: Some(wrap_up_instruction(steps_left)),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    name: None,
+                    created_at: None,
+                });
+            }
+            let final_text = self.run_turn_with_streaming(session, handler).await?;
+            let last_step = session
+                .messages
+                .iter()
+                .rev()
+                .find(|m| m.role == "assistant");
+            tool_calls.extend(
+                last_step
+                    .map(|m| step_calls(m, session))
+                    .unwrap_or_default(),
+            );
+            if final_text.is_some() {
+                return Ok(AgentRunResult {
+                    final_text,
+                    steps: step + 1,
+                    hit_step_limit: false,
+                    completed: last_step.and_then(completion_summary).is_some(),
+                    tool_calls,
+                });
             }
             // If run_turn returned None, it means tools were called and
             // Observations appended. Continue the loop to let LLM react.
-            if step + 1 == self.opts.max_steps {
-                // If we reach max steps without final text, summarize last turn
-                println!("(Reached step limit without final answer.)");
+            let failures = consecutive_tool_errors(&session.messages);
+            if failures.is_empty() {
+                // A success ends the streak; a later one starts fresh
+                breaker_tripped = false;
+            }
+            if self.error_threshold > 0 && failures.len() >= self.error_threshold {
+                if breaker_tripped {
+                    anyhow::bail!(
+                        "Stopping: {} tool calls failed in a row again after a reminder ({})",
+                        failures.len(),
+                        failures.join(", ")
+                    );
+                }
+                breaker_tripped = true;
+                session.add_message(Message {
+                    role: "user".into(),
+                    content: Some(repeated_failure_instruction(&failures)),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    name: None,
+                    created_at: None,
+                });
             }
         }
-        Ok(())
+        // Out of steps without a final answer; callers decide how to report it
+        Ok(AgentRunResult {
+            final_text: None,
+            steps: self.opts.max_steps,
+            hit_step_limit: true,
+            completed: false,
+            tool_calls,
+        })
     }
+
+    // Run the agent loop until it finishes or `cancel` fires. Returns
+    // Ok(None) when cancelled; the session stays usable for the next prompt
+    // and keeps whatever part of the reply had already streamed in.
+    pub async fn run_agent_loop_cancellable(
+        &self,
+        initial_user_input: String,
+        session: &mut Session,
+        handler: &mut dyn AgentStreamHandler,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Option<AgentRunResult>> {
+        let mut capture = PartialReply {
+            inner: handler,
+            text: String::new(),
+        };
+        let outcome = tokio::select! {
+            res = self.run_agent_loop(initial_user_input, session, &mut capture) => Some(res),
+            _ = cancel.cancelled() => None,
+        };
+
+        match outcome {
+            Some(res) => res.map(Some),
+            None => {
+                session.close_dangling_tool_calls("Cancelled by user");
+                if !capture.text.is_empty() {
+                    session.add_message(Message {
+                        role: "assistant".into(),
+                        content: Some(capture.text),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                        created_at: None,
+                    });
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+// Forwards to the real handler while keeping the content streamed by the
+// current LLM call, which is only recorded in the session once complete
+struct PartialReply<'a> {
+    inner: &'a mut dyn AgentStreamHandler,
+    text: String,
+}
+
+impl AgentStreamHandler for PartialReply<'_> {
+    fn on_llm_start(&mut self) {
+        self.text.clear();
+        self.inner.on_llm_start();
+    }
+
+    fn on_llm_end(&mut self) {
+        // The reply is added to the session right after this
+        self.text.clear();
+        self.inner.on_llm_end();
+    }
+
+    fn on_content(&mut self, chunk: &str) {
+        self.text.push_str(chunk);
+        self.inner.on_content(chunk);
+    }
+
+    fn on_reasoning(&mut self, chunk: &str) {
+        self.inner.on_reasoning(chunk);
+    }
+
+    fn on_tool_batch(&mut self, calls: &[ToolCall]) {
+        self.inner.on_tool_batch(calls);
+    }
+
+    fn on_tool_call(&mut self, name: &str, args: &Value) {
+        self.inner.on_tool_call(name, args);
+    }
+
+    fn on_tool_result(&mut self, name: &str, result: &str) {
+        self.inner.on_tool_result(name, result);
+    }
+
+    fn review_tool_result(&mut self, name: &str, chars: usize) -> ResultRetention {
+        self.inner.review_tool_result(name, chars)
+    }
+}
+
+// Number of most recent assistant steps (newest first, stopping at the first
+// clean one) whose tool calls included bad arguments.
+fn consecutive_bad_arg_steps(messages: &[Message], is_bad: impl Fn(&ToolCall) -> bool) -> usize {
+    messages
+        .iter()
+        .rev()
+        .filter(|m| m.role != "tool")
+        .take_while(|m| {
+            m.role == "assistant"
+                && m.tool_calls
+                    .as_ref()
+                    .is_some_and(|calls| calls.iter().any(&is_bad))
+        })
+        .count()
+}
+
+// Required parameters left out of `args`. Empty strings only count for
+// `path`, since an empty `new_str` or `content` is a legitimate deletion.
+fn missing_required<'a>(args: &Value, required: &[&'a str]) -> Vec<&'a str> {
+    required
+        .iter()
+        .copied()
+        .filter(|p| match args.get(*p) {
+            None | Some(Value::Null) => true,
+            Some(Value::String(s)) => *p == "path" && s.trim().is_empty(),
+            Some(_) => false,
+        })
+        .collect()
+}
+
+// Lines of context shown around an edit when verifying it
+const VERIFY_CONTEXT_LINES: usize = 3;
+// Lines shown when the edited region can't be located
+const VERIFY_FALLBACK_LINES: usize = 40;
+
+// The summary of a task_complete call in an assistant step, if it has one
+fn completion_summary(step: &Message) -> Option<String> {
+    step.tool_calls
+        .iter()
+        .flatten()
+        .filter(|tc| tc.function.name == "task_complete")
+        .find_map(|tc| {
+            let args: Value = serde_json::from_str(&tc.function.arguments).ok()?;
+            let summary = args["summary"].as_str()?.trim();
+            Some(
+                if summary.is_empty() {
+                    "Task complete."
+                } else {
+                    summary
+                }
+                .to_string(),
+            )
+        })
+}
+
+// The calls in an assistant step with their results, which follow it
+fn step_calls(step: &Message, session: &Session) -> Vec<RunToolCall> {
+    step.tool_calls
+        .iter()
+        .flatten()
+        .map(|call| RunToolCall {
+            id: call.id.clone(),
+            name: call.function.name.clone(),
+            arguments: call.function.arguments.clone(),
+            result: session
+                .messages
+                .iter()
+                .rev()
+                .find(|m| m.role == "tool" && m.tool_call_id.as_deref() == Some(&call.id))
+                .and_then(|m| m.content.clone()),
+        })
+        .collect()
+}
+
+// Parsed arguments of the call with this id in an assistant step
+fn call_args(step: &Message, id: &str) -> Option<Value> {
+    let call = step.tool_calls.as_ref()?.iter().find(|tc| tc.id == id)?;
+    serde_json::from_str(&call.function.arguments).ok()
+}
+
+// Files a mutating call writes and the bytes it writes to each
+fn write_targets(name: &str, args: &Value) -> Vec<(String, usize)> {
+    let text_len = |value: &Value| value.as_str().map_or(0, str::len);
+    if name == "apply_changes" {
+        return args["changes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|change| {
+                let path = change["path"].as_str().unwrap_or("").to_string();
+                let bytes = text_len(&change["content"]) + text_len(&change["new_str"]);
+                (path, bytes)
+            })
+            .collect();
+    }
+    if name == "delete_file" {
+        return vec![(args["path"].as_str().unwrap_or("").to_string(), 0)];
+    }
+    let Some((_, content_key)) = MUTATING_TOOLS.iter().find(|(tool, _)| *tool == name) else {
+        return Vec::new();
+    };
+    let bytes = match &args[*content_key] {
+        Value::Array(edits) => edits.iter().map(|e| text_len(&e["new_str"])).sum(),
+        value => text_len(value),
+    };
+    vec![(args["path"].as_str().unwrap_or("").to_string(), bytes)]
+}
+
+// Files a finished call wrote and the bytes written to each, read from the
+// result for tools whose targets aren't in their arguments
+fn written_targets(name: &str, args: &Value, observation: &str) -> Vec<(String, usize)> {
+    match name {
+        // A dry run ("Dry run: would make …") wrote nothing
+        "replace_in_files" if observation.starts_with("Made") => {
+            let replacement = args["replacement"].as_str().map_or(0, str::len);
+            observation
+                .lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let (file, count) = line.rsplit_once(": ")?;
+                    let count: usize = count.split_whitespace().next()?.parse().ok()?;
+                    Some((file.to_string(), replacement * count))
+                })
+                .collect()
+        }
+        // Reformatting rewrites files without adding content; count files only
+        "format_code" => observation
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.trim().strip_prefix("- "))
+            .map(|file| (file.to_string(), 0))
+            .collect(),
+        "restore_file" => observation
+            .strip_prefix("Restored ")
+            .map(|file| vec![(file.trim().to_string(), 0)])
+            .unwrap_or_default(),
+        "replace_in_files" => Vec::new(),
+        _ => write_targets(name, args),
+    }
+}
+
+// Count a finished call's writes against the session's budget, unless it
+// failed, was refused or was denied
+fn charge_writes(stats: &mut WriteStats, name: &str, args: &Value, observation: &str) {
+    if tool_failed(observation) {
+        return;
+    }
+    for (path, bytes) in written_targets(name, args, observation) {
+        if !path.is_empty() {
+            stats.files.insert(path);
+        }
+        stats.bytes += bytes;
+    }
+}
+
+fn mutation_succeeded(name: &str, observation: &str) -> bool {
+    MUTATING_TOOLS.iter().any(|(tool, _)| *tool == name) && !tool_failed(observation)
+}
+
+// The edited part of the file as it now reads on disk, for
+// `verify_after_edit`. The region is found from the tool's arguments.
+fn verification_excerpt(name: &str, args: &Value) -> Option<String> {
+    let path = args["path"].as_str()?;
+    let content = std::fs::read_to_string(path).ok()?;
+
+    let written = match name {
+        "edit_file" => args["new_str"].as_str(),
+        "edit_file_batch" => args["edits"][0]["new_str"].as_str(),
+        "insert_in_file" | "insert_at_line" => args["content"].as_str(),
+        "replace_lines" | "replace_between" => args["new_content"].as_str(),
+        _ => None,
+    };
+    let located = written
+        .filter(|w| !w.is_empty())
+        .and_then(|w| content.find(w).map(|at| (at, w)));
+    let (start, end) = match located {
+        Some((at, w)) => {
+            let first = content[..at].matches('\n').count() + 1;
+            let last = first + w.trim_end_matches('\n').matches('\n').count();
+            (
+                first.saturating_sub(VERIFY_CONTEXT_LINES).max(1),
+                last + VERIFY_CONTEXT_LINES,
+            )
+        }
+        None => (1, VERIFY_FALLBACK_LINES),
+    };
+
+    let region = crate::tools::read_file(path, Some(start), Some(end), false, None, None).ok()?;
+    Some(format!(
+        "\n\n[verify] {} lines {}-{} after the change:\n{}",
+        path, start, end, region
+    ))
+}
+
+// Names of the tool calls that failed most recently in a row (newest
+// first), stopping at a success or at the last user message. Observations
+// for unparseable arguments don't count either way.
+fn consecutive_tool_errors(messages: &[Message]) -> Vec<String> {
+    let mut failures = Vec::new();
+    for m in messages.iter().rev() {
+        match m.role.as_str() {
+            "assistant" => continue,
+            "tool" => {
+                let content = m.content.as_deref().unwrap_or("");
+                if content.starts_with(BAD_ARGS_PREFIX)
+                    || content.starts_with(MISSING_ARGS_PREFIX)
+                    || content.starts_with("Not executed")
+                {
+                    continue;
+                }
+                if !content.starts_with("Error") {
+                    break;
+                }
+                failures.push(m.name.clone().unwrap_or_else(|| "tool".to_string()));
+            }
+            _ => break,
+        }
+    }
+    failures
+}
+
+pub fn repeated_failure_instruction(failures: &[String]) -> String {
+    format!(
+        "[repeated failures] Your last {} tool calls all failed ({}). Stop \
+         retrying the same approach: re-read the relevant files to check their \
+         current contents, then try again.",
+        failures.len(),
+        failures.join(", ")
+    )
+}
+
+pub fn wrap_up_instruction(steps_left: usize) -> String {
+    format!(
+        "[step budget] You have {} step(s) left. Stop calling tools unless \
+         absolutely necessary and give your final answer now, noting anything \
+         left unfinished.",
+        steps_left
+    )
 }
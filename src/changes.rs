@@ -0,0 +1,172 @@
+use serde_json::Value;
+
+// Observations that mean the tool didn't run or didn't do anything
+const FAILURE_PREFIXES: &[&str] = &["Error", "Policy", "User denied"];
+
+/// What happened to a file over the session, after merging every change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileChange {
+    Created,
+    Edited,
+    Deleted,
+}
+
+/// Files changed and shell commands run during a session, recorded from
+/// the tool calls that actually took effect rather than what the model says
+/// it did. Shell commands that ran but failed are kept, marked with how
+/// they ended.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeLog {
+    pub files: Vec<(String, FileChange)>, // in the order first touched
+    pub commands: Vec<String>,
+}
+
+impl ChangeLog {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.commands.is_empty()
+    }
+
+    /// Records the effect of one finished tool call. Failed, denied and
+    /// read-only calls are ignored, except shell commands that did run.
+    pub fn record(&mut self, name: &str, args: &Value, observation: &str) {
+        if name == "run_shell" {
+            self.record_command(args, observation);
+            return;
+        }
+        if tool_failed(observation) {
+            return;
+        }
+        let path = args["path"].as_str().unwrap_or("");
+        match name {
+            "write_file" if observation.starts_with("Created") => {
+                self.record_file(path, FileChange::Created)
+            }
+            "write_file" | "edit_file" | "edit_file_batch" | "insert_in_file"
+            | "insert_at_line" | "replace_lines" | "replace_between" => {
+                self.record_file(path, FileChange::Edited)
+            }
+            "delete_file" => self.record_file(path, FileChange::Deleted),
+            // "  - create src/a.rs" per change; "create" is a write to a new file
+            "apply_changes" => {
+                for line in observation.lines().skip(1) {
+                    let Some((op, path)) = line
+                        .trim()
+                        .strip_prefix("- ")
+                        .and_then(|l| l.split_once(' '))
+                    else {
+                        continue;
+                    };
+                    let kind = match op {
+                        "create" => FileChange::Created,
+                        "delete" => FileChange::Deleted,
+                        _ => FileChange::Edited,
+                    };
+                    self.record_file(path, kind);
+                }
+            }
+            // "src/a.rs: 2 replacement(s)" per file, unless it was a dry run
+            "replace_in_files" if observation.starts_with("Made") => {
+                for line in observation.lines().skip(1) {
+                    if let Some((file, _)) = line.rsplit_once(": ") {
+                        self.record_file(file, FileChange::Edited);
+                    }
+                }
+            }
+            // "  - src/a.rs" per reformatted file
+            "format_code" => {
+                for line in observation.lines().skip(1) {
+                    if let Some(file) = line.trim().strip_prefix("- ") {
+                        self.record_file(file, FileChange::Edited);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // A command that failed still ran if run_shell gave its exit status
+    // ("[exit code 1]", "[killed]") or it timed out; refused or blocked
+    // ones didn't
+    fn record_command(&mut self, args: &Value, observation: &str) {
+        let Some(command) = args["command"].as_str() else {
+            return;
+        };
+        if !tool_failed(observation) {
+            self.commands.push(command.to_string());
+            return;
+        }
+        let exit = observation.lines().rev().find_map(|line| {
+            let marker = line.strip_prefix('[')?.strip_suffix(']')?;
+            (marker.starts_with("exit code ") || marker == "killed").then_some(marker)
+        });
+        let ended = match exit {
+            Some(marker) => marker,
+            None if observation.contains("timed out") => "timed out",
+            None => return,
+        };
+        self.commands.push(format!("{} ({})", command, ended));
+    }
+
+    fn record_file(&mut self, path: &str, change: FileChange) {
+        if path.is_empty() {
+            return;
+        }
+        let Some(i) = self.files.iter().position(|(p, _)| p == path) else {
+            self.files.push((path.to_string(), change));
+            return;
+        };
+        let merged = match (self.files[i].1, change) {
+            // Made and removed within the session: nothing left to report
+            (FileChange::Created, FileChange::Deleted) => {
+                self.files.remove(i);
+                return;
+            }
+            (FileChange::Created, _) => FileChange::Created,
+            (FileChange::Deleted, FileChange::Created) => FileChange::Edited,
+            (_, change) => change,
+        };
+        self.files[i].1 = merged;
+    }
+}
+
+/// True when a tool's observation reports that it failed or was refused.
+pub fn tool_failed(observation: &str) -> bool {
+    FAILURE_PREFIXES
+        .iter()
+        .any(|prefix| observation.starts_with(prefix))
+}
+
+/// The "Changes this session" report: files grouped by what happened to
+/// them, then the shell commands in the order they ran.
+pub fn format_change_report(log: &ChangeLog) -> String {
+    if log.is_empty() {
+        return "Changes this session: none".to_string();
+    }
+    let mut report = "Changes this session:".to_string();
+    for (label, kind) in [
+        ("Created", FileChange::Created),
+        ("Edited", FileChange::Edited),
+        ("Deleted", FileChange::Deleted),
+    ] {
+        let files: Vec<&str> = log
+            .files
+            .iter()
+            .filter(|(_, change)| *change == kind)
+            .map(|(path, _)| path.as_str())
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        report.push_str(&format!("\n  {} ({}):", label, files.len()));
+        for file in files {
+            report.push_str(&format!("\n    {}", file));
+        }
+    }
+    if !log.commands.is_empty() {
+        report.push_str(&format!("\n  Commands run ({}):", log.commands.len()));
+        for command in &log.commands {
+            report.push_str(&format!("\n    $ {}", command));
+        }
+    }
+    report
+}
@@ -0,0 +1,234 @@
+use crate::agent::AgentRunResult;
+use crate::session::Session;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use serde_json::{Value, json};
+use std::io::{self, Write};
+
+/// Command-line options. With no prompt, termx starts the interactive REPL.
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub prompt: Option<String>, // one-shot: run this prompt and exit
+    pub json: bool,             // one-shot: print a single JSON report
+    pub no_color: bool,
+    pub max_steps: Option<usize>, // overrides the agent's step budget
+    pub show_reasoning: bool,     // print model reasoning instead of a marker
+    pub extra_instructions: Option<String>, // appended after the system prompt
+    pub import: Option<String>,   // transcript file to start from
+    pub resume: Option<String>,   // saved session id (or prefix) to continue
+    pub sessions: Option<SessionsCommand>, // run instead of the agent
+}
+
+/// `termx sessions` actions that print something and exit.
+#[derive(Debug, PartialEq)]
+pub enum SessionsCommand {
+    List,
+    /// Messages of a saved session as JSON `--import` accepts
+    Export {
+        id: String,
+        output: Option<String>,
+    },
+}
+
+/// A coding agent for your terminal. Without a subcommand or --prompt it
+/// starts the interactive REPL; piped stdin runs as a one-shot prompt.
+#[derive(Debug, Parser)]
+#[command(name = "termx", version)]
+struct Cli {
+    /// Run this prompt and exit (same as `termx run <PROMPT>`)
+    #[arg(short, long, value_name = "TEXT")]
+    prompt: Option<String>,
+
+    #[command(flatten)]
+    options: Options,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+// Accepted before or after any subcommand
+#[derive(Debug, Args)]
+struct Options {
+    /// Print a single JSON report (one-shot runs only)
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Disable colored output
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Step budget for each request
+    #[arg(long, global = true, value_name = "N", value_parser = parse_step_count)]
+    max_steps: Option<usize>,
+
+    /// Print the model's reasoning instead of a marker
+    #[arg(long, global = true)]
+    show_reasoning: bool,
+
+    /// Text appended after the system prompt [fallback: TERMX_EXTRA_INSTRUCTIONS]
+    #[arg(long, global = true, value_name = "TEXT")]
+    extra_instructions: Option<String>,
+
+    /// Start from a transcript exported by termx or another tool
+    #[arg(long, global = true, value_name = "FILE")]
+    import: Option<String>,
+
+    /// Continue a saved session (id or unique prefix)
+    #[arg(long, global = true, value_name = "ID")]
+    resume: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum CliCommand {
+    /// Start the interactive REPL (the default)
+    Chat,
+    /// Run one prompt and exit
+    Run {
+        /// The task to run
+        prompt: String,
+    },
+    /// List, export or resume saved sessions
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SessionsAction {
+    /// List saved sessions, newest first
+    List,
+    /// Print a saved session's messages as JSON
+    Export {
+        id: String,
+        /// Write to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+    },
+    /// Continue a saved session in the REPL
+    Resume { id: String },
+}
+
+/// Parses the arguments after the program name. `--help` and `--version`
+/// come back as errors too; `clap::Error::exit` prints them properly.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<CliArgs, clap::Error> {
+    let cli = Cli::try_parse_from(std::iter::once("termx".to_string()).chain(args))?;
+    let options = cli.options;
+    let mut parsed = CliArgs {
+        prompt: cli.prompt,
+        json: options.json,
+        no_color: options.no_color,
+        max_steps: options.max_steps,
+        show_reasoning: options.show_reasoning,
+        extra_instructions: options.extra_instructions,
+        import: options.import,
+        resume: options.resume,
+        sessions: None,
+    };
+    match cli.command {
+        None => {}
+        Some(_) if parsed.prompt.is_some() => {
+            return Err(usage_error(
+                ErrorKind::ArgumentConflict,
+                "--prompt can't be combined with a subcommand; use `termx run <PROMPT>`",
+            ));
+        }
+        Some(CliCommand::Chat) => {}
+        Some(CliCommand::Run { prompt }) => parsed.prompt = Some(prompt),
+        Some(CliCommand::Sessions { action }) => match action {
+            SessionsAction::List => parsed.sessions = Some(SessionsCommand::List),
+            SessionsAction::Export { id, output } => {
+                parsed.sessions = Some(SessionsCommand::Export { id, output })
+            }
+            SessionsAction::Resume { id } => parsed.resume = Some(id),
+        },
+    }
+    if parsed.json && parsed.prompt.is_none() {
+        return Err(usage_error(
+            ErrorKind::MissingRequiredArgument,
+            "--json requires --prompt or `termx run <PROMPT>`",
+        ));
+    }
+    Ok(parsed)
+}
+
+fn usage_error(kind: ErrorKind, message: &str) -> clap::Error {
+    Cli::command().error(kind, message)
+}
+
+/// The one-shot prompt to run, or `None` for the interactive REPL. Without
+/// `--prompt`, input piped into a non-terminal stdin (`echo "task" | termx`)
+/// is read whole and becomes the prompt; an empty pipe is an error rather
+/// than a REPL nobody can type into.
+pub fn resolve_prompt(
+    prompt: Option<String>,
+    stdin_is_terminal: bool,
+    read_stdin: impl FnOnce() -> io::Result<String>,
+) -> Result<Option<String>, String> {
+    if prompt.is_some() || stdin_is_terminal {
+        return Ok(prompt);
+    }
+    let piped = read_stdin().map_err(|e| format!("Failed to read the prompt from stdin: {}", e))?;
+    let piped = piped.trim();
+    if piped.is_empty() {
+        return Err(
+            "stdin is not a terminal and held no prompt; pipe in a task or pass --prompt"
+                .to_string(),
+        );
+    }
+    Ok(Some(piped.to_string()))
+}
+
+/// Parses a step budget for `--max-steps` or `/steps`; must be at least 1.
+pub fn parse_step_count(value: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(n) if n >= 1 => Ok(n),
+        _ => Err(format!(
+            "Invalid step count '{}': expected a whole number of at least 1",
+            value.trim()
+        )),
+    }
+}
+
+/// Writes the `--json` report as the one document on `out`.
+pub fn write_json_report(
+    out: &mut impl Write,
+    result: &AgentRunResult,
+    session: &Session,
+) -> io::Result<()> {
+    let report = serde_json::to_string_pretty(&json_report(result, session))?;
+    writeln!(out, "{}", report)
+}
+
+/// JSON summary of a one-shot run: final answer, every tool call the run
+/// made (with its result), and step counts.
+pub fn json_report(result: &AgentRunResult, session: &Session) -> Value {
+    let tool_calls: Vec<Value> = result
+        .tool_calls
+        .iter()
+        .map(|call| {
+            let arguments = serde_json::from_str::<Value>(&call.arguments)
+                .unwrap_or_else(|_| Value::String(call.arguments.clone()));
+            json!({
+                "id": call.id,
+                "name": call.name,
+                "arguments": arguments,
+                "result": call.result,
+            })
+        })
+        .collect();
+
+    json!({
+        "final": result.final_text,
+        "tool_calls": tool_calls,
+        // The provider's token counts aren't tracked, only call counts
+        "usage": {
+            "llm_calls": result.steps,
+            "tool_calls": tool_calls.len(),
+        },
+        "steps": result.steps,
+        "hit_step_limit": result.hit_step_limit,
+        "completed": result.completed,
+        "session_id": session.id,
+    })
+}
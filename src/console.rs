@@ -0,0 +1,647 @@
+use crate::agent::{AgentOptions, AgentStreamHandler};
+use crate::session::{ResultRetention, Session};
+use crate::style::{BRIGHT_CYAN, BRIGHT_RED, GRAY, MAGENTA, YELLOW, paint, paint_with};
+use crate::tool_registry::ToolRegistry;
+use crate::tools::ask_result_retention;
+use crate::types::{Message, ToolCall};
+use crate::utils::{clip, display_diff_side_by_side};
+use serde_json::Value;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const RESULT_PREVIEW_CHARS: usize = 300;
+pub const ARG_PREVIEW_CHARS: usize = 500;
+// Characters of each @-mentioned file attached to a prompt
+const MENTION_FILE_CHARS: usize = 8000;
+// Arguments shown in a batch summary, first present wins
+const BATCH_KEY_ARGS: &[&str] = &["pattern", "command", "url", "path", "question", "id"];
+// Characters of that argument shown per line
+const BATCH_KEY_CHARS: usize = 60;
+const REASONING_HIDDEN: &str = "[reasoning hidden]";
+const SPINNER_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_TICK: Duration = Duration::from_millis(100);
+
+/// Labels printed before the user's input and the agent's reply in the REPL,
+/// with their SGR color codes (empty for none).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplLabels {
+    pub user: String,
+    pub user_color: String,
+    pub agent: String,
+    pub agent_color: String,
+}
+
+impl Default for ReplLabels {
+    fn default() -> Self {
+        Self {
+            user: "You:".to_string(),
+            user_color: YELLOW.to_string(),
+            agent: "Agent:".to_string(),
+            agent_color: BRIGHT_CYAN.to_string(),
+        }
+    }
+}
+
+impl ReplLabels {
+    /// The input prompt, e.g. "You: ", colored only when `color` is on.
+    pub fn user_prompt(&self, color: bool) -> String {
+        format!("{} ", paint_with(color, &self.user_color, &self.user))
+    }
+
+    /// Printed before the agent's streamed reply.
+    pub fn agent_prompt(&self, color: bool) -> String {
+        format!("{} ", paint_with(color, &self.agent_color, &self.agent))
+    }
+}
+
+/// Trims a line read from the prompt. Returns None for empty or
+/// whitespace-only input, which should not reach the model.
+pub fn prepare_input(raw: &str) -> Option<&str> {
+    let trimmed = raw.trim();
+    (!trimmed.is_empty()).then_some(trimmed)
+}
+
+/// Attaches the contents of files mentioned as `@path` to the prompt, so the
+/// model doesn't need a `read_file` call first. Mentions that aren't readable
+/// text files stay as literal text and are returned as the second value.
+pub fn expand_mentions(input: &str) -> (String, Vec<String>) {
+    let mut attached: Vec<String> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+    let mut out = input.to_string();
+    for word in input.split_whitespace() {
+        let Some(mention) = word.strip_prefix('@') else {
+            continue;
+        };
+        // Allow "see @src/main.rs." and "(@notes.md)"
+        let path = mention.trim_end_matches([',', '.', ';', ':', '!', '?', ')']);
+        if path.is_empty() || attached.iter().chain(&missing).any(|p| p == path) {
+            continue;
+        }
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                out.push_str(&format!(
+                    "\n\nContents of {}:\n```\n{}\n```",
+                    path,
+                    clip(content.trim_end(), MENTION_FILE_CHARS)
+                ));
+                attached.push(path.to_string());
+            }
+            Err(_) => missing.push(path.to_string()),
+        }
+    }
+    (out, missing)
+}
+
+/// If `input` is the slash command `name` (alone or followed by a space),
+/// returns its trimmed argument.
+pub fn command_arg<'a>(input: &'a str, name: &str) -> Option<&'a str> {
+    let rest = input.strip_prefix(name)?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
+
+/// Handles `/title [text]`: sets the session title, or reports the current
+/// one when no text is given. Returns the line to print.
+pub fn title_command(session: &mut Session, arg: &str) -> String {
+    if arg.is_empty() {
+        return match &session.title {
+            Some(title) => format!("Title: {}", title),
+            None => "Untitled (a title is generated from the first prompt on save)".to_string(),
+        };
+    }
+    session.set_title(Some(arg));
+    format!("Title set to: {}", arg)
+}
+
+/// Renders a message for `/raw`: role, content and each tool call with its
+/// arguments pretty-printed (or raw if they aren't valid JSON).
+pub fn format_raw_message(message: &Message) -> String {
+    let mut out = vec![format!("role: {}", message.role)];
+    match message.content.as_deref() {
+        Some(content) if !content.is_empty() => out.push(format!("content:\n{}", content)),
+        _ => out.push("content: (none)".to_string()),
+    }
+    if let Some(calls) = &message.tool_calls {
+        out.push(format!("tool_calls: {}", calls.len()));
+        for (i, call) in calls.iter().enumerate() {
+            out.push(format!(
+                "  [{}] {} (id {})",
+                i + 1,
+                call.function.name,
+                call.id
+            ));
+            let args = match serde_json::from_str::<Value>(&call.function.arguments) {
+                Ok(v) => serde_json::to_string_pretty(&v).unwrap_or_default(),
+                Err(_) => format!("{} (invalid JSON)", call.function.arguments),
+            };
+            for line in args.lines() {
+                out.push(format!("      {}", line));
+            }
+        }
+    }
+    out.join("\n")
+}
+
+/// Renders the effective configuration for `/config`. Only the scheme and
+/// host of `base_url` are shown, so credentials or keys embedded in the URL
+/// never reach the terminal.
+pub fn format_config(base_url: &str, model: &str, opts: &AgentOptions, tools: &[&str]) -> String {
+    let endpoint = match reqwest::Url::parse(base_url) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}://{}:{}", url.scheme(), host, port),
+            (Some(host), None) => format!("{}://{}", url.scheme(), host),
+            (None, _) => "(no host)".to_string(),
+        },
+        Err(_) => "(invalid URL)".to_string(),
+    };
+    [
+        format!("Base URL:      {}", endpoint),
+        format!("Model:         {}", model),
+        format!("Max steps:     {}", opts.max_steps),
+        format!("Auto-approve:  {}", if opts.yolo { "on" } else { "off" }),
+        format!("Step timeout:  {}s", opts.step_timeout.as_secs()),
+        format!("Output clip:   {} chars", opts.observation_clip),
+        format!("Tools ({}):     {}", tools.len(), tools.join(", ")),
+    ]
+    .join("\n")
+}
+
+/// The editor to launch for `/open`: `$VISUAL`, then `$EDITOR`, then a
+/// platform default, split into program and arguments (e.g. `code --wait`).
+pub fn editor_command(visual: Option<&str>, editor: Option<&str>) -> Vec<String> {
+    let configured = [visual, editor]
+        .into_iter()
+        .flatten()
+        .find(|cmd| !cmd.trim().is_empty());
+    match configured {
+        Some(cmd) => cmd.split_whitespace().map(String::from).collect(),
+        None if cfg!(target_os = "windows") => vec!["notepad".to_string()],
+        None => vec!["vi".to_string()],
+    }
+}
+
+/// Opens `path` in the user's editor and waits for it to close. Returns
+/// whether the file's contents changed meanwhile.
+pub fn open_in_editor(path: &str) -> io::Result<bool> {
+    let visual = std::env::var("VISUAL").ok();
+    let editor = std::env::var("EDITOR").ok();
+    let command = editor_command(visual.as_deref(), editor.as_deref());
+    let before = std::fs::read(path).ok();
+
+    let status = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .arg(path)
+        .status()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "could not start editor '{}' ({}); set $EDITOR or $VISUAL",
+                    command[0], e
+                ),
+            )
+        })?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "editor '{}' exited with {}",
+            command[0], status
+        )));
+    }
+    Ok(std::fs::read(path).ok() != before)
+}
+
+/// Tells the model about a file the user changed by hand, with its new
+/// contents, so the next turn doesn't work from a stale copy.
+pub fn user_edit_message(path: &str) -> Message {
+    let content = std::fs::read_to_string(path)
+        .map(|c| clip(c.trim_end(), MENTION_FILE_CHARS))
+        .unwrap_or_else(|e| format!("(unreadable: {})", e));
+    Message {
+        role: "user".into(),
+        content: Some(format!(
+            "[I edited {} by hand in my editor. It now reads:]\n```\n{}\n```",
+            path, content
+        )),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+        created_at: None,
+    }
+}
+
+/// Renders the tool list for `/tools`: each tool with its description, a
+/// marker when calls need approval, and its parameters (required first)
+/// with type and description.
+pub fn format_tools(tools: &ToolRegistry, needs_approval: impl Fn(&str) -> bool) -> String {
+    let schemas = tools.schemas().as_array().cloned().unwrap_or_default();
+    let mut out = vec![format!("Tools ({}):", schemas.len())];
+    for schema in &schemas {
+        let function = &schema["function"];
+        let name = function["name"].as_str().unwrap_or("(unnamed)");
+        let approval = if needs_approval(name) {
+            " [needs approval]"
+        } else {
+            ""
+        };
+        out.push(format!("\n{}{}", name, approval));
+        if let Some(description) = function["description"].as_str() {
+            out.push(format!("  {}", description));
+        }
+
+        let required = tools.required_params(name);
+        let Some(params) = function["parameters"]["properties"].as_object() else {
+            continue;
+        };
+        let (mut first, rest): (Vec<_>, Vec<_>) = params
+            .iter()
+            .partition(|(p, _)| required.contains(&p.as_str()));
+        first.extend(rest);
+        for (param, spec) in first {
+            let kind = spec["type"].as_str().unwrap_or("any");
+            let flag = if required.contains(&param.as_str()) {
+                ", required"
+            } else {
+                ""
+            };
+            let mut line = format!("    {} ({}{})", param, kind, flag);
+            if let Some(description) = spec["description"].as_str() {
+                line.push_str(&format!(": {}", description));
+            }
+            out.push(line);
+        }
+    }
+    out.join("\n")
+}
+
+/// Preview of a tool call's arguments, shown before it runs. `write_file`
+/// shows the path and content size rather than the content itself; other
+/// tools get pretty JSON clipped to `limit` characters.
+pub fn format_tool_args(name: &str, args: &Value, limit: usize) -> String {
+    if name == "write_file" {
+        let path = args["path"].as_str().unwrap_or("(missing path)");
+        let bytes = args["content"].as_str().map_or(0, str::len);
+        return format!("Path: {}\nContent: {} bytes", path, bytes);
+    }
+    let pretty = serde_json::to_string_pretty(args).unwrap_or_else(|_| args.to_string());
+    clip(&pretty, limit)
+}
+
+/// One line per planned tool call, numbered, with the argument that says
+/// most about it (path, command, pattern, …), so a whole step's batch can be
+/// seen before the first call runs or asks for approval.
+pub fn format_tool_batch(calls: &[ToolCall]) -> String {
+    let mut out = format!("Planned tool calls ({}):", calls.len());
+    for (i, call) in calls.iter().enumerate() {
+        let args: Value = serde_json::from_str(&call.function.arguments).unwrap_or(Value::Null);
+        let key = BATCH_KEY_ARGS
+            .iter()
+            .find_map(|k| args[*k].as_str())
+            .map(|v| v.split_whitespace().collect::<Vec<_>>().join(" "));
+        out.push_str(&format!("\n  {}. {}", i + 1, call.function.name));
+        if let Some(key) = key.filter(|k| !k.is_empty()) {
+            out.push(' ');
+            if key.chars().count() > BATCH_KEY_CHARS {
+                out.extend(key.chars().take(BATCH_KEY_CHARS));
+                out.push('…');
+            } else {
+                out.push_str(&key);
+            }
+        }
+    }
+    out
+}
+
+/// Makes model or tool text safe to print: ANSI escape sequences (colors,
+/// cursor moves, OSC titles) are removed and other control characters are
+/// shown in caret notation (`^G`) or as `\u{..}`, so output can't clear or
+/// garble the terminal. Newlines and tabs pass through; `\r\n` becomes
+/// `\n`. Only for display: the session keeps the raw text for the model.
+pub fn sanitize_for_display(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' | '\t' => out.push(c),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\u{1b}' => match chars.next() {
+                // CSI: parameters and intermediates up to a final byte
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\u{40}'..='\u{7e}').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC and other strings: up to BEL or ESC \\
+                Some(']' | 'P' | 'X' | '^' | '_') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' || (c == '\u{1b}' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                // Two-character sequences such as ESC c (reset)
+                _ => {}
+            },
+            '\u{0}'..='\u{1f}' => {
+                out.push('^');
+                out.push((c as u8 + b'@') as char);
+            }
+            '\u{7f}' => out.push_str("^?"),
+            c if c.is_control() => out.push_str(&c.escape_unicode().to_string()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// What to print for a streamed reasoning chunk: the chunk itself (dimmed)
+/// when reasoning is shown, otherwise a single "[reasoning hidden]" marker
+/// for the first chunk of a reply and nothing after it.
+pub fn render_reasoning(chunk: &str, show: bool, marked: &mut bool) -> Option<String> {
+    if show {
+        return Some(paint(GRAY, &sanitize_for_display(chunk)));
+    }
+    if *marked {
+        return None;
+    }
+    *marked = true;
+    Some(format!("{}\n", paint(GRAY, REASONING_HIDDEN)))
+}
+
+/// Prints agent progress to the terminal: streamed content as it arrives,
+/// a header per tool call and a short colored preview of each result.
+#[derive(Default)]
+pub struct ConsoleStreamHandler {
+    spinner: Spinner,
+    out: Option<Box<dyn Write + Send>>, // stdout when unset
+    review_results: bool,               // ask whether to keep large tool results
+    show_reasoning: bool,
+    reasoning_marked: bool,     // hidden-reasoning marker already printed
+    arg_preview: Option<usize>, // ARG_PREVIEW_CHARS when unset
+}
+
+impl ConsoleStreamHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_reasoning(mut self, show: bool) -> Self {
+        self.show_reasoning = show;
+        self
+    }
+
+    pub fn with_arg_preview(mut self, limit: Option<usize>) -> Self {
+        self.arg_preview = limit;
+        self
+    }
+
+    // For the REPL, where someone is there to answer the keep/drop prompt
+    pub fn interactive() -> Self {
+        Self {
+            review_results: true,
+            ..Self::default()
+        }
+    }
+
+    // Send output somewhere other than stdout (tests capture it this way)
+    #[cfg(test)]
+    pub fn with_output(mut self, out: impl Write + Send + 'static) -> Self {
+        self.out = Some(Box::new(out));
+        self
+    }
+
+    // Everything the handler shows goes through here, so each streamed
+    // chunk is written exactly once
+    fn write(&mut self, text: &str) {
+        match self.out.as_mut() {
+            Some(out) => {
+                let _ = out.write_all(text.as_bytes());
+                let _ = out.flush();
+            }
+            None => {
+                print!("{}", text);
+                let _ = io::stdout().flush();
+            }
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        self.write(&format!("{}\n", line));
+    }
+}
+
+impl AgentStreamHandler for ConsoleStreamHandler {
+    fn on_llm_start(&mut self) {
+        self.reasoning_marked = false;
+        self.spinner.start();
+    }
+
+    fn on_llm_end(&mut self) {
+        self.spinner.stop();
+    }
+
+    fn on_content(&mut self, chunk: &str) {
+        self.spinner.stop();
+        self.write(&sanitize_for_display(chunk));
+    }
+
+    fn on_reasoning(&mut self, chunk: &str) {
+        if let Some(text) = render_reasoning(chunk, self.show_reasoning, &mut self.reasoning_marked)
+        {
+            self.spinner.stop();
+            self.write(&text);
+        }
+    }
+
+    fn on_tool_batch(&mut self, calls: &[ToolCall]) {
+        self.spinner.stop();
+        let batch = paint(GRAY, &sanitize_for_display(&format_tool_batch(calls)));
+        self.write_line(&format!("\n{}", batch));
+    }
+
+    fn on_tool_call(&mut self, name: &str, args: &Value) {
+        self.spinner.stop();
+        self.write_line(&format!("\n{}", paint(MAGENTA, &format!("▌🔧 {}", name))));
+
+        // Arguments that weren't valid JSON arrive as the raw string
+        if let Value::String(raw) = args {
+            self.write_line(&paint(
+                GRAY,
+                &format!(
+                    "   Raw arguments (invalid JSON): {}",
+                    sanitize_for_display(raw)
+                ),
+            ));
+            return;
+        }
+
+        // Special handling for edit_file
+        if name == "edit_file" {
+            if let (Some(old_str), Some(new_str)) = (
+                args.get("old_str").and_then(|v| v.as_str()),
+                args.get("new_str").and_then(|v| v.as_str()),
+            ) {
+                display_diff_side_by_side(
+                    &sanitize_for_display(old_str),
+                    &sanitize_for_display(new_str),
+                );
+
+                if let Some(path) = args.get("path").and_then(|v| v.as_str()) {
+                    let path = sanitize_for_display(path);
+                    self.write_line(&paint(GRAY, &format!("   Path: {}", path)));
+                }
+            }
+        } else if name == "read_file" {
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .unwrap_or("(missing path)");
+            let start = args
+                .get("start_line")
+                .and_then(|v| v.as_u64())
+                .map(|n| n.to_string());
+            let end = args
+                .get("end_line")
+                .and_then(|v| v.as_u64())
+                .map(|n| n.to_string());
+
+            let range = match (start.as_deref(), end.as_deref()) {
+                (Some(s), Some(e)) => format!(" (lines {}-{})", s, e),
+                (Some(s), None) => format!(" (from line {})", s),
+                (None, Some(e)) => format!(" (through line {})", e),
+                (None, None) => String::new(),
+            };
+
+            let path = sanitize_for_display(path);
+            self.write_line(&paint(GRAY, &format!("Read File: {}{}", path, range)));
+        } else {
+            let limit = self.arg_preview.unwrap_or(ARG_PREVIEW_CHARS);
+            let preview = sanitize_for_display(&format_tool_args(name, args, limit));
+            self.write_line(&paint(GRAY, &preview));
+        }
+    }
+
+    fn on_tool_result(&mut self, name: &str, result: &str) {
+        let color = if result.starts_with("Error") {
+            BRIGHT_RED
+        } else {
+            GRAY
+        };
+        let preview = sanitize_for_display(&clip(result.trim_end(), RESULT_PREVIEW_CHARS));
+        self.write_line(&paint(color, &format!("   ↳ {}: {}", name, preview)));
+    }
+
+    fn review_tool_result(&mut self, name: &str, chars: usize) -> ResultRetention {
+        if !self.review_results {
+            return ResultRetention::Full;
+        }
+        self.spinner.stop();
+        ask_result_retention(name, chars)
+    }
+}
+
+/// Swallows all progress, for output modes that print only a final result.
+pub struct SilentStreamHandler;
+
+impl AgentStreamHandler for SilentStreamHandler {
+    fn on_content(&mut self, _chunk: &str) {}
+    fn on_tool_call(&mut self, _name: &str, _args: &Value) {}
+    fn on_tool_result(&mut self, _name: &str, _result: &str) {}
+}
+
+/// "Thinking…" indicator shown while waiting for the first streamed token.
+/// The ticker and `stop` share a lock, so the line is always cleared before
+/// anything else is written.
+pub struct Spinner {
+    active: Arc<Mutex<bool>>,
+    ticker: Option<tokio::task::JoinHandle<()>>,
+    draw: bool,
+}
+
+impl Default for Spinner {
+    fn default() -> Self {
+        Self {
+            active: Arc::new(Mutex::new(false)),
+            ticker: None,
+            // Carriage-return redraws would garble piped output
+            draw: io::stdout().is_terminal(),
+        }
+    }
+}
+
+impl Spinner {
+    // A spinner that tracks state but never touches the terminal
+    #[cfg(test)]
+    pub fn hidden() -> Self {
+        let mut spinner = Self::default();
+        spinner.draw = false;
+        spinner
+    }
+
+    /// Starts spinning. Returns false if it was already running.
+    pub fn start(&mut self) -> bool {
+        let mut active = self.active.lock().unwrap();
+        if *active {
+            return false;
+        }
+        *active = true;
+        drop(active);
+
+        if self.draw && tokio::runtime::Handle::try_current().is_ok() {
+            let active = self.active.clone();
+            self.ticker = Some(tokio::spawn(async move {
+                let started = Instant::now();
+                let mut frame = 0;
+                loop {
+                    {
+                        let active = active.lock().unwrap();
+                        if !*active {
+                            return;
+                        }
+                        print!("\r{}", spinner_line(frame, started.elapsed()));
+                        let _ = io::stdout().flush();
+                    }
+                    frame += 1;
+                    tokio::time::sleep(SPINNER_TICK).await;
+                }
+            }));
+        }
+        true
+    }
+
+    /// Stops and clears the spinner. Returns false if it wasn't running.
+    pub fn stop(&mut self) -> bool {
+        let mut active = self.active.lock().unwrap();
+        if !*active {
+            return false;
+        }
+        *active = false;
+        if let Some(ticker) = self.ticker.take() {
+            ticker.abort();
+            print!("\r\u{001b}[2K");
+            let _ = io::stdout().flush();
+        }
+        true
+    }
+
+    #[cfg(test)]
+    pub fn is_active(&self) -> bool {
+        *self.active.lock().unwrap()
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub fn spinner_line(frame: usize, elapsed: Duration) -> String {
+    let text = format!(
+        "{} Thinking… {}s",
+        SPINNER_FRAMES[frame % SPINNER_FRAMES.len()],
+        elapsed.as_secs()
+    );
+    paint(GRAY, &text)
+}
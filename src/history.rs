@@ -0,0 +1,91 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Prompts kept across runs.
+pub const HISTORY_CAP: usize = 500;
+
+/// Prompts submitted in earlier runs, oldest first, one per line in
+/// `.termx/history`. New prompts are appended to the file so several
+/// instances can write to it at once; the file is compacted (deduplicated
+/// and capped) once it grows to twice the cap.
+pub struct PromptHistory {
+    path: PathBuf,
+    cap: usize,
+    entries: Vec<String>,
+}
+
+impl PromptHistory {
+    /// Loads the history at `path`. A missing or unreadable file gives an
+    /// empty history.
+    pub fn load(path: &Path, cap: usize) -> Self {
+        let raw = fs::read_to_string(path).unwrap_or_default();
+        Self {
+            path: path.to_path_buf(),
+            cap,
+            entries: compact(raw.lines(), cap),
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Records `prompt`, moving it to the end if it was already there.
+    /// Multi-line prompts are stored on one line.
+    pub fn append(&mut self, prompt: &str) -> io::Result<()> {
+        let line = prompt.trim().replace(['\r', '\n'], " ");
+        if line.is_empty() {
+            return Ok(());
+        }
+        self.entries.retain(|e| *e != line);
+        self.entries.push(line.clone());
+        if self.entries.len() > self.cap {
+            self.entries.remove(0);
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // One write per prompt, in append mode, so concurrent runs interleave
+        // whole lines instead of clobbering each other
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(format!("{}\n", line).as_bytes())?;
+        drop(file);
+
+        let on_disk = fs::read_to_string(&self.path)?;
+        if on_disk.lines().count() >= self.cap * 2 {
+            self.rewrite(&on_disk)?;
+        }
+        Ok(())
+    }
+
+    // Replace the file with its compacted contents, keeping prompts other
+    // instances appended since we loaded
+    fn rewrite(&mut self, on_disk: &str) -> io::Result<()> {
+        self.entries = compact(on_disk.lines(), self.cap);
+        let tmp = self.path.with_extension("tmp");
+        let mut body = self.entries.join("\n");
+        body.push('\n');
+        fs::write(&tmp, body)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+// Latest occurrence of each prompt, oldest first, at most `cap` of them
+fn compact<'a>(lines: impl DoubleEndedIterator<Item = &'a str>, cap: usize) -> Vec<String> {
+    let mut kept: Vec<String> = Vec::new();
+    for line in lines.rev() {
+        if kept.len() == cap {
+            break;
+        }
+        if !line.trim().is_empty() && !kept.iter().any(|k| k == line) {
+            kept.push(line.to_string());
+        }
+    }
+    kept.reverse();
+    kept
+}
@@ -0,0 +1,23 @@
+use std::future::Future;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Cancels `token` once `signal` resolves. Abort the returned handle when the
+/// guarded work finishes so a later signal isn't consumed by a stale watcher.
+pub fn cancel_on_signal<F>(signal: F, token: CancellationToken) -> JoinHandle<()>
+where
+    F: Future + Send + 'static,
+{
+    tokio::spawn(async move {
+        signal.await;
+        token.cancel();
+    })
+}
+
+/// Resolves on the next Ctrl-C.
+pub async fn ctrl_c() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        // No signal support: never resolve rather than cancel spuriously.
+        std::future::pending::<()>().await;
+    }
+}
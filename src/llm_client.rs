@@ -1,17 +1,232 @@
+use crate::agent::{OnContent, StreamDelta};
 use crate::types::{FunctionCall, Message, ToolCall};
+use crate::utils::clip;
+use regex::{NoExpand, Regex};
 use serde_json::Value;
-use std::io::{self, Write};
-use tokio::time::Duration;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Duration, Instant};
+
+// How long a key that got a 429 or 401 is skipped
+const KEY_COOLDOWN: Duration = Duration::from_secs(60);
+
+// LLM requests in flight at once across the agent and its sub-agents,
+// unless set_max_concurrent_requests says otherwise
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4;
+
+static SHARED_LIMITER: OnceLock<RequestLimiter> = OnceLock::new();
+
+/// What a redacted secret is replaced with in outgoing messages.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Common secret formats: OpenAI/Anthropic-style keys, GitHub and Slack
+/// tokens, AWS access key ids, Google API keys, bearer tokens and PEM
+/// private keys.
+pub const DEFAULT_REDACTION_PATTERNS: &[&str] = &[
+    r"\bsk-[A-Za-z0-9_-]{20,}",
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}",
+    r"\bgithub_pat_[A-Za-z0-9_]{22,}",
+    r"\bxox[abprs]-[A-Za-z0-9-]{10,}",
+    r"\bAKIA[0-9A-Z]{16}\b",
+    r"\bAIza[0-9A-Za-z_-]{35}",
+    r"(?i)\bbearer\s+[A-Za-z0-9._~+/-]{20,}=*",
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+];
+
+// How providers word "the request doesn't fit the context window". Only
+// their specific codes and phrases: looser words like "too many tokens"
+// also turn up in rate-limit and proxy errors.
+const CONTEXT_LENGTH_SIGNATURES: &[&str] = &[
+    "context_length_exceeded",
+    "maximum context length",
+    "prompt is too long",
+    "input is too long for requested model",
+    "reduce the length of the messages",
+];
+
+// A streamed delta repeated back to back is taken as a proxy resending the
+// chunk only when it's at least this long; shorter repeats ("ha", "ha")
+// can be real text
+const REPLAYED_CHUNK_MIN_CHARS: usize = 8;
+
+// API keys to fail over between, each with an optional cooldown deadline
+struct KeyRing {
+    keys: Vec<String>,
+    cooling_until: Vec<Option<Instant>>,
+}
+
+impl KeyRing {
+    // `spec` is one key or a comma-separated list
+    fn new(spec: &str) -> Self {
+        let mut keys: Vec<String> = spec
+            .split(',')
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        if keys.is_empty() {
+            keys.push(spec.to_string());
+        }
+        let cooling_until = vec![None; keys.len()];
+        Self {
+            keys,
+            cooling_until,
+        }
+    }
+
+    // First key not in `skip` that isn't cooling down; failing that, the
+    // one whose cooldown ends soonest
+    fn pick(&self, now: Instant, skip: &[usize]) -> usize {
+        let candidates = (0..self.keys.len()).filter(|i| !skip.contains(i));
+        candidates
+            .clone()
+            .find(|&i| self.cooling_until[i].is_none_or(|until| until <= now))
+            .or_else(|| candidates.min_by_key(|&i| self.cooling_until[i]))
+            .unwrap_or(0)
+    }
+}
+
+/// Caps how many LLM requests run at once. Clones share the same slots, so
+/// every client holding one (the main agent, `ask_orackle`, …) counts
+/// against one limit. A slot is held for the whole request, including
+/// reading the streamed reply.
+#[derive(Clone, Debug)]
+pub struct RequestLimiter {
+    slots: Arc<Semaphore>,
+    in_flight: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+impl RequestLimiter {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            peak: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    async fn acquire(&self) -> RequestSlot {
+        let permit = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("request limiter semaphore is never closed");
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        RequestSlot {
+            _permit: permit,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Most requests that were ever in flight at once.
+    #[cfg(test)]
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+// Frees its slot when the request finishes
+struct RequestSlot {
+    _permit: OwnedSemaphorePermit,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for RequestSlot {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Sets the limit shared by every client created afterwards. Call once at
+/// startup, before the first `LlmClient::new`; later calls are ignored.
+pub fn set_max_concurrent_requests(max: usize) {
+    let _ = SHARED_LIMITER.set(RequestLimiter::new(max));
+}
+
+/// The process-wide limiter new clients use by default.
+pub fn shared_limiter() -> RequestLimiter {
+    SHARED_LIMITER
+        .get_or_init(|| RequestLimiter::new(DEFAULT_MAX_CONCURRENT_REQUESTS))
+        .clone()
+}
+
+/// Optional request parameters beyond the model and messages.
+#[derive(Clone, Debug, Default)]
+pub struct LlmParams {
+    /// Ask for `response_format: {"type": "json_object"}`. Some providers
+    /// reject it on streaming requests, so JSON-mode calls always use the
+    /// non-streaming endpoint and don't offer tools.
+    pub json_mode: bool,
+    /// Sent as `tool_choice` with the tools; None leaves it to the provider
+    /// (normally "auto").
+    pub tool_choice: Option<ToolChoice>,
+}
+
+/// Whether the model may, must or must not call tools.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolChoice {
+    Auto,
+    /// Answer in text only
+    None,
+    /// Call at least one tool
+    Required,
+    /// Call this tool
+    Function(String),
+}
+
+impl ToolChoice {
+    /// Parses "auto", "none" or "required"; anything else names the function
+    /// to force. Empty input is None.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "" => None,
+            "auto" => Some(Self::Auto),
+            "none" => Some(Self::None),
+            "required" => Some(Self::Required),
+            name => Some(Self::Function(name.to_string())),
+        }
+    }
+
+    /// The request body value, in OpenAI chat format.
+    pub fn to_json(&self) -> Value {
+        match self {
+            Self::Auto => Value::from("auto"),
+            Self::None => Value::from("none"),
+            Self::Required => Value::from("required"),
+            Self::Function(name) => serde_json::json!({
+                "type": "function",
+                "function": {"name": name}
+            }),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct LlmClient {
     base_url: String,
-    api_key: String,
+    keys: Arc<std::sync::Mutex<KeyRing>>,
     model: String,
     http: reqwest::Client,
+    // e.g. "api-key" or "Authorization: Token"; None means bearer auth
+    auth_header: Option<String>,
+    extra_headers: HashMap<String, String>,
+    // Minimum spacing between requests; None sends immediately
+    min_request_interval: Option<Duration>,
+    last_request: Arc<Mutex<Option<Instant>>>,
+    params: LlmParams,
+    limiter: RequestLimiter,
+    // Applied to message content on the wire only, never to the session
+    redactions: Vec<Regex>,
 }
 
 impl LlmClient {
+    /// `api_key` may list several comma-separated keys; a key answered with
+    /// 429 or 401 is put on cooldown and the request retried with the next.
     pub fn new(base_url: String, api_key: String, model: String) -> anyhow::Result<Self> {
         let http = reqwest::Client::builder()
             .pool_idle_timeout(Duration::from_secs(30))
@@ -21,29 +236,206 @@ impl LlmClient {
             .build()?;
         Ok(Self {
             base_url,
-            api_key,
+            keys: Arc::new(std::sync::Mutex::new(KeyRing::new(&api_key))),
             model,
             http,
+            auth_header: None,
+            extra_headers: HashMap::new(),
+            min_request_interval: None,
+            last_request: Arc::new(Mutex::new(None)),
+            params: LlmParams::default(),
+            limiter: shared_limiter(),
+            redactions: Vec::new(),
         })
     }
 
+    /// Send the API key as `<name>: [prefix ]<key>` instead of bearer auth.
+    /// `spec` is a header name optionally followed by `:` and a value prefix.
+    pub fn with_auth_header(mut self, spec: Option<String>) -> Self {
+        self.auth_header = spec.filter(|s| !s.trim().is_empty());
+        self
+    }
+
+    /// Headers added to every request (API version, org id, …).
+    pub fn with_extra_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.extra_headers = headers;
+        self
+    }
+
+    /// Applies `OPENAI_AUTH_HEADER` and `OPENAI_EXTRA_HEADERS`
+    /// (comma-separated `name=value` pairs) when they are set.
+    pub fn with_env_headers(self) -> Self {
+        let extra = env::var("OPENAI_EXTRA_HEADERS")
+            .map(|v| parse_extra_headers(&v))
+            .unwrap_or_default();
+        self.with_auth_header(env::var("OPENAI_AUTH_HEADER").ok())
+            .with_extra_headers(extra)
+    }
+
+    /// Replace every match of `patterns` in outgoing message content with
+    /// `[REDACTED]`. Only the request body changes; callers' messages don't.
+    pub fn with_redaction(mut self, patterns: Vec<Regex>) -> Self {
+        self.redactions = patterns;
+        self
+    }
+
+    /// Redacts `DEFAULT_REDACTION_PATTERNS` plus the whitespace-separated
+    /// regexes in `TERMX_REDACT_PATTERNS`. `TERMX_REDACT=0` turns it off.
+    pub fn with_env_redaction(self) -> Self {
+        if env::var("TERMX_REDACT").is_ok_and(|v| v == "0" || v == "false") {
+            return self.with_redaction(Vec::new());
+        }
+        let extra = env::var("TERMX_REDACT_PATTERNS").unwrap_or_default();
+        let patterns = DEFAULT_REDACTION_PATTERNS
+            .iter()
+            .copied()
+            .chain(extra.split_whitespace())
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    eprintln!("Ignoring redaction pattern '{}': {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        self.with_redaction(patterns)
+    }
+
+    pub fn with_params(mut self, params: LlmParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Use `limiter` instead of the process-wide one.
+    #[cfg(test)]
+    pub fn with_request_limiter(mut self, limiter: RequestLimiter) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Space requests at least `interval` apart, to stay under provider
+    /// rate limits during fast tool loops. Off by default.
+    pub fn with_min_request_interval(mut self, interval: Option<Duration>) -> Self {
+        self.min_request_interval = interval.filter(|d| !d.is_zero());
+        self
+    }
+
+    // Sleep until `min_request_interval` has passed since the previous
+    // request. The lock is held while sleeping so concurrent callers queue up.
+    async fn throttle(&self) {
+        let Some(interval) = self.min_request_interval else {
+            return;
+        };
+        let mut last = self.last_request.lock().await;
+        if let Some(prev) = *last {
+            tokio::time::sleep_until(prev + interval).await;
+        }
+        *last = Some(Instant::now());
+    }
+
+    // wire_messages with secrets redacted from the content
+    fn wire(&self, messages: &[Message]) -> Vec<Value> {
+        let mut wire = wire_messages(messages);
+        if self.redactions.is_empty() {
+            return wire;
+        }
+        for message in &mut wire {
+            if let Some(Value::String(text)) = message.get_mut("content") {
+                *text = redact_secrets(text, &self.redactions);
+            }
+        }
+        wire
+    }
+
+    fn post(&self, url: &str, api_key: &str) -> reqwest::RequestBuilder {
+        let mut req = self.http.post(url);
+        req = match &self.auth_header {
+            Some(spec) => {
+                let (name, prefix) = spec.split_once(':').unwrap_or((spec.as_str(), ""));
+                let prefix = prefix.trim();
+                let value = if prefix.is_empty() {
+                    api_key.to_string()
+                } else {
+                    format!("{} {}", prefix, api_key)
+                };
+                req.header(name.trim(), value)
+            }
+            None => req.bearer_auth(api_key),
+        };
+        for (name, value) in &self.extra_headers {
+            req = req.header(name.as_str(), value.as_str());
+        }
+        req
+    }
+
+    // POST `body`, failing over to the next key on 429/401. When every key
+    // has been refused, the last response is returned as is. Keys are never
+    // logged, only their position in the list.
+    async fn send(&self, url: &str, body: &Value) -> anyhow::Result<reqwest::Response> {
+        let mut tried = Vec::new();
+        loop {
+            let (idx, key) = {
+                let ring = self.keys.lock().unwrap();
+                let idx = ring.pick(Instant::now(), &tried);
+                (idx, ring.keys[idx].clone())
+            };
+            self.throttle().await;
+            let resp = self.post(url, &key).json(body).send().await?;
+
+            let status = resp.status().as_u16();
+            if status != 429 && status != 401 {
+                return Ok(resp);
+            }
+            let total = {
+                let mut ring = self.keys.lock().unwrap();
+                ring.cooling_until[idx] = Some(Instant::now() + KEY_COOLDOWN);
+                ring.keys.len()
+            };
+            tried.push(idx);
+            if tried.len() >= total {
+                return Ok(resp);
+            }
+            eprintln!(
+                "API key {} of {} got HTTP {}; retrying with the next key",
+                idx + 1,
+                total,
+                status
+            );
+        }
+    }
+
     pub async fn chat_once(&self, messages: &[Message], tools: &Value) -> anyhow::Result<Message> {
+        self.chat_once_streaming(messages, tools, &mut |_| {}).await
+    }
+
+    /// Streams one completion, passing each content delta to `on_content`.
+    pub async fn chat_once_streaming(
+        &self,
+        messages: &[Message],
+        tools: &Value,
+        on_content: &mut OnContent<'_>,
+    ) -> anyhow::Result<Message> {
+        if self.params.json_mode {
+            let reply = self.complete_no_stream(messages, Some(tools)).await?;
+            if let Some(content) = reply.content.as_deref() {
+                on_content(StreamDelta::Content(content));
+            }
+            return Ok(reply);
+        }
+
+        let _slot = self.limiter.acquire().await;
         let url = format!("{}/chat/completions", self.base_url);
-        let req = serde_json::json!({
+        let mut req = serde_json::json!({
             "model": self.model,
-            "messages": messages,
+            "messages": self.wire(messages),
             "tools": tools,
             "stream":true
-            // "tool_choice": "auto", // optional, if your provider supports it
         });
+        if let Some(choice) = &self.params.tool_choice {
+            req["tool_choice"] = choice.to_json();
+        }
 
-        let resp = self
-            .http
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&req)
-            .send()
-            .await?;
+        let resp = self.send(&url, &req).await?;
 
         // Replace the response parsing in chat_once:
         let mut stream = resp.bytes_stream();
@@ -52,9 +444,17 @@ impl LlmClient {
             content: Some(String::new()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         };
         let mut tool_calls_map: std::collections::HashMap<usize, ToolCall> =
             std::collections::HashMap::new();
+        // Evidence for the "stream produced nothing" case
+        let mut saw_finish = false;
+        let mut error_text: Vec<String> = Vec::new();
+        // Last delta appended, to drop chunks a proxy sends twice
+        let mut last_content = String::new();
+        let mut last_args: HashMap<usize, String> = HashMap::new();
 
         use futures::StreamExt;
 
@@ -66,10 +466,15 @@ impl LlmClient {
 
             for line in text.lines() {
                 if !line.starts_with("data: ") {
+                    // A plain (non-SSE) body is usually an error payload
+                    let line = line.trim();
+                    if !line.is_empty() && !line.starts_with(':') && !line.starts_with("event:") {
+                        error_text.push(line.to_string());
+                    }
                     continue;
                 }
                 let json_str = line.strip_prefix("data: ").unwrap().trim();
-                if json_str == "[DONE]" || json_str == "" {
+                if json_str == "[DONE]" || json_str.is_empty() {
                     should_stop = true; // ← set flag instead of break
                     break;
                 }
@@ -84,20 +489,37 @@ impl LlmClient {
                         continue; // Skip malformed JSON and continue processing
                     }
                 };
+                if let Some(err) = delta.get("error") {
+                    let msg = err["message"].as_str().map(str::to_string);
+                    error_text.push(msg.unwrap_or_else(|| err.to_string()));
+                    continue;
+                }
                 let choice = &delta["choices"][0];
                 let delta_obj = &choice["delta"];
 
                 if let Some(finish) = choice["finish_reason"].as_str() {
+                    saw_finish = true;
                     if finish == "stop" || finish == "tool_calls" {
                         should_stop = true; // ← set flag
                         break;
                     }
                 }
 
+                // Reasoning is shown while streaming but not kept in the reply
+                if let Some(reasoning) = delta_obj["reasoning_content"]
+                    .as_str()
+                    .or(delta_obj["reasoning"].as_str())
+                    .filter(|r| !r.is_empty())
+                {
+                    on_content(StreamDelta::Reasoning(reasoning));
+                }
+
                 // Accumulate content
-                if let Some(content) = delta_obj["content"].as_str() {
-                    print!("{}", content);
-                    io::stdout().flush().unwrap();
+                if let Some(content) = delta_obj["content"].as_str()
+                    && !is_replayed_chunk(&last_content, content)
+                {
+                    last_content = content.to_string();
+                    on_content(StreamDelta::Content(content));
                     accumulated_message
                         .content
                         .as_mut()
@@ -125,7 +547,11 @@ impl LlmClient {
                             entry.function.name = name.to_string();
                         }
                         if let Some(args) = tc_delta["function"]["arguments"].as_str() {
-                            entry.function.arguments.push_str(args);
+                            let last = last_args.entry(index).or_default();
+                            if !is_replayed_chunk(last, args) {
+                                entry.function.arguments.push_str(args);
+                                *last = args.to_string();
+                            }
                         }
                     }
                 }
@@ -137,6 +563,19 @@ impl LlmClient {
             }
         }
 
+        let no_content = accumulated_message
+            .content
+            .as_deref()
+            .is_none_or(str::is_empty);
+        if no_content && tool_calls_map.is_empty() && !saw_finish {
+            let detail = if error_text.is_empty() {
+                "empty response".to_string()
+            } else {
+                clip(&error_text.join("\n"), 2000)
+            };
+            anyhow::bail!("Provider stream produced no output: {}", detail);
+        }
+
         // Reconstruct tool_calls vector from map if any
         if !tool_calls_map.is_empty() {
             let mut calls: Vec<_> = tool_calls_map.into_iter().collect();
@@ -147,27 +586,34 @@ impl LlmClient {
         Ok(accumulated_message)
     }
 
-    pub async fn chat_once_no_stream(
+    pub async fn chat_once_no_stream(&self, messages: &[Message]) -> anyhow::Result<Message> {
+        self.complete_no_stream(messages, None).await
+    }
+
+    /// Sends one non-streaming completion, offering `tools` when given.
+    async fn complete_no_stream(
         &self,
         messages: &[Message],
-        // tools: &Value,
+        tools: Option<&Value>,
     ) -> anyhow::Result<Message> {
+        let _slot = self.limiter.acquire().await;
         let url = format!("{}/chat/completions", self.base_url);
-        let req = serde_json::json!({
+        let mut req = serde_json::json!({
             "model": self.model,
-            "messages": messages,
-            // "tools": tools,
+            "messages": self.wire(messages),
             "stream": false
-            // "tool_choice": "auto", // optional, if your provider supports it
         });
+        if let Some(tools) = tools.filter(|t| t.as_array().is_some_and(|a| !a.is_empty())) {
+            req["tools"] = tools.clone();
+            if let Some(choice) = &self.params.tool_choice {
+                req["tool_choice"] = choice.to_json();
+            }
+        }
+        if self.params.json_mode {
+            req["response_format"] = serde_json::json!({"type": "json_object"});
+        }
 
-        let resp = self
-            .http
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&req)
-            .send()
-            .await?;
+        let resp = self.send(&url, &req).await?;
 
         // Parse non-streaming response
         let response_text = resp.text().await?;
@@ -182,33 +628,79 @@ impl LlmClient {
 
         let message = &choice["message"];
 
-        // // Parse tool calls if present
-        // let tool_calls = if let Some(tool_calls_array) = message["tool_calls"].as_array() {
-        //     Some(
-        //         tool_calls_array
-        //             .iter()
-        //             .map(|tc| ToolCall {
-        //                 id: tc["id"].as_str().unwrap_or("").to_string(),
-        //                 call_type: tc["type"].as_str().unwrap_or("function").to_string(),
-        //                 function: FunctionCall {
-        //                     name: tc["function"]["name"].as_str().unwrap_or("").to_string(),
-        //                     arguments: tc["function"]["arguments"]
-        //                         .as_str()
-        //                         .unwrap_or("")
-        //                         .to_string(),
-        //                 },
-        //             })
-        //             .collect(),
-        //     )
-        // } else {
-        //     None
-        // };
+        let tool_calls = message["tool_calls"].as_array().map(|calls| {
+            calls
+                .iter()
+                .map(|tc| ToolCall {
+                    id: tc["id"].as_str().unwrap_or("").to_string(),
+                    call_type: tc["type"].as_str().unwrap_or("function").to_string(),
+                    function: FunctionCall {
+                        name: tc["function"]["name"].as_str().unwrap_or("").to_string(),
+                        arguments: tc["function"]["arguments"]
+                            .as_str()
+                            .unwrap_or("")
+                            .to_string(),
+                    },
+                })
+                .collect::<Vec<_>>()
+        });
 
         Ok(Message {
             role: message["role"].as_str().unwrap_or("assistant").to_string(),
             content: message["content"].as_str().map(|s| s.to_string()),
-            tool_calls: None,
+            tool_calls: tool_calls.filter(|calls| !calls.is_empty()),
             tool_call_id: None,
+            name: None,
+            created_at: None,
         })
     }
 }
+
+/// Whether a provider error says the request was too long for the model's
+/// context window.
+pub fn is_context_length_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    CONTEXT_LENGTH_SIGNATURES
+        .iter()
+        .any(|signature| message.contains(signature))
+}
+
+// Whether `next` is `previous` sent again rather than new text
+fn is_replayed_chunk(previous: &str, next: &str) -> bool {
+    next == previous && next.chars().count() >= REPLAYED_CHUNK_MIN_CHARS && !next.trim().is_empty()
+}
+
+pub fn parse_extra_headers(spec: &str) -> HashMap<String, String> {
+    spec.split(',')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim();
+            (!name.is_empty()).then(|| (name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// `text` with every match of `patterns` replaced by `[REDACTED]`.
+pub fn redact_secrets(text: &str, patterns: &[Regex]) -> String {
+    let mut text = text.to_string();
+    for re in patterns {
+        if re.is_match(&text) {
+            text = re.replace_all(&text, NoExpand(REDACTED)).into_owned();
+        }
+    }
+    text
+}
+
+// Messages as sent to the provider: local-only fields are dropped
+pub fn wire_messages(messages: &[Message]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|m| {
+            let mut v = serde_json::to_value(m).unwrap_or(Value::Null);
+            if let Some(obj) = v.as_object_mut() {
+                obj.remove("created_at");
+            }
+            v
+        })
+        .collect()
+}
@@ -1,50 +1,79 @@
+#![recursion_limit = "256"]
+
 mod agent;
+mod changes;
+mod cli;
+mod console;
+mod history;
+mod interrupt;
 mod llm_client;
+mod prompt;
 mod session;
+mod style;
+mod tool_cache;
 mod tool_registry;
 mod tools;
+mod transcript;
 mod types;
 mod utils;
+mod workspace;
 
 #[cfg(test)]
 mod mocks;
 #[cfg(test)]
 mod tests;
-use agent::{Agent, AgentOptions};
+use agent::{Agent, AgentOptions, WriteLimits};
 use chrono::Utc;
+use console::{ConsoleStreamHandler, SilentStreamHandler};
+use history::{HISTORY_CAP, PromptHistory};
 use llm_client::LlmClient;
 use session::Session;
 use std::env;
-use std::io::{self, Write};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 use std::process::Command;
+use tokio_util::sync::CancellationToken;
 use tool_registry::ToolRegistry;
-use types::Message;
+
+// Prompts listed by /history
+const HISTORY_SHOWN: usize = 20;
+// Minimum time between autosaves while a turn adds messages
+const AUTOSAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
 
 // ----------------------------------- Main -----------------------------------
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    create_agent_dir();
+    // Prints help, the version or a usage error and exits as appropriate
+    let mut args = cli::parse_args(env::args().skip(1)).unwrap_or_else(|e| e.exit());
+    if let Some(command) = &args.sessions {
+        if let Err(e) = sessions_command(command) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    // ASCII Art Banner
-    println!(
-        r#"
-        ███████████ ██████████ ███████████   ██████   ██████ █████ █████
-       ░█░░░███░░░█░░███░░░░░█░░███░░░░░███ ░░██████ ██████ ░░███ ░░███
-       ░   ░███  ░  ░███  █ ░  ░███    ░███  ░███░█████░███  ░░███ ███
-           ░███     ░██████    ░██████████   ░███░░███ ░███   ░░█████
-           ░███     ░███░░█    ░███░░░░░███  ░███ ░░░  ░███    ███░███
-           ░███     ░███ ░   █ ░███    ░███  ░███      ░███   ███ ░░███
-           █████    ██████████ █████   █████ █████     █████ █████ █████
-          ░░░░░    ░░░░░░░░░░ ░░░░░   ░░░░░ ░░░░░     ░░░░░ ░░░░░ ░░░░░
-        "#
-    );
+    // Piped input runs as a one-shot prompt; the REPL needs a terminal
+    let stdin_is_terminal = io::stdin().is_terminal();
+    let prompt = cli::resolve_prompt(args.prompt.take(), stdin_is_terminal, || {
+        io::read_to_string(io::stdin())
+    });
+    args.prompt = match prompt {
+        Ok(prompt) => prompt,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
 
-    println!("\u{001b}[94mtermx - Advanced Coding Assistant\u{001b}[0m");
-    println!(
-        "\u{001b}[90mStarted at: {}\u{001b}[0m",
-        Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
-    );
+    style::init(args.no_color);
+    create_agent_dir();
+
+    if args.prompt.is_none() {
+        print_banner();
+    }
 
     // Environment
     let base_url = env::var("OPENAI_BASE_URL").expect("OPENAI_BASE_URL not set");
@@ -54,91 +83,301 @@ async fn main() -> anyhow::Result<()> {
         "glm-4.6".to_string()
     });
 
-    let llm = LlmClient::new(base_url, api_key, model.clone())?;
-    let tools = ToolRegistry::new();
+    // Shared with ask_orackle's client, so sub-agents can't flood the provider
+    if let Some(max) = env::var("TERMX_MAX_CONCURRENT_LLM")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n: &usize| n > 0)
+    {
+        llm_client::set_max_concurrent_requests(max);
+    }
+    if let Some(max) = env::var("TERMX_MAX_ORACLE_DEPTH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        tools::set_max_oracle_depth(max);
+    }
+    let min_interval = env::var("OPENAI_MIN_REQUEST_INTERVAL_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(tokio::time::Duration::from_millis);
+    let tool_choice = env::var("TERMX_TOOL_CHOICE")
+        .ok()
+        .and_then(|v| llm_client::ToolChoice::parse(&v));
+    let llm = LlmClient::new(base_url.clone(), api_key, model.clone())?
+        .with_env_headers()
+        .with_env_redaction()
+        .with_min_request_interval(min_interval)
+        .with_params(llm_client::LlmParams {
+            tool_choice,
+            ..Default::default()
+        });
+    let mut tools = enable_web_search(ToolRegistry::new());
+    // Project-specific steering, e.g. "prefer edit_file over write_file"
+    for (var, replace) in [
+        ("TERMX_TOOL_DESCRIPTIONS", true),
+        ("TERMX_TOOL_HINTS", false),
+    ] {
+        let spec = env::var(var).unwrap_or_default();
+        let (notes, malformed) = tool_registry::parse_tool_notes(&spec);
+        for entry in malformed {
+            eprintln!(
+                "{}",
+                style::paint(
+                    style::YELLOW,
+                    &format!(
+                        "Warning: ignoring '{}' in {}: expected name=text",
+                        entry, var
+                    )
+                )
+            );
+        }
+        for (name, text) in notes {
+            let edit = if replace {
+                tool_registry::DescriptionEdit::Replace(text)
+            } else {
+                tool_registry::DescriptionEdit::Append(text)
+            };
+            if !tools.set_description(&name, edit) {
+                eprintln!(
+                    "{}",
+                    style::paint(
+                        style::YELLOW,
+                        &format!("Warning: unknown tool '{}' in {}", name, var)
+                    )
+                );
+            }
+        }
+    }
     let opts = AgentOptions {
         max_steps: 12,
         yolo: false, // set true to auto-approve tool calls
         step_timeout: tokio::time::Duration::from_secs(45),
         observation_clip: 4000, // keep large enough for code blocks
     };
-    let agent = Agent::with_real_client(llm, tools, opts);
-
-    // Create session with system message
-    let mut session = Session::new(Some("Coding Session"), Some(&model));
-    session.add_message(Message {
-        role: "system".to_string(),
-        content: Some(
-            "You are an advanced coding assistant with expert-level reasoning capabilities.
-
-        ## CORE PRINCIPLES
-        1. **Think Before Acting**: Always analyze the task thoroughly before using tools
-        2. **Plan & Decompose**: Break complex tasks into clear, sequential steps
-        3. **Verify Results**: Double-check your work before presenting final answers
-        4. **Learn & Adapt**: Use feedback to improve your approach
-        5. **Be Efficient**: Use tools in parallel when possible, avoid redundant operations
-
-        ## TASK EXECUTION STRATEGY
-        1. **Understand**: Clarify the user's goal and constraints
-        2. **Plan**: Outline the steps needed to complete the task
-        3. **Execute**: Use tools systematically and efficiently
-        4. **Verify**: Test and validate your implementation
-        5. **Summarize**: Provide clear explanation of what was accomplished
-
-        ## TOOL USAGE GUIDELINES
-        - **read_file**: Gather context before making changes
-        - **list_dir**: Understand project structure
-        - **search_in_files**: Find relevant code patterns
-        - **edit_file/insert_in_file**: Make precise, targeted changes
-        - **write_file**: Create new files with proper structure
-        - **run_shell**: Execute commands when necessary
-
-        ## QUALITY STANDARDS
-        - Never fabricate file contents or code
-        - Ensure code is syntactically correct and follows conventions
-        - Test your changes when possible
-        - Provide clear explanations of your approach
-        - Ask for clarification if the task is ambiguous
-
-        ## COMMUNICATION STYLE
-        - Be concise but thorough in your explanations
-        - Show your reasoning process for complex tasks
-        - Highlight important changes or decisions
-        - Provide context for why certain approaches were chosen
-
-        Remember: Your goal is to deliver high-quality, working solutions while being transparent about your process."
-                .to_string(),
-        ),
-        tool_calls: None,
-        tool_call_id: None,
-    });
+    let write_limits = WriteLimits {
+        max_files_written: env::var("TERMX_MAX_FILES_WRITTEN")
+            .ok()
+            .and_then(|v| v.parse().ok()),
+        max_write_bytes: env::var("TERMX_MAX_WRITE_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok()),
+    };
+    let mut agent = Agent::with_real_client(llm, tools, opts).with_write_limits(write_limits);
+    if let Some(lines) = env::var("TERMX_READ_LINES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n: &usize| n > 0)
+    {
+        agent = agent.with_default_read_lines(lines);
+    }
+    let mut text_format = tools::TextFormat {
+        ensure_trailing_newline: env::var("TERMX_ENSURE_TRAILING_NEWLINE")
+            .is_ok_and(|v| v == "1" || v == "true"),
+        ..Default::default()
+    };
+    if let Ok(value) = env::var("TERMX_LINE_ENDINGS") {
+        match tools::LineEndings::parse(&value) {
+            Some(endings) => text_format.line_endings = endings,
+            None => eprintln!(
+                "{}",
+                style::paint(
+                    style::YELLOW,
+                    &format!(
+                        "Warning: TERMX_LINE_ENDINGS must be keep, lf or crlf (got '{}')",
+                        value
+                    )
+                )
+            ),
+        }
+    }
+    agent = agent.with_text_format(text_format);
+    if let Some(bytes) = env::var("TERMX_MAX_READ_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n: &u64| n > 0)
+    {
+        agent = agent.with_max_read_bytes(bytes);
+    }
+    if let Some(failures) = env::var("TERMX_MAX_TOOL_ERRORS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        agent = agent.with_error_threshold(failures);
+    }
+    if env::var("TERMX_VERIFY_EDITS").is_ok_and(|v| v == "1" || v == "true") {
+        agent = agent.with_verify_after_edit(true);
+    }
+    let approvals = tools::ApprovalOverrides::from_lists(
+        &env::var("TERMX_REQUIRE_APPROVAL").unwrap_or_default(),
+        &env::var("TERMX_SKIP_APPROVAL").unwrap_or_default(),
+    );
+    for name in approvals.require.iter().chain(&approvals.skip) {
+        if !agent.tools().names().contains(&name.as_str()) {
+            eprintln!(
+                "{}",
+                style::paint(
+                    style::YELLOW,
+                    &format!("Warning: unknown tool '{}' in approval settings", name)
+                )
+            );
+        }
+    }
+    agent = agent.with_approval_overrides(approvals);
+    // stdout is reserved for the report, and a piped prompt leaves no stdin
+    // to answer with
+    agent = agent.with_approval_prompts(!args.json);
+    if let Some(max_steps) = args.max_steps {
+        agent.set_max_steps(max_steps);
+    }
+    if let Some(steps_left) = env::var("TERMX_WRAP_UP_STEPS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        agent = agent.with_wrap_up_threshold(steps_left);
+    }
+
+    // Orient the model up front instead of spending its first steps exploring
+    let project_scan = (!env::var("TERMX_PROJECT_SCAN").is_ok_and(|v| v == "0" || v == "false"))
+        .then(|| tools::scan_project(Path::new(".")));
+    let extra_instructions = args
+        .extra_instructions
+        .clone()
+        .or_else(|| env::var("TERMX_EXTRA_INSTRUCTIONS").ok());
+    let mut session = prompt::initial_session(&model, project_scan, extra_instructions.as_deref());
+    if let Some(id) = &args.resume {
+        match load_saved_session(id) {
+            Ok(saved) => {
+                if !args.json {
+                    println!(
+                        "{}",
+                        style::paint(
+                            style::BRIGHT_GREEN,
+                            &format!(
+                                "Resumed session {} ({} messages)",
+                                saved.id,
+                                saved.messages.len()
+                            )
+                        )
+                    );
+                }
+                session = saved;
+                session.close_dangling_tool_calls("Interrupted before the session was saved");
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(path) = &args.import {
+        let imported = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| session.import_transcript(&json));
+        match imported {
+            // Keep --json output a single report
+            Ok(_) if args.json => {}
+            Ok(count) => println!(
+                "{}",
+                style::paint(
+                    style::BRIGHT_GREEN,
+                    &format!("Imported {} messages from {}", count, path)
+                )
+            ),
+            Err(e) => {
+                eprintln!("Could not import {}: {}", path, e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let arg_preview = env::var("TERMX_ARG_PREVIEW_CHARS")
+        .ok()
+        .and_then(|v| v.parse().ok());
+    let mut show_reasoning = args.show_reasoning;
+    let transcript = env::var("TERMX_TRANSCRIPT").is_ok_and(|v| v == "1" || v == "true");
+    if let Some(prompt) = args.prompt {
+        if transcript {
+            start_transcript(&mut session, args.json);
+        }
+        let console = ConsoleStreamHandler::new()
+            .with_reasoning(show_reasoning)
+            .with_arg_preview(arg_preview);
+        return run_one_shot(&agent, &mut session, prompt, args.json, console).await;
+    }
+
+    let mut labels = console::ReplLabels::default();
+    if let Ok(label) = env::var("TERMX_USER_LABEL") {
+        labels.user = label;
+    }
+    if let Ok(label) = env::var("TERMX_AGENT_LABEL") {
+        labels.agent = label;
+    }
+    for (var, color) in [
+        ("TERMX_USER_COLOR", &mut labels.user_color),
+        ("TERMX_AGENT_COLOR", &mut labels.agent_color),
+    ] {
+        let Ok(value) = env::var(var) else { continue };
+        match style::parse_color(&value) {
+            Some(code) => *color = code,
+            None => eprintln!(
+                "{}",
+                style::paint(
+                    style::YELLOW,
+                    &format!("Warning: unknown color '{}' in {}", value, var)
+                )
+            ),
+        }
+    }
+
+    start_autosave(&mut session, args.resume.is_none());
+    // After autosave, which may have swapped in a resumed session
+    if transcript {
+        start_transcript(&mut session, false);
+    }
+    let mut history = PromptHistory::load(
+        &Path::new(workspace::AGENT_DIR).join(workspace::HISTORY_FILE),
+        HISTORY_CAP,
+    );
 
     loop {
-        print!("\u{001b}[93mYou:\u{001b}[0m ");
+        print!("{}", labels.user_prompt(style::color_enabled()));
         io::stdout().flush().unwrap();
 
-        let mut input = String::new();
-        if io::stdin().read_line(&mut input).is_err() {
-            eprintln!("Failed to read input.");
-            continue;
+        // Read on a blocking thread so Ctrl-C at the idle prompt can end the session
+        let read = tokio::select! {
+            read = tokio::task::spawn_blocking(read_input_line) => read,
+            _ = interrupt::ctrl_c() => {
+                println!();
+                end_session(&mut session);
+                // The pending stdin read would block runtime shutdown
+                std::process::exit(0);
+            }
+        };
+        let input = match read {
+            Ok(Ok(input)) => input,
+            _ => {
+                eprintln!("Failed to read input.");
+                continue;
+            }
+        };
+
+        // End of input (Ctrl-D or a closed pipe)
+        if input.is_empty() {
+            println!();
+            end_session(&mut session);
+            break;
         }
 
-        let trimmed = input.trim();
-        if trimmed.eq_ignore_ascii_case("quit") {
+        let Some(trimmed) = console::prepare_input(&input) else {
             println!(
-                r#"
-{cyan}Session Summary:{reset}
-{green}  Session ID:{reset}    {}
-{green}  Total Messages:{reset} {}
-{green}  Ended at:{reset}      {}
-{cyan}Thank you for using termx! 🚀{reset}"#,
-                session.id,
-                session.messages.len(),
-                Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
-                cyan = "\x1b[36m",
-                green = "\x1b[32m",
-                reset = "\x1b[0m"
+                "{}",
+                style::paint(style::GRAY, "(type a request, or 'help' for commands)")
             );
+            continue;
+        };
+        if trimmed.eq_ignore_ascii_case("quit") {
+            end_session(&mut session);
             break;
         } else if trimmed.eq_ignore_ascii_case("help") {
             println!(
@@ -148,23 +387,191 @@ async fn main() -> anyhow::Result<()> {
 {green}  clear{reset}    - Clear the terminal screen
 {green}  quit{reset}     - Exit the program and show session summary
 {green}  status{reset}   - Show current session information
+{green}  /raw{reset}     - Show the last raw assistant message and its tool calls
+{green}  /save{reset}    - Save the session now and show where
+{green}  /reasoning{reset} - Toggle showing the model's reasoning
+{green}  /title [text]{reset} - Show or set the session title
+{green}  /config{reset}  - Show the effective configuration (secrets redacted)
+{green}  /tools{reset}   - List the agent's tools, their parameters and which need approval
+{green}  /steps [n]{reset}  - Show or set the step budget for following prompts
+{green}  /pin <path>{reset}   - Keep a file's current contents in context every turn
+{green}  /unpin <path>{reset} - Stop pinning a file
+{green}  /open <path>{reset}  - Edit a file in $VISUAL/$EDITOR; the agent sees your changes
+{green}  /restore [n]{reset}  - List trashed files, or restore entry n (or its id)
+{green}  /history{reset} - List prompts from this and earlier runs
+{green}  /summary{reset} - List files changed and commands run this session
+{green}  Ctrl-C{reset}   - Cancel the running turn (exits when idle)
 
 {cyan}Usage:{reset}
 Simply type your coding task or question as a natural language prompt.
-The agent will use various tools to help you with your request."#,
-                cyan = "\x1b[36m",
-                green = "\x1b[32m",
-                reset = "\x1b[0m"
+The agent will use various tools to help you with your request.
+Mention a file as @path to attach its contents to the prompt."#,
+                cyan = style::sgr(style::CYAN),
+                green = style::sgr(style::GREEN),
+                reset = style::reset()
             );
             continue;
         } else if trimmed.eq_ignore_ascii_case("clear") {
             // Clear terminal screen
             if cfg!(target_os = "windows") {
-                Command::new("cmd").args(&["/C", "cls"]).status().ok();
+                Command::new("cmd").args(["/C", "cls"]).status().ok();
             } else {
                 Command::new("clear").status().ok();
             }
             continue;
+        } else if trimmed == "/raw" || trimmed == "/last" {
+            match session
+                .messages
+                .iter()
+                .rev()
+                .find(|m| m.role == "assistant")
+            {
+                Some(message) => println!(
+                    "{}",
+                    console::sanitize_for_display(&console::format_raw_message(message))
+                ),
+                None => println!("No assistant message yet"),
+            }
+            continue;
+        } else if trimmed == "/summary" || trimmed == "/changes" {
+            println!("{}", changes::format_change_report(&session.changes));
+            continue;
+        } else if trimmed == "/tools" {
+            let opts = agent.options();
+            let approvals = agent.approvals();
+            println!(
+                "{}",
+                console::format_tools(agent.tools(), |name| {
+                    !opts.yolo && approvals.requires_approval(name)
+                })
+            );
+            continue;
+        } else if trimmed == "/config" || trimmed == "/env" {
+            let tools = agent.tools().names();
+            println!(
+                "{}",
+                console::format_config(&base_url, &model, agent.options(), &tools)
+            );
+            continue;
+        } else if let Some(value) = console::command_arg(trimmed, "/steps") {
+            if value.is_empty() {
+                println!("Max steps: {}", agent.options().max_steps);
+            } else {
+                match cli::parse_step_count(value) {
+                    Ok(n) => {
+                        agent.set_max_steps(n);
+                        println!(
+                            "{}",
+                            style::paint(style::BRIGHT_GREEN, &format!("Max steps set to {}", n))
+                        );
+                    }
+                    Err(e) => eprintln!("{}", style::paint(style::BRIGHT_RED, &e)),
+                }
+            }
+            continue;
+        } else if let Some(choice) = console::command_arg(trimmed, "/restore") {
+            restore_command(choice);
+            continue;
+        } else if trimmed == "/history" {
+            let entries = history.entries();
+            let shown = &entries[entries.len().saturating_sub(HISTORY_SHOWN)..];
+            if shown.is_empty() {
+                println!("{}", style::paint(style::GRAY, "No prompt history yet"));
+            }
+            let first = entries.len() - shown.len() + 1;
+            for (i, prompt) in shown.iter().enumerate() {
+                println!("{:>4}  {}", first + i, prompt);
+            }
+            continue;
+        } else if trimmed == "/reasoning" {
+            show_reasoning = !show_reasoning;
+            let state = if show_reasoning { "shown" } else { "hidden" };
+            println!(
+                "{}",
+                style::paint(style::GRAY, &format!("Reasoning is now {}", state))
+            );
+            continue;
+        } else if trimmed == "/save" {
+            match save_session(&mut session) {
+                Ok(path) => println!(
+                    "{}",
+                    style::paint(style::BRIGHT_GREEN, &format!("Saved to {}", path.display()))
+                ),
+                Err(e) => eprintln!(
+                    "{} {}",
+                    style::paint(style::BRIGHT_RED, "Failed to save session:"),
+                    e
+                ),
+            }
+            continue;
+        } else if let Some(title) = console::command_arg(trimmed, "/title") {
+            println!("{}", console::title_command(&mut session, title));
+            continue;
+        } else if let Some(path) = console::command_arg(trimmed, "/pin") {
+            let path = path.trim();
+            if path.is_empty() {
+                if session.pinned_files.is_empty() {
+                    println!(
+                        "{}",
+                        style::paint(style::GRAY, "No pinned files. Usage: /pin <path>")
+                    );
+                } else {
+                    println!("Pinned: {}", session.pinned_files.join(", "));
+                }
+            } else if !Path::new(path).is_file() {
+                eprintln!(
+                    "{} {}",
+                    style::paint(style::BRIGHT_RED, "Not a file:"),
+                    path
+                );
+            } else if session.pin(path) {
+                println!(
+                    "{}",
+                    style::paint(style::BRIGHT_GREEN, &format!("📌 Pinned {}", path))
+                );
+            } else {
+                println!("{} is already pinned", path);
+            }
+            continue;
+        } else if let Some(path) = console::command_arg(trimmed, "/open") {
+            if path.is_empty() {
+                println!("{}", style::paint(style::GRAY, "Usage: /open <path>"));
+                continue;
+            }
+            match console::open_in_editor(path) {
+                // Show the agent the new contents on the next turn
+                Ok(true) => {
+                    session.add_message(console::user_edit_message(path));
+                    println!(
+                        "{}",
+                        style::paint(
+                            style::BRIGHT_GREEN,
+                            &format!("The agent will see your changes to {}", path)
+                        )
+                    );
+                }
+                Ok(false) => println!(
+                    "{}",
+                    style::paint(style::GRAY, &format!("{} unchanged", path))
+                ),
+                Err(e) => eprintln!(
+                    "{} {}",
+                    style::paint(style::BRIGHT_RED, &format!("Could not open {}:", path)),
+                    e
+                ),
+            }
+            continue;
+        } else if let Some(path) = console::command_arg(trimmed, "/unpin") {
+            let path = path.trim();
+            if session.unpin(path) {
+                println!(
+                    "{}",
+                    style::paint(style::BRIGHT_GREEN, &format!("Unpinned {}", path))
+                );
+            } else {
+                println!("{} is not pinned", path);
+            }
+            continue;
         } else if trimmed.eq_ignore_ascii_case("status") {
             println!(
                 r#"
@@ -177,37 +584,361 @@ The agent will use various tools to help you with your request."#,
                 session.model.as_deref().unwrap_or("default"),
                 session.messages.len(),
                 Utc::now().format("%H:%M:%S"),
-                cyan = "\x1b[36m",
-                green = "\x1b[32m",
-                reset = "\x1b[0m"
+                cyan = style::sgr(style::CYAN),
+                green = style::sgr(style::GREEN),
+                reset = style::reset()
             );
             continue;
         }
 
-        print!("\u{001b}[96mAgent:\u{001b}[0m ");
+        if let Err(e) = history.append(trimmed) {
+            eprintln!(
+                "{}",
+                style::paint(
+                    style::GRAY,
+                    &format!("Could not save prompt history: {}", e)
+                )
+            );
+        }
+        let (prompt, missing) = console::expand_mentions(trimmed);
+        for path in missing {
+            eprintln!(
+                "{}",
+                style::paint(
+                    style::YELLOW,
+                    &format!(
+                        "Warning: @{} is not a readable file; sent as plain text",
+                        path
+                    )
+                )
+            );
+        }
+
+        print!("{}", labels.agent_prompt(style::color_enabled()));
         io::stdout().flush().unwrap();
 
-        if let Err(e) = agent
-            .run_agent_loop(trimmed.to_string(), &mut session)
-            .await
-        {
-            eprintln!("\n\u{001b}[91mError:\u{001b}[0m {}", e);
-            println!(
-                "\n\u{001b}[96mAgent:\u{001b}[0m Something went wrong. Please try again or type 'help' for available commands."
+        // Ctrl-C during a turn cancels it and returns to the prompt
+        let cancel = CancellationToken::new();
+        let watcher = interrupt::cancel_on_signal(interrupt::ctrl_c(), cancel.clone());
+        let result = agent
+            .run_agent_loop_cancellable(
+                prompt,
+                &mut session,
+                &mut ConsoleStreamHandler::interactive()
+                    .with_reasoning(show_reasoning)
+                    .with_arg_preview(arg_preview),
+                &cancel,
+            )
+            .await;
+        watcher.abort();
+
+        match result {
+            Ok(Some(run)) => {
+                if run.hit_step_limit {
+                    println!("\n(Reached step limit without final answer.)");
+                }
+                // Print newline to separate from next prompt
+                println!();
+            }
+            Ok(None) => {
+                println!("\n{}", style::paint(style::GRAY, "(stopped)"));
+            }
+            Err(e) => {
+                eprintln!("\n{} {}", style::paint(style::BRIGHT_RED, "Error:"), e);
+                println!(
+                    "\n{} Something went wrong. Please try again or type 'help' for available commands.",
+                    style::paint(style::BRIGHT_CYAN, "Agent:")
+                );
+            }
+        }
+        session.flush_autosave();
+    }
+
+    Ok(())
+}
+
+// Offer to resume a session that ended without a clean exit (unless the
+// user already picked one to resume), then keep the current one saved as it
+// changes
+fn start_autosave(session: &mut Session, offer_resume: bool) {
+    let dir = match workspace::ensure_subdir(Path::new("."), workspace::SESSIONS_DIR) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                style::paint(style::GRAY, &format!("Autosave disabled: {}", e))
             );
-        } else {
-            // Print newline to separate from next prompt
-            println!();
+            return;
+        }
+    };
+
+    if let Some(path) = session::find_unfinished(&dir).filter(|_| offer_resume) {
+        match Session::load(&path) {
+            Ok(previous) => {
+                let question = format!(
+                    "Resume unfinished session '{}' ({} messages)?",
+                    previous.title.as_deref().unwrap_or("untitled"),
+                    previous.messages.len()
+                );
+                if tools::get_user_approval(&question).unwrap_or(false) {
+                    println!(
+                        "{}",
+                        style::paint(
+                            style::BRIGHT_GREEN,
+                            &format!("Resumed session {}", previous.id)
+                        )
+                    );
+                    *session = previous;
+                    // A crash mid-turn can leave tool calls without results
+                    session.close_dangling_tool_calls("Interrupted by a crash");
+                }
+            }
+            Err(e) => eprintln!("Could not read {}: {}", path.display(), e),
         }
+        let _ = session::discard_unfinished(&path);
     }
 
+    if let Err(e) = session.enable_autosave(&dir, AUTOSAVE_INTERVAL) {
+        eprintln!(
+            "{}",
+            style::paint(style::GRAY, &format!("Autosave disabled: {}", e))
+        );
+    }
+}
+
+// Offer web_search when TERMX_WEB_SEARCH_URL names a search API
+#[cfg(feature = "web-search")]
+fn enable_web_search(registry: ToolRegistry) -> ToolRegistry {
+    let Some(endpoint) = env::var("TERMX_WEB_SEARCH_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+    else {
+        return registry;
+    };
+    tools::configure_web_search(tools::WebSearchConfig {
+        endpoint,
+        api_key: env::var("TERMX_WEB_SEARCH_KEY").ok(),
+        key_header: env::var("TERMX_WEB_SEARCH_KEY_HEADER").ok(),
+    });
+    registry.with_web_search()
+}
+
+#[cfg(not(feature = "web-search"))]
+fn enable_web_search(registry: ToolRegistry) -> ToolRegistry {
+    if env::var("TERMX_WEB_SEARCH_URL").is_ok() {
+        eprintln!(
+            "{}",
+            style::paint(
+                style::YELLOW,
+                "Warning: TERMX_WEB_SEARCH_URL is set but termx was built without the web-search feature"
+            )
+        );
+    }
+    registry
+}
+
+// Log the conversation to .termx/transcripts/{id}.log as it happens
+fn start_transcript(session: &mut Session, quiet: bool) {
+    let started = workspace::ensure_subdir(Path::new("."), workspace::TRANSCRIPTS_DIR)
+        .and_then(|dir| session.enable_transcript(&dir));
+    match started {
+        Ok(path) if !quiet => {
+            println!(
+                "{}",
+                style::paint(style::GRAY, &format!("Transcript: {}", path.display()))
+            )
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!(
+            "{}",
+            style::paint(style::GRAY, &format!("Transcript disabled: {}", e))
+        ),
+    }
+}
+
+// `/restore` lists the trash; `/restore <n|id>` restores one entry
+fn restore_command(choice: &str) {
+    let root = Path::new(".");
+    let ids = match tools::list_trash(root) {
+        Ok(ids) => ids,
+        Err(e) => {
+            eprintln!("{}", style::paint(style::BRIGHT_RED, &e.to_string()));
+            return;
+        }
+    };
+    if choice.is_empty() {
+        if ids.is_empty() {
+            println!("{}", style::paint(style::GRAY, "Trash is empty"));
+        }
+        for (i, id) in ids.iter().enumerate() {
+            println!("  {:>3}  {}", i + 1, id);
+        }
+        return;
+    }
+    let id = match choice.parse::<usize>() {
+        Ok(n) if (1..=ids.len()).contains(&n) => ids[n - 1].as_str(),
+        _ => choice,
+    };
+    match tools::restore_from_trash(id, root) {
+        Ok(msg) => println!("{}", style::paint(style::BRIGHT_GREEN, &msg)),
+        Err(e) => eprintln!("{}", style::paint(style::BRIGHT_RED, &e.to_string())),
+    }
+}
+
+fn print_banner() {
+    // ASCII Art Banner
+    println!(
+        r#"
+        ███████████ ██████████ ███████████   ██████   ██████ █████ █████
+       ░█░░░███░░░█░░███░░░░░█░░███░░░░░███ ░░██████ ██████ ░░███ ░░███
+       ░   ░███  ░  ░███  █ ░  ░███    ░███  ░███░█████░███  ░░███ ███
+           ░███     ░██████    ░██████████   ░███░░███ ░███   ░░█████
+           ░███     ░███░░█    ░███░░░░░███  ░███ ░░░  ░███    ███░███
+           ░███     ░███ ░   █ ░███    ░███  ░███      ░███   ███ ░░███
+           █████    ██████████ █████   █████ █████     █████ █████ █████
+          ░░░░░    ░░░░░░░░░░ ░░░░░   ░░░░░ ░░░░░     ░░░░░ ░░░░░ ░░░░░
+        "#
+    );
+
+    println!(
+        "{}",
+        style::paint(style::BLUE, "termx - Advanced Coding Assistant")
+    );
+    println!(
+        "{}",
+        style::paint(
+            style::GRAY,
+            &format!("Started at: {}", Utc::now().format("%Y-%m-%d %H:%M:%S UTC"))
+        )
+    );
+}
+
+fn read_input_line() -> io::Result<String> {
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input)
+}
+
+// Run a single prompt to completion, printing either the streamed answer or
+// a JSON report, then save the session
+async fn run_one_shot(
+    agent: &Agent,
+    session: &mut Session,
+    prompt: String,
+    json: bool,
+    mut console: ConsoleStreamHandler,
+) -> anyhow::Result<()> {
+    let result = if json {
+        agent
+            .run_agent_loop(prompt, session, &mut SilentStreamHandler)
+            .await?
+    } else {
+        let result = agent.run_agent_loop(prompt, session, &mut console).await?;
+        if result.hit_step_limit {
+            println!("\n(Reached step limit without final answer.)");
+        }
+        println!();
+        if !session.changes.is_empty() {
+            println!("\n{}", changes::format_change_report(&session.changes));
+        }
+        result
+    };
+
+    if json {
+        cli::write_json_report(&mut io::stdout().lock(), &result, session)?;
+    }
+
+    if let Err(err) = save_session(session) {
+        eprintln!("Failed to save session: {}", err);
+    }
     Ok(())
 }
 
-fn create_agent_dir() {
-    if let Err(err) = std::fs::create_dir(".termx") {
-        if err.kind() != std::io::ErrorKind::AlreadyExists {
-            eprintln!("Error creating .termx: {}", err);
+// Write the session under .termx/sessions, titling it first if needed
+fn save_session(session: &mut Session) -> io::Result<std::path::PathBuf> {
+    session.ensure_title();
+    let dir = workspace::ensure_subdir(Path::new("."), workspace::SESSIONS_DIR)?;
+    session.save(&dir)
+}
+
+// `termx sessions list` and `termx sessions export`
+fn sessions_command(command: &cli::SessionsCommand) -> Result<(), String> {
+    let dir = Path::new(workspace::AGENT_DIR).join(workspace::SESSIONS_DIR);
+    match command {
+        cli::SessionsCommand::List => {
+            let sessions = session::saved_sessions(&dir);
+            if sessions.is_empty() {
+                println!("No saved sessions in {}", dir.display());
+            }
+            for (_, saved) in sessions {
+                println!(
+                    "{}  {}  {:>4} messages  {}",
+                    saved.id,
+                    saved.updated_at.format("%Y-%m-%d %H:%M"),
+                    saved.messages.len(),
+                    saved.title.as_deref().unwrap_or("untitled")
+                );
+            }
+        }
+        cli::SessionsCommand::Export { id, output } => {
+            let saved = load_saved_session(id)?;
+            // The shape --import reads back
+            let export =
+                serde_json::json!({ "messages": llm_client::wire_messages(&saved.messages) });
+            let json = serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?;
+            match output {
+                Some(file) => {
+                    fs::write(file, json + "\n")
+                        .map_err(|e| format!("Could not write {}: {}", file, e))?;
+                    eprintln!("Exported {} messages to {}", saved.messages.len(), file);
+                }
+                None => println!("{}", json),
+            }
         }
     }
+    Ok(())
+}
+
+// A session saved under .termx/sessions, by id or unique id prefix
+fn load_saved_session(id: &str) -> Result<Session, String> {
+    let dir = Path::new(workspace::AGENT_DIR).join(workspace::SESSIONS_DIR);
+    let path = session::find_saved(&dir, id)?;
+    Session::load(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))
+}
+
+// Save the session and print the closing summary
+fn end_session(session: &mut Session) {
+    let saved = save_session(session);
+    if let Err(err) = &saved {
+        eprintln!("Failed to save session: {}", err);
+    }
+    session.finish_autosave();
+
+    if !session.changes.is_empty() {
+        println!("\n{}", changes::format_change_report(&session.changes));
+    }
+    println!(
+        r#"
+{cyan}Session Summary:{reset}
+{green}  Session ID:{reset}    {}
+{green}  Total Messages:{reset} {}
+{green}  Saved to:{reset}      {}
+{green}  Ended at:{reset}      {}
+{cyan}Thank you for using termx! 🚀{reset}"#,
+        session.id,
+        session.messages.len(),
+        saved
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "-".to_string()),
+        Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+        cyan = style::sgr(style::CYAN),
+        green = style::sgr(style::GREEN),
+        reset = style::reset()
+    );
+}
+
+fn create_agent_dir() {
+    if let Err(err) = workspace::init_agent_dir(Path::new(".")) {
+        eprintln!("Error creating {}: {}", workspace::AGENT_DIR, err);
+    }
 }
@@ -1,18 +1,18 @@
+use crate::agent::{OnContent, StreamDelta};
 use crate::types::{Message, ToolCall, FunctionCall};
 use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::Value;
 use std::sync::{Arc, Mutex};
-
-#[async_trait]
-pub trait LlmClientTrait {
-    async fn chat_once(&self, messages: &[Message], tools: &Value) -> Result<Message>;
-}
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct MockLlmClient {
     responses: Arc<Mutex<Vec<Message>>>,
     call_history: Arc<Mutex<Vec<Vec<Message>>>>,
+    failures: Arc<Mutex<Vec<String>>>,
+    delay: Option<Duration>,
+    chunk_delay: Option<Duration>,
 }
 
 impl MockLlmClient {
@@ -20,15 +20,30 @@ impl MockLlmClient {
         Self {
             responses: Arc::new(Mutex::new(Vec::new())),
             call_history: Arc::new(Mutex::new(Vec::new())),
+            failures: Arc::new(Mutex::new(Vec::new())),
+            delay: None,
+            chunk_delay: None,
         }
     }
 
+    // Simulate a slow provider: every chat call sleeps before responding
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = Some(delay);
+    }
+
+    // Pause between streamed chunks, so a turn can be cancelled mid-reply
+    pub fn set_chunk_delay(&mut self, delay: Duration) {
+        self.chunk_delay = Some(delay);
+    }
+
     pub fn add_text_response(&mut self, content: &str) {
         let response = Message {
             role: "assistant".to_string(),
             content: Some(content.to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         };
         self.responses.lock().unwrap().push(response);
     }
@@ -48,24 +63,63 @@ impl MockLlmClient {
             content: None,
             tool_calls: Some(vec![tool_call]),
             tool_call_id: None,
+            name: None,
+            created_at: None,
         };
         self.responses.lock().unwrap().push(response);
     }
 
+    // Several tool calls in one assistant message, ids "test-call-1", "test-call-2", …
+    pub fn add_tool_calls_response(&mut self, calls: &[(&str, &str)]) {
+        let tool_calls = calls
+            .iter()
+            .enumerate()
+            .map(|(i, (name, args))| ToolCall {
+                id: format!("test-call-{}", i + 1),
+                call_type: "function".to_string(),
+                function: FunctionCall {
+                    name: name.to_string(),
+                    arguments: args.to_string(),
+                },
+            })
+            .collect();
+
+        let response = Message {
+            role: "assistant".to_string(),
+            content: None,
+            tool_calls: Some(tool_calls),
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        };
+        self.responses.lock().unwrap().push(response);
+    }
+
+    // Make the next chat call fail with `error` (like a provider error
+    // response) before any configured response is used
+    pub fn fail_next(&mut self, error: &str) {
+        self.failures.lock().unwrap().push(error.to_string());
+    }
+
+    #[allow(dead_code)]
     pub fn add_error_response(&mut self, error_msg: &str) {
         let response = Message {
             role: "assistant".to_string(),
             content: Some(format!("Error: {}", error_msg)),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         };
         self.responses.lock().unwrap().push(response);
     }
 
+    #[allow(dead_code)]
     pub fn get_call_history(&self) -> Vec<Vec<Message>> {
         self.call_history.lock().unwrap().clone()
     }
 
+    #[allow(dead_code)]
     pub fn clear_responses(&mut self) {
         self.responses.lock().unwrap().clear();
         self.call_history.lock().unwrap().clear();
@@ -79,6 +133,8 @@ impl MockLlmClient {
                 content: Some("No more mock responses configured".to_string()),
                 tool_calls: None,
                 tool_call_id: None,
+                name: None,
+                created_at: None,
             })
         } else {
             Some(responses.remove(0))
@@ -91,7 +147,18 @@ impl MockLlmClient {
     pub async fn chat_once(&self, messages: &[Message], _tools: &Value) -> Result<Message> {
         // Store the call for verification
         self.call_history.lock().unwrap().push(messages.to_vec());
-        
+
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        {
+            let mut failures = self.failures.lock().unwrap();
+            if !failures.is_empty() {
+                anyhow::bail!("{}", failures.remove(0));
+            }
+        }
+
         // Return the next configured response
         self.pop_response()
             .ok_or_else(|| anyhow::anyhow!("No mock response available"))
@@ -104,6 +171,25 @@ impl crate::agent::LlmClientTrait for MockLlmClient {
         self.chat_once(messages, tools).await
     }
     
+    // Stream content back one word at a time, like a real provider would
+    async fn chat_once_streaming(
+        &self,
+        messages: &[Message],
+        tools: &Value,
+        on_content: &mut OnContent<'_>,
+    ) -> Result<Message> {
+        let msg = self.chat_once(messages, tools).await?;
+        if let Some(content) = msg.content.as_deref() {
+            for chunk in content.split_inclusive(' ') {
+                on_content(StreamDelta::Content(chunk));
+                if let Some(delay) = self.chunk_delay {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+        Ok(msg)
+    }
+
     async fn chat_once_no_stream(&self, _messages: &[Message]) -> Result<Message> {
         // For mock, just return the next configured response
         self.pop_response()
@@ -1 +1,2 @@
-pub mod mock_llm_client;
\ No newline at end of file
+pub mod mock_http_server;
+pub mod mock_llm_client;
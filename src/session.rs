@@ -1,8 +1,51 @@
 use chrono::Utc;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+use crate::console::sanitize_for_display;
 use crate::types::Message;
 pub use crate::types::Session;
+use crate::utils::{clip, clip_observation};
+
+// First line of the system message that carries pinned file contents
+const PINNED_CONTEXT_HEADER: &str = "Pinned files (current contents, refreshed every turn):";
+
+// Longest auto-generated title, in characters
+const TITLE_MAX_CHARS: usize = 48;
+
+// Stands in for messages dropped to fit the model's context window
+const DROPPED_NOTE_PREFIX: &str = "[Earlier conversation dropped to fit the context window:";
+
+// Characters of each tool result written to the transcript
+const TRANSCRIPT_RESULT_CHARS: usize = 2000;
+
+// Lines kept when a tool result is reduced to a summary
+const SUMMARY_LINES: usize = 10;
+
+/// Saves a session to `<dir>/{id}.json` as messages arrive, at most once per
+/// `interval`; changes in between are written by the next save or `flush`.
+/// While active, `<dir>/{id}.lock` marks the session as unfinished, so a
+/// crash can be detected on the next start.
+#[derive(Clone, Debug)]
+pub struct Autosave {
+    dir: PathBuf,
+    interval: Duration,
+    last_save: Option<Instant>,
+    pending: bool,
+}
+
+/// What to keep of a large tool result once the user has seen it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultRetention {
+    Full,
+    Summary,
+    Drop,
+}
 
 impl Session {
     pub fn new(title: Option<&str>, model: Option<&str>) -> Session {
@@ -13,19 +56,135 @@ impl Session {
             updated_at: Utc::now(),
             title: title.map(|s| s.to_string()),
             model: model.map(|s| s.to_string()),
+            pinned_files: Vec::new(),
+            shell_cwd: None,
+            write_stats: Default::default(),
+            changes: Default::default(),
+            autosave: None,
+            transcript: None,
+        }
+    }
+
+    /// Reads a session written by `save`.
+    pub fn load(path: &Path) -> io::Result<Session> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(io::Error::other)
+    }
+
+    /// Starts saving into `dir` after every change (debounced by `interval`)
+    /// and marks the session unfinished until `finish_autosave`.
+    pub fn enable_autosave(&mut self, dir: &Path, interval: Duration) -> io::Result<()> {
+        fs::write(lock_path(dir, &self.id), std::process::id().to_string())?;
+        self.save(dir)?;
+        self.autosave = Some(Autosave {
+            dir: dir.to_path_buf(),
+            interval,
+            last_save: Some(Instant::now()),
+            pending: false,
+        });
+        Ok(())
+    }
+
+    /// Starts appending a readable log of the conversation (prompts, replies,
+    /// tool calls and results) to `<dir>/{id}.log` as messages are added.
+    /// Returns the log's path.
+    pub fn enable_transcript(&mut self, dir: &Path) -> io::Result<PathBuf> {
+        let path = dir.join(format!("{}.log", self.id));
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        writeln!(
+            file,
+            "=== Session {} ({}) ===",
+            self.id,
+            Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+        )?;
+        self.transcript = Some(path.clone());
+        Ok(path)
+    }
+
+    // Append `msg` to the transcript, if one is on. Logging never fails a turn.
+    fn log_transcript(&self, msg: &Message) {
+        let Some(path) = &self.transcript else {
+            return;
+        };
+        let entry = transcript_entry(msg);
+        if entry.is_empty() {
+            return;
+        }
+        if let Ok(mut file) = fs::OpenOptions::new().append(true).open(path) {
+            let _ = file.write_all(entry.as_bytes());
+        }
+    }
+
+    /// Writes any change the debounce held back.
+    pub fn flush_autosave(&mut self) {
+        if let Some(mut autosave) = self.autosave.take() {
+            if autosave.pending && self.save(&autosave.dir).is_ok() {
+                autosave.pending = false;
+                autosave.last_save = Some(Instant::now());
+            }
+            self.autosave = Some(autosave);
+        }
+    }
+
+    /// Flushes, then clears the unfinished marker and stops autosaving.
+    pub fn finish_autosave(&mut self) {
+        self.flush_autosave();
+        if let Some(autosave) = self.autosave.take() {
+            let _ = fs::remove_file(lock_path(&autosave.dir, &self.id));
+        }
+    }
+
+    // Save now if the last autosave is at least `interval` old, otherwise
+    // leave it for the next change or flush. Failures are retried likewise.
+    fn autosave_changed(&mut self) {
+        let Some(mut autosave) = self.autosave.take() else {
+            return;
+        };
+        let now = Instant::now();
+        autosave.pending = true;
+        let due = autosave
+            .last_save
+            .is_none_or(|at| now.duration_since(at) >= autosave.interval);
+        if due && self.save(&autosave.dir).is_ok() {
+            autosave.pending = false;
+            autosave.last_save = Some(now);
+        }
+        self.autosave = Some(autosave);
+    }
+
+    /// Appends the conversation in an exported transcript (see
+    /// `transcript::parse_transcript`) and returns how many messages it added.
+    /// Tool calls the transcript never answered are closed.
+    pub fn import_transcript(&mut self, json: &str) -> Result<usize, String> {
+        let imported = crate::transcript::parse_transcript(json)?;
+        let count = imported.len();
+        for message in imported {
+            self.add_message(message);
         }
+        self.close_dangling_tool_calls("No result in the imported transcript");
+        self.ensure_title();
+        Ok(count)
     }
 
     // Replace all messages
+    #[cfg(test)]
     pub fn replace_messages(&mut self, messages: Vec<Message>) {
         self.messages = messages;
         self.updated_at = Utc::now();
     }
 
-    // Append one message
-    pub fn add_message(&mut self, msg: Message) {
+    // Append one message, stamping it if it has no timestamp yet
+    pub fn add_message(&mut self, mut msg: Message) {
+        if msg.created_at.is_none() {
+            msg.created_at = Some(Utc::now());
+        }
+        self.log_transcript(&msg);
         self.messages.push(msg);
         self.updated_at = Utc::now();
+        self.autosave_changed();
     }
 
     pub fn set_title(&mut self, title: Option<&str>) {
@@ -33,8 +192,359 @@ impl Session {
         self.updated_at = Utc::now();
     }
 
+    // Title derived from the first user message: its first line with
+    // whitespace collapsed, cut at a word boundary if long
+    pub fn derive_title(&self) -> Option<String> {
+        let first = self
+            .messages
+            .iter()
+            .find(|m| m.role == "user")?
+            .content
+            .as_deref()?;
+        let line = first.lines().find(|l| !l.trim().is_empty())?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let full = words.join(" ");
+        if full.chars().count() <= TITLE_MAX_CHARS {
+            return Some(full);
+        }
+        let mut title = String::new();
+        for word in words {
+            if title.chars().count() + word.chars().count() + 1 > TITLE_MAX_CHARS {
+                break;
+            }
+            if !title.is_empty() {
+                title.push(' ');
+            }
+            title.push_str(word);
+        }
+        if title.is_empty() {
+            title = full.chars().take(TITLE_MAX_CHARS).collect();
+        }
+        Some(format!("{}…", title))
+    }
+
+    // Give an untitled session a title from its first prompt
+    pub fn ensure_title(&mut self) {
+        if self.title.is_none()
+            && let Some(title) = self.derive_title()
+        {
+            self.set_title(Some(&title));
+        }
+    }
+
+    #[cfg(test)]
     pub fn set_model(&mut self, model: Option<&str>) {
         self.model = model.map(|s| s.to_string());
         self.updated_at = Utc::now();
     }
+
+    // Answer tool calls that never got a result (e.g. an interrupted turn),
+    // otherwise the provider rejects the next request.
+    pub fn close_dangling_tool_calls(&mut self, note: &str) {
+        let answered: HashSet<&str> = self
+            .messages
+            .iter()
+            .filter_map(|m| m.tool_call_id.as_deref())
+            .collect();
+        let pending: Vec<(String, String)> = self
+            .messages
+            .iter()
+            .filter_map(|m| m.tool_calls.as_ref())
+            .flatten()
+            .filter(|tc| !answered.contains(tc.id.as_str()))
+            .map(|tc| (tc.id.clone(), tc.function.name.clone()))
+            .collect();
+
+        for (id, name) in pending {
+            self.add_message(Message {
+                role: "tool".to_string(),
+                content: Some(note.to_string()),
+                tool_calls: None,
+                tool_call_id: Some(id),
+                name: Some(name),
+                created_at: None,
+            });
+        }
+    }
+
+    // Drop the older half of the conversation after the leading system
+    // messages, leaving a note in its place (a note from an earlier drop is
+    // folded into the new one). The cut moves forward past tool results so
+    // none is left without its call, and the newest message is always kept.
+    // Returns how many messages were dropped.
+    pub fn drop_oldest_half(&mut self) -> usize {
+        let is_note = |m: &Message| {
+            m.content
+                .as_deref()
+                .is_some_and(|c| c.starts_with(DROPPED_NOTE_PREFIX))
+        };
+        let start = self
+            .messages
+            .iter()
+            .position(|m| m.role != "system" || is_note(m))
+            .unwrap_or(self.messages.len());
+        let mut cut = start + (self.messages.len() - start) / 2;
+        while cut < self.messages.len() && self.messages[cut].role == "tool" {
+            cut += 1;
+        }
+        if cut >= self.messages.len() || self.messages[start..cut].iter().all(is_note) {
+            return 0;
+        }
+
+        let dropped: Vec<Message> = self.messages.drain(start..cut).collect();
+        let earlier: usize = dropped
+            .iter()
+            .filter_map(|m| m.content.as_deref()?.strip_prefix(DROPPED_NOTE_PREFIX))
+            .filter_map(|rest| rest.split_whitespace().next()?.parse::<usize>().ok())
+            .sum();
+        let notes = dropped.iter().filter(|m| is_note(m)).count();
+        let count = dropped.len() - notes;
+        self.messages.insert(
+            start,
+            Message {
+                role: "system".to_string(),
+                content: Some(format!(
+                    "{} {} messages. Re-read files or rerun tools if you need what they contained.]",
+                    DROPPED_NOTE_PREFIX,
+                    count + earlier
+                )),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+                created_at: Some(Utc::now()),
+            },
+        );
+        self.updated_at = Utc::now();
+        self.autosave_changed();
+        count
+    }
+
+    // Pin a file so its contents stay in context. Returns false if already pinned.
+    pub fn pin(&mut self, path: &str) -> bool {
+        if self.pinned_files.iter().any(|p| p == path) {
+            return false;
+        }
+        self.pinned_files.push(path.to_string());
+        self.updated_at = Utc::now();
+        true
+    }
+
+    // Returns false if the path wasn't pinned
+    pub fn unpin(&mut self, path: &str) -> bool {
+        let before = self.pinned_files.len();
+        self.pinned_files.retain(|p| p != path);
+        self.updated_at = Utc::now();
+        self.pinned_files.len() != before
+    }
+
+    pub fn is_pinned_context(message: &Message) -> bool {
+        message.role == "system"
+            && message
+                .content
+                .as_deref()
+                .is_some_and(|c| c.starts_with(PINNED_CONTEXT_HEADER))
+    }
+
+    // Replace the pinned-context system message with freshly read file
+    // contents (each clipped to `budget` chars). It sits right after the
+    // main system prompt; with nothing pinned it is removed.
+    pub fn refresh_pinned_context(&mut self, budget: usize) {
+        self.messages.retain(|m| !Self::is_pinned_context(m));
+        if self.pinned_files.is_empty() {
+            return;
+        }
+
+        let mut content = PINNED_CONTEXT_HEADER.to_string();
+        for path in &self.pinned_files {
+            let body = fs::read_to_string(path)
+                .map(|c| clip(&c, budget))
+                .unwrap_or_else(|e| format!("(unreadable: {})", e));
+            content.push_str(&format!("\n\n=== {} ===\n{}", path, body));
+        }
+
+        let at = usize::from(self.messages.first().is_some_and(|m| m.role == "system"));
+        self.messages.insert(
+            at,
+            Message {
+                role: "system".to_string(),
+                content: Some(content),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+                created_at: Some(Utc::now()),
+            },
+        );
+    }
+
+    // Rewrite the tool message answering `tool_call_id` per `retention`.
+    // Dropped results keep a stub, since every tool call needs an answer.
+    // Returns false if no such message exists.
+    pub fn retain_tool_result(&mut self, tool_call_id: &str, retention: ResultRetention) -> bool {
+        let Some(message) = self
+            .messages
+            .iter_mut()
+            .find(|m| m.role == "tool" && m.tool_call_id.as_deref() == Some(tool_call_id))
+        else {
+            return false;
+        };
+        let content = message.content.take().unwrap_or_default();
+        let total = content.lines().count();
+        message.content = Some(match retention {
+            ResultRetention::Full => content,
+            ResultRetention::Summary if total <= SUMMARY_LINES => content,
+            ResultRetention::Summary => {
+                let head: Vec<&str> = content.lines().take(SUMMARY_LINES).collect();
+                format!(
+                    "{}\n[summary: first {} of {} lines kept; run the tool again for the rest]",
+                    head.join("\n"),
+                    SUMMARY_LINES,
+                    total
+                )
+            }
+            ResultRetention::Drop => format!(
+                "[output dropped to save context ({} chars); run the tool again if needed]",
+                content.chars().count()
+            ),
+        });
+        self.updated_at = Utc::now();
+        true
+    }
+
+    // Write the session as `{id}.json` into `dir`. The file is replaced by
+    // a rename, so a crash mid-write leaves the previous save intact.
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        let path = dir.join(format!("{}.json", self.id));
+        let tmp = dir.join(format!("{}.json.tmp", self.id));
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+}
+
+/// One transcript block for `msg`: a timestamped header line, then the text.
+/// System messages (prompt, pinned files) are left out; control characters
+/// are neutralized so the log stays readable in a terminal.
+fn transcript_entry(msg: &Message) -> String {
+    let time = msg
+        .created_at
+        .unwrap_or_else(Utc::now)
+        .format("%H:%M:%S")
+        .to_string();
+    let text = sanitize_for_display(msg.content.as_deref().unwrap_or("").trim_end());
+    let mut out = String::new();
+    match msg.role.as_str() {
+        "user" => out.push_str(&format!("\n[{}] You:\n{}\n", time, text)),
+        "assistant" => {
+            if !text.is_empty() {
+                out.push_str(&format!("\n[{}] Agent:\n{}\n", time, text));
+            }
+            for call in msg.tool_calls.iter().flatten() {
+                out.push_str(&format!(
+                    "\n[{}] Tool call: {} {}\n",
+                    time,
+                    call.function.name,
+                    sanitize_for_display(&call.function.arguments)
+                ));
+            }
+        }
+        "tool" => out.push_str(&format!(
+            "\n[{}] Tool result ({}):\n{}\n",
+            time,
+            msg.name.as_deref().unwrap_or("unknown"),
+            clip_observation(&text, TRANSCRIPT_RESULT_CHARS)
+        )),
+        _ => {}
+    }
+    out
+}
+
+/// The most recently saved session in `dir` still marked unfinished, i.e.
+/// one whose run ended without `finish_autosave` (a crash or a kill).
+/// Sessions whose owning process is still running are left alone.
+pub fn find_unfinished(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lock"))
+        .filter(|lock| !lock_owner_alive(lock))
+        .map(|lock| lock.with_extension("json"))
+        .filter(|json| json.is_file())
+        .max_by_key(|json| fs::metadata(json).and_then(|m| m.modified()).ok())
+}
+
+/// Clears the unfinished marker of the session saved at `path`.
+pub fn discard_unfinished(path: &Path) -> io::Result<()> {
+    fs::remove_file(path.with_extension("lock"))
+}
+
+/// Sessions saved in `dir`, most recently updated first. Files that don't
+/// parse as a session are skipped.
+pub fn saved_sessions(dir: &Path) -> Vec<(PathBuf, Session)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut sessions: Vec<(PathBuf, Session)> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| Session::load(&path).ok().map(|s| (path, s)))
+        .collect();
+    sessions.sort_by_key(|(_, session)| std::cmp::Reverse(session.updated_at));
+    sessions
+}
+
+/// The saved session in `dir` with id `id`, or else the only one whose id
+/// starts with it.
+pub fn find_saved(dir: &Path, id: &str) -> Result<PathBuf, String> {
+    let id = id.trim();
+    let exact = dir.join(format!("{}.json", id));
+    if !id.is_empty() && exact.is_file() {
+        return Ok(exact);
+    }
+    let matches: Vec<PathBuf> = saved_sessions(dir)
+        .into_iter()
+        .filter(|(_, session)| !id.is_empty() && session.id.starts_with(id))
+        .map(|(path, _)| path)
+        .collect();
+    match matches.as_slice() {
+        [path] => Ok(path.clone()),
+        [] => Err(format!("No saved session matches '{}'", id)),
+        _ => Err(format!(
+            "'{}' matches {} saved sessions; give more of the id",
+            id,
+            matches.len()
+        )),
+    }
+}
+
+// Whether the process whose pid `enable_autosave` wrote into `lock` is
+// still running. An unreadable lock counts as left behind by a crash.
+fn lock_owner_alive(lock: &Path) -> bool {
+    fs::read_to_string(lock)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .is_some_and(process_alive)
+}
+
+fn process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        return Path::new("/proc").join(pid.to_string()).exists();
+    }
+    if cfg!(target_os = "windows") {
+        return Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .is_ok_and(|out| String::from_utf8_lossy(&out.stdout).contains(&pid.to_string()));
+    }
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn lock_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.lock", id))
 }
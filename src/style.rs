@@ -0,0 +1,81 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// ANSI SGR codes used across the UI
+pub const RED: &str = "31";
+pub const GREEN: &str = "32";
+pub const CYAN: &str = "36";
+pub const GRAY: &str = "90";
+pub const BRIGHT_RED: &str = "91";
+pub const BRIGHT_GREEN: &str = "92";
+pub const YELLOW: &str = "93";
+pub const BLUE: &str = "94";
+pub const MAGENTA: &str = "35";
+pub const BRIGHT_CYAN: &str = "96";
+
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Enables color only when stdout is a terminal, `NO_COLOR` is unset and
+/// `--no-color` wasn't passed. Call once at startup.
+pub fn init(no_color_flag: bool) {
+    let enabled = !no_color_flag
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && std::io::stdout().is_terminal();
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+/// Parses a color setting: a name ("yellow", "bright_cyan", …), a raw SGR
+/// code such as "1;33", or "none" for no color (an empty code).
+pub fn parse_color(value: &str) -> Option<String> {
+    let value = value.trim().to_ascii_lowercase();
+    let code = match value.replace('-', "_").as_str() {
+        "none" => "",
+        "red" => RED,
+        "green" => GREEN,
+        "cyan" => CYAN,
+        "gray" | "grey" => GRAY,
+        "bright_red" => BRIGHT_RED,
+        "bright_green" => BRIGHT_GREEN,
+        "yellow" => YELLOW,
+        "blue" => BLUE,
+        "magenta" => MAGENTA,
+        "bright_cyan" => BRIGHT_CYAN,
+        code if !code.is_empty() && code.chars().all(|c| c.is_ascii_digit() || c == ';') => {
+            return Some(value);
+        }
+        _ => return None,
+    };
+    Some(code.to_string())
+}
+
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Wraps `text` in the given SGR code when color is enabled.
+pub fn paint(code: &str, text: &str) -> String {
+    paint_with(color_enabled(), code, text)
+}
+
+/// The escape that starts `code`, for templates that color several
+/// pieces; empty when color is off. Close with `reset()`.
+pub fn sgr(code: &str) -> String {
+    if color_enabled() && !code.is_empty() {
+        format!("\u{001b}[{}m", code)
+    } else {
+        String::new()
+    }
+}
+
+/// Ends whatever `sgr` started; empty when color is off.
+pub fn reset() -> &'static str {
+    if color_enabled() { "\u{001b}[0m" } else { "" }
+}
+
+pub fn paint_with(enabled: bool, code: &str, text: &str) -> String {
+    if enabled && !code.is_empty() {
+        format!("\u{001b}[{}m{}\u{001b}[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
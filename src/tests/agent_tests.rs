@@ -1,14 +1,71 @@
-use crate::agent::{Agent, AgentOptions};
+use crate::agent::{
+    Agent, AgentOptions, AgentStreamHandler, WriteLimits, repeated_failure_instruction,
+    wrap_up_instruction,
+};
+use crate::changes::{FileChange, format_change_report};
+use crate::console::ConsoleStreamHandler;
+use crate::interrupt::cancel_on_signal;
 use crate::mocks::mock_llm_client::MockLlmClient;
 use crate::session::Session;
 use crate::tool_registry::ToolRegistry;
+use crate::tools::{ApprovalOverrides, TextFormat};
 use crate::types::Message;
+use serde_json::Value;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+// Records every callback so tests can assert on the event order
+#[derive(Default)]
+struct RecordingHandler {
+    events: Vec<String>,
+    args: Vec<Value>,
+    results: Vec<String>,
+}
+
+impl AgentStreamHandler for RecordingHandler {
+    fn on_content(&mut self, chunk: &str) {
+        self.events.push(format!("content:{}", chunk));
+    }
+
+    fn on_tool_call(&mut self, name: &str, args: &Value) {
+        self.events.push(format!("call:{}", name));
+        self.args.push(args.clone());
+    }
+
+    fn on_tool_result(&mut self, name: &str, result: &str) {
+        self.events.push(format!("result:{}", name));
+        self.results.push(result.to_string());
+    }
+}
+
+// Stands in for stdout so a console handler's output can be inspected
+#[derive(Clone, Default)]
+struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedOutput {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[allow(dead_code)]
     fn create_test_agent() -> Agent {
         let mock_client = MockLlmClient::new();
         let tools = ToolRegistry::new();
@@ -35,6 +92,17 @@ mod tests {
         Agent::new(Box::new(mock_client), tools, opts)
     }
 
+    fn create_test_agent_with_client(mock_client: MockLlmClient) -> Agent {
+        let opts = AgentOptions {
+            max_steps: 5,
+            yolo: true, // auto-approve for tests
+            step_timeout: Duration::from_secs(10),
+            observation_clip: 1000,
+        };
+
+        Agent::new(Box::new(mock_client), ToolRegistry::new(), opts)
+    }
+
     #[tokio::test]
     async fn test_agent_compact_history() {
         let mut session = Session::new(None, None);
@@ -51,6 +119,8 @@ mod tests {
             content: Some("This is a very long tool response that definitely exceeds the observation clip limit of fifty characters and should be truncated".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Add a normal message
@@ -59,6 +129,8 @@ mod tests {
             content: Some("Normal message".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Create agent and test compaction
@@ -68,11 +140,15 @@ mod tests {
         agent.compact_history(&mut session);
         
         // Check that the tool message was clipped
-        let tool_content = session.messages[0].content.as_ref().unwrap();
-        if tool_content.len() > opts.observation_clip {
-            assert!(tool_content.contains("… [truncated]"));
-            assert!(tool_content.len() <= opts.observation_clip + "… [truncated]".len());
-        }
+        let tool_content = session.messages[0].content.clone().unwrap();
+        assert_eq!(
+            tool_content,
+            "[truncated: showing 50 of 128 chars]\nThis is a very long tool response that definitely "
+        );
+
+        // Compacting again leaves it as it is
+        agent.compact_history(&mut session);
+        assert_eq!(session.messages[0].content, Some(tool_content));
         
         // Check that normal messages are not affected
         assert_eq!(session.messages[1].content, Some("Normal message".to_string()));
@@ -115,6 +191,8 @@ mod tests {
         assert!(tool_names.contains(&"search_in_files".to_string()));
         assert!(tool_names.contains(&"edit_file".to_string()));
         assert!(tool_names.contains(&"insert_in_file".to_string()));
+        assert!(tool_names.contains(&"insert_at_line".to_string()));
+        assert!(tool_names.contains(&"replace_lines".to_string()));
     }
 
     #[test]
@@ -137,6 +215,8 @@ mod tests {
             content: Some("You are a helpful assistant".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Create agent with mock that returns text
@@ -168,6 +248,8 @@ mod tests {
             content: Some("You are a helpful assistant".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Add user message requesting a tool call
@@ -176,6 +258,8 @@ mod tests {
             content: Some("List the current directory".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Create agent with mock that returns a tool call
@@ -215,6 +299,8 @@ mod tests {
             content: Some("You are a helpful assistant".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         session.add_message(Message {
@@ -222,6 +308,8 @@ mod tests {
             content: Some("List the current directory".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Verify session state
@@ -231,4 +319,1152 @@ mod tests {
         assert_eq!(session.title, Some("Test Session".to_string()));
         assert_eq!(session.model, Some("test-model".to_string()));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_cancel_signal_interrupts_long_turn() {
+        let mut session = Session::new(None, None);
+
+        // A reply that would otherwise sit behind a 30s provider delay
+        let mut mock_client = MockLlmClient::new();
+        mock_client.set_delay(Duration::from_secs(30));
+        mock_client.add_text_response("too late");
+
+        let agent = Agent::new(Box::new(mock_client), ToolRegistry::new(), AgentOptions {
+            max_steps: 5,
+            yolo: true,
+            step_timeout: Duration::from_secs(60),
+            observation_clip: 1000,
+        });
+
+        let cancel = CancellationToken::new();
+        let signal = tokio::time::sleep(Duration::from_millis(50));
+        let watcher = cancel_on_signal(signal, cancel.clone());
+
+        let started = std::time::Instant::now();
+        let result = agent
+            .run_agent_loop_cancellable(
+                "do something slow".to_string(),
+                &mut session,
+                &mut RecordingHandler::default(),
+                &cancel,
+            )
+            .await
+            .unwrap();
+        watcher.abort();
+
+        assert!(result.is_none());
+        assert!(cancel.is_cancelled());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // The user prompt is kept so the conversation can continue
+        assert_eq!(session.messages.last().unwrap().role, "user");
+    }
+
+    #[tokio::test]
+    async fn test_cancellable_loop_completes_without_signal() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_text_response("Done");
+
+        let agent = Agent::new(Box::new(mock_client), ToolRegistry::new(), AgentOptions {
+            max_steps: 5,
+            yolo: true,
+            step_timeout: Duration::from_secs(10),
+            observation_clip: 1000,
+        });
+
+        let cancel = CancellationToken::new();
+        let result = agent
+            .run_agent_loop_cancellable(
+                "hi".to_string(),
+                &mut session,
+                &mut RecordingHandler::default(),
+                &cancel,
+            )
+            .await
+            .unwrap();
+
+        assert!(result.is_some());
+        assert_eq!(session.messages.last().unwrap().content, Some("Done".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_tool_observation_carries_name() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+
+        let agent = create_test_agent_with_client(mock_client);
+        agent.run_turn(&mut session).await.unwrap();
+
+        let observation = session.messages.last().unwrap();
+        assert_eq!(observation.role, "tool");
+        let wire = serde_json::to_value(observation).unwrap();
+        assert_eq!(wire["name"], "list_dir");
+        assert_eq!(wire["tool_call_id"], "test-call-123");
+    }
+
+
+    #[tokio::test]
+    async fn test_stream_handler_sees_content_and_tools_in_order() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_text_response("All done here");
+
+        let agent = create_test_agent_with_client(mock_client);
+        let mut handler = RecordingHandler::default();
+        agent
+            .run_agent_loop("look around".to_string(), &mut session, &mut handler)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            handler.events,
+            vec![
+                "call:list_dir",
+                "result:list_dir",
+                "content:All ",
+                "content:done ",
+                "content:here",
+            ]
+        );
+    }
+
+
+    #[tokio::test]
+    async fn test_console_prints_streamed_answer_once() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_text_response("The answer is 42");
+
+        let agent = create_test_agent_with_client(mock_client);
+        let output = CapturedOutput::default();
+        let mut console = ConsoleStreamHandler::new().with_output(output.clone());
+        let run = agent
+            .run_agent_loop("what is it?".to_string(), &mut session, &mut console)
+            .await
+            .unwrap();
+
+        assert_eq!(run.final_text.as_deref(), Some("The answer is 42"));
+        let printed = output.text();
+        assert_eq!(printed.matches("The answer is 42").count(), 1, "{}", printed);
+        assert!(printed.contains("list_dir"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_tool_arguments_are_repaired() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."#);
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_text_response("Done");
+
+        let agent = create_test_agent_with_client(mock_client);
+        agent
+            .run_agent_loop("look around".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let observations: Vec<&Message> = session.messages.iter().filter(|m| m.role == "tool").collect();
+        assert_eq!(observations.len(), 2);
+        assert!(observations[0].content.as_ref().unwrap().contains("invalid JSON arguments"));
+        assert!(!observations[1].content.as_ref().unwrap().contains("invalid JSON arguments"));
+        assert_eq!(session.messages.last().unwrap().content, Some("Done".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_malformed_arguments_give_up() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        for _ in 0..5 {
+            mock_client.add_tool_call_response("list_dir", "not json");
+        }
+
+        let agent = create_test_agent_with_client(mock_client);
+        let result = agent
+            .run_agent_loop("look around".to_string(), &mut session, &mut RecordingHandler::default())
+            .await;
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("4 times in a row"), "{}", err);
+    }
+
+
+    #[tokio::test]
+    async fn test_run_agent_loop_returns_final_text_and_steps() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_text_response("  All done  ");
+
+        let agent = create_test_agent_with_client(mock_client);
+        let run = agent
+            .run_agent_loop("go".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(run.final_text, Some("All done".to_string()));
+        assert_eq!(run.steps, 2);
+        assert!(!run.hit_step_limit);
+    }
+
+    #[tokio::test]
+    async fn test_run_agent_loop_reports_step_limit() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        for _ in 0..5 {
+            mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        }
+
+        let agent = create_test_agent_with_client(mock_client);
+        let run = agent
+            .run_agent_loop("go".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(run.final_text, None);
+        assert_eq!(run.steps, 5);
+        assert!(run.hit_step_limit);
+    }
+
+
+    #[tokio::test]
+    async fn test_pinned_file_is_refreshed_between_turns() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("notes.txt");
+        std::fs::write(&file_path, "version one").unwrap();
+        let path = file_path.to_str().unwrap();
+
+        let mut session = Session::new(None, None);
+        session.add_message(Message {
+            role: "system".to_string(),
+            content: Some("You are a helpful assistant".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+        session.pin(path);
+
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_text_response("first");
+        mock_client.add_text_response("second");
+        let agent = create_test_agent_with_client(mock_client);
+
+        let mut handler = RecordingHandler::default();
+        agent.run_agent_loop("one".to_string(), &mut session, &mut handler).await.unwrap();
+        let pinned = &session.messages[1];
+        assert!(Session::is_pinned_context(pinned));
+        assert!(pinned.content.as_ref().unwrap().contains("version one"));
+
+        std::fs::write(&file_path, "version two").unwrap();
+        agent.run_agent_loop("two".to_string(), &mut session, &mut handler).await.unwrap();
+
+        let pinned: Vec<&Message> = session.messages.iter().filter(|m| Session::is_pinned_context(m)).collect();
+        assert_eq!(pinned.len(), 1);
+        let content = pinned[0].content.as_ref().unwrap();
+        assert!(content.contains("version two"));
+        assert!(!content.contains("version one"));
+
+        session.unpin(path);
+        session.refresh_pinned_context(100);
+        assert!(!session.messages.iter().any(Session::is_pinned_context));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_context_survives_compaction() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("big.txt");
+        let big = "x".repeat(500);
+        std::fs::write(&file_path, &big).unwrap();
+
+        let mut session = Session::new(None, None);
+        session.pin(file_path.to_str().unwrap());
+        session.refresh_pinned_context(10_000);
+
+        let agent = create_test_agent_with_clip(50);
+        agent.compact_history(&mut session);
+
+        let content = session.messages[0].content.as_ref().unwrap();
+        assert!(content.contains(&big));
+        assert!(!content.contains("[truncated]"));
+    }
+
+
+    #[tokio::test]
+    async fn test_write_file_count_limit_blocks_next_write() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let first = temp_dir.path().join("first.txt");
+        let second = temp_dir.path().join("second.txt");
+        std::fs::write(&first, "old").unwrap();
+        std::fs::write(&second, "old").unwrap();
+
+        let mut mock_client = MockLlmClient::new();
+        for path in [&first, &second] {
+            let args = serde_json::json!({
+                "path": path.to_str().unwrap(),
+                "old_str": "old",
+                "new_str": "new",
+            });
+            mock_client.add_tool_call_response("edit_file", &args.to_string());
+        }
+        mock_client.add_text_response("Done");
+        let agent = create_test_agent_with_client(mock_client).with_write_limits(WriteLimits {
+            max_files_written: Some(1),
+            max_write_bytes: None,
+        });
+
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("edit both".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(&second).unwrap(), "old");
+        let observations: Vec<&Message> = session.messages.iter().filter(|m| m.role == "tool").collect();
+        assert!(observations[1].content.as_ref().unwrap().starts_with("Error: write limit reached"));
+    }
+
+    #[tokio::test]
+    async fn test_write_byte_limit() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("f.txt");
+        std::fs::write(&file, "a").unwrap();
+
+        let mut mock_client = MockLlmClient::new();
+        let args = serde_json::json!({
+            "path": file.to_str().unwrap(),
+            "old_str": "a",
+            "new_str": "way too many bytes",
+        });
+        mock_client.add_tool_call_response("edit_file", &args.to_string());
+        mock_client.add_text_response("Done");
+        let agent = create_test_agent_with_client(mock_client).with_write_limits(WriteLimits {
+            max_files_written: None,
+            max_write_bytes: Some(5),
+        });
+
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("edit".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "a");
+    }
+
+
+    #[tokio::test]
+    async fn test_wrap_up_instruction_before_last_step() {
+        let mut mock_client = MockLlmClient::new();
+        for _ in 0..5 {
+            mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        }
+        let history = mock_client.clone();
+        let agent = create_test_agent_with_client(mock_client);
+
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("keep going".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let is_wrap_up = |m: &Message| m.content.as_deref() == Some(wrap_up_instruction(1).as_str());
+        let calls = history.get_call_history();
+        assert_eq!(calls.len(), 5);
+        // Only the final (5th) request carries the instruction, as its last message
+        assert!(!calls[3].iter().any(is_wrap_up));
+        assert!(is_wrap_up(calls[4].last().unwrap()));
+        assert_eq!(session.messages.iter().filter(|m| is_wrap_up(m)).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_wrap_up_threshold_is_configurable() {
+        let mut mock_client = MockLlmClient::new();
+        for _ in 0..5 {
+            mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        }
+        let history = mock_client.clone();
+        let agent = create_test_agent_with_client(mock_client).with_wrap_up_threshold(2);
+
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("keep going".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let calls = history.get_call_history();
+        let expected = wrap_up_instruction(2);
+        assert_eq!(calls[3].last().unwrap().content.as_deref(), Some(expected.as_str()));
+        assert!(!calls[2].iter().any(|m| m.content.as_deref() == Some(expected.as_str())));
+    }
+
+
+    #[tokio::test]
+    async fn test_duplicate_read_only_calls_run_once_per_turn() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("data.txt");
+        std::fs::write(&file_path, "cached contents").unwrap();
+        let args = serde_json::json!({ "path": file_path.to_str().unwrap() }).to_string();
+
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_calls_response(&[("read_file", &args), ("read_file", &args)]);
+        mock_client.add_tool_calls_response(&[("read_file", &args)]);
+        let agent = create_test_agent_with_client(mock_client);
+
+        let mut session = Session::new(None, None);
+        agent.run_turn(&mut session).await.unwrap();
+
+        assert_eq!(agent.tool_cache_misses(), 1);
+        let observations: Vec<&Message> = session.messages.iter().filter(|m| m.role == "tool").collect();
+        assert_eq!(observations.len(), 2);
+        assert_eq!(observations[0].tool_call_id.as_deref(), Some("test-call-1"));
+        assert_eq!(observations[1].tool_call_id.as_deref(), Some("test-call-2"));
+        assert_eq!(observations[0].content, observations[1].content);
+        assert!(observations[0].content.as_ref().unwrap().contains("cached contents"));
+
+        // The next turn starts with an empty cache
+        std::fs::write(&file_path, "fresh contents").unwrap();
+        agent.run_turn(&mut session).await.unwrap();
+        assert_eq!(agent.tool_cache_misses(), 1);
+        assert!(session.messages.last().unwrap().content.as_ref().unwrap().contains("fresh contents"));
+    }
+
+
+    #[tokio::test]
+    async fn test_max_steps_override_caps_the_loop() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        for _ in 0..5 {
+            mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        }
+        let calls = mock_client.clone();
+
+        // The default allows 5 steps; the override wins
+        let mut agent = create_test_agent_with_client(mock_client);
+        agent.set_max_steps(2);
+        let run = agent
+            .run_agent_loop("go".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(run.steps, 2);
+        assert!(run.hit_step_limit);
+        assert_eq!(calls.get_call_history().len(), 2);
+    }
+
+
+    #[tokio::test]
+    async fn test_on_tool_call_fires_with_raw_unparseable_arguments() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("read_file", r#"{"path": "src/main.rs""#);
+        mock_client.add_text_response("Done");
+
+        let agent = create_test_agent_with_client(mock_client);
+        let mut handler = RecordingHandler::default();
+        agent
+            .run_agent_loop("read it".to_string(), &mut session, &mut handler)
+            .await
+            .unwrap();
+
+        // The call is shown with its raw text, then the parse error as its result
+        assert_eq!(handler.args, vec![Value::String(r#"{"path": "src/main.rs""#.to_string())]);
+        let call = handler.events.iter().position(|e| e == "call:read_file").unwrap();
+        let result = handler.events.iter().position(|e| e == "result:read_file").unwrap();
+        assert!(call < result);
+
+        let observation = session
+            .messages
+            .iter()
+            .find(|m| m.role == "tool")
+            .and_then(|m| m.content.clone())
+            .unwrap();
+        assert!(observation.contains("invalid JSON arguments"));
+    }
+
+
+    fn failing_edit_client(times: usize) -> MockLlmClient {
+        let mut mock_client = MockLlmClient::new();
+        for _ in 0..times {
+            mock_client.add_tool_call_response(
+                "edit_file",
+                r#"{"path": "/nonexistent/file.rs", "old_str": "a", "new_str": "b"}"#,
+            );
+        }
+        mock_client
+    }
+
+    #[tokio::test]
+    async fn test_error_breaker_nudges_at_threshold() {
+        let mut session = Session::new(None, None);
+        let agent = create_test_agent_with_client(failing_edit_client(5)).with_error_threshold(3);
+
+        let run = agent
+            .run_agent_loop("fix it".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+        assert!(run.hit_step_limit);
+
+        let nudge = repeated_failure_instruction(&vec!["edit_file".to_string(); 3]);
+        let nudges: Vec<usize> = session
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.content.as_deref() == Some(nudge.as_str()))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(nudges.len(), 1);
+
+        // It comes right after the third failed edit
+        let failed_before = session.messages[..nudges[0]]
+            .iter()
+            .filter(|m| m.role == "tool")
+            .count();
+        assert_eq!(failed_before, 3);
+    }
+
+    #[tokio::test]
+    async fn test_error_breaker_halts_when_failures_continue() {
+        let mut session = Session::new(None, None);
+        let agent = create_test_agent_with_client(failing_edit_client(5)).with_error_threshold(2);
+
+        let err = agent
+            .run_agent_loop("fix it".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("2 tool calls failed in a row again"), "{}", err);
+        assert_eq!(session.messages.iter().filter(|m| m.role == "tool").count(), 4);
+    }
+
+
+    #[tokio::test]
+    async fn test_verify_after_edit_appends_edited_region() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("lib.rs");
+        let lines: Vec<String> = (1..=20).map(|n| format!("let v{} = {};", n, n)).collect();
+        std::fs::write(&file, lines.join("\n")).unwrap();
+        let path = file.to_str().unwrap();
+
+        let edit = serde_json::json!({"path": path, "old_str": "let v10 = 10;", "new_str": "let v10 = 100;"});
+        let mut plain_client = MockLlmClient::new();
+        plain_client.add_tool_call_response("edit_file", &edit.to_string());
+        plain_client.add_text_response("Done");
+
+        // Off by default
+        let mut session = Session::new(None, None);
+        create_test_agent_with_client(plain_client)
+            .run_agent_loop("edit".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+        let observation = session.messages.iter().find(|m| m.role == "tool").unwrap();
+        assert!(!observation.content.as_ref().unwrap().contains("[verify]"));
+
+        let edit = serde_json::json!({"path": path, "old_str": "let v10 = 100;", "new_str": "let v10 = 1000;"});
+        let mut verify_client = MockLlmClient::new();
+        verify_client.add_tool_call_response("edit_file", &edit.to_string());
+        verify_client.add_text_response("Done");
+        let agent = create_test_agent_with_client(verify_client).with_verify_after_edit(true);
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("edit".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let observation = session.messages.iter().find(|m| m.role == "tool").unwrap();
+        let content = observation.content.as_ref().unwrap();
+        assert!(content.starts_with("Successfully edited file"));
+        assert!(content.contains(&format!("[verify] {} lines 7-13 after the change:", path)), "{}", content);
+        assert!(content.contains("10: let v10 = 1000;"));
+        assert!(content.contains("7: let v7 = 7;"));
+        assert!(!content.contains("14: let v14"));
+    }
+
+
+    // Cancels the turn as soon as the first chunk of the reply arrives
+    struct CancelOnContent {
+        cancel: CancellationToken,
+    }
+
+    impl AgentStreamHandler for CancelOnContent {
+        fn on_content(&mut self, _chunk: &str) {
+            self.cancel.cancel();
+        }
+        fn on_tool_call(&mut self, _name: &str, _args: &Value) {}
+        fn on_tool_result(&mut self, _name: &str, _result: &str) {}
+    }
+
+    #[tokio::test]
+    async fn test_cancel_mid_stream_keeps_partial_reply() {
+        let full = "one two three four five";
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.set_chunk_delay(Duration::from_millis(200));
+        mock_client.add_text_response(full);
+        let agent = create_test_agent_with_client(mock_client);
+
+        let cancel = CancellationToken::new();
+        let mut handler = CancelOnContent { cancel: cancel.clone() };
+        let started = std::time::Instant::now();
+        let result = agent
+            .run_agent_loop_cancellable("count".to_string(), &mut session, &mut handler, &cancel)
+            .await
+            .unwrap();
+
+        assert!(result.is_none());
+        assert!(started.elapsed() < Duration::from_millis(800));
+        let last = session.messages.last().unwrap();
+        assert_eq!(last.role, "assistant");
+        let partial = last.content.as_deref().unwrap();
+        assert!(!partial.is_empty());
+        assert!(partial.len() < full.len());
+        assert!(full.starts_with(partial));
+    }
+
+
+    #[tokio::test]
+    async fn test_missing_required_arguments_are_retried() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("out.txt");
+        let path = path.to_str().unwrap();
+
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("write_file", "{}");
+        mock_client.add_tool_call_response(
+            "write_file",
+            &serde_json::json!({"path": path, "content": "hello", "create_only": true}).to_string(),
+        );
+        mock_client.add_text_response("Done");
+
+        let agent = create_test_agent_with_client(mock_client);
+        agent
+            .run_agent_loop("write it".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let observations: Vec<&Message> = session.messages.iter().filter(|m| m.role == "tool").collect();
+        assert_eq!(observations.len(), 2);
+        let first = observations[0].content.as_deref().unwrap();
+        assert!(first.contains("missing required arguments"), "{}", first);
+        assert!(first.contains("path, content"), "{}", first);
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "hello");
+        assert_eq!(session.messages.last().unwrap().content, Some("Done".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_missing_arguments_give_up() {
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        for _ in 0..5 {
+            mock_client.add_tool_call_response("read_file", r#"{"path": ""}"#);
+        }
+
+        let agent = create_test_agent_with_client(mock_client);
+        let err = agent
+            .run_agent_loop("read".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("4 times in a row"), "{}", err);
+    }
+
+
+    #[tokio::test]
+    async fn test_run_shell_cd_carries_over_to_later_calls() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        let cd = serde_json::json!({"command": format!("cd {}/sub", root.display())});
+        mock_client.add_tool_call_response("run_shell", &cd.to_string());
+        mock_client.add_tool_call_response("run_shell", r#"{"command": "pwd"}"#);
+        mock_client.add_text_response("Done");
+
+        let agent = create_test_agent_with_client(mock_client);
+        agent
+            .run_agent_loop("where am I".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let expected = root.join("sub").display().to_string();
+        let observations: Vec<&str> = session
+            .messages
+            .iter()
+            .filter(|m| m.role == "tool")
+            .map(|m| m.content.as_deref().unwrap())
+            .collect();
+        assert_eq!(observations[1].trim(), expected);
+        assert_eq!(session.shell_cwd, Some(expected));
+    }
+
+
+    #[tokio::test]
+    async fn test_failed_tool_call_result_keeps_its_tool_call_id() {
+        let mut session = Session::new(None, None);
+        session.add_message(Message {
+            role: "user".to_string(),
+            content: Some("Read two files".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_calls_response(&[
+            ("list_dir", r#"{"path": "."}"#),
+            ("read_file", r#"{"path": "/nonexistent/termx/missing.rs"}"#),
+        ]);
+        let agent = Agent::new(Box::new(mock_client), ToolRegistry::new(), AgentOptions {
+            max_steps: 5,
+            yolo: true,
+            step_timeout: Duration::from_secs(10),
+            observation_clip: 1000,
+        });
+
+        agent.run_turn(&mut session).await.unwrap();
+
+        let results: Vec<&Message> = session.messages.iter().filter(|m| m.role == "tool").collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].tool_call_id.as_deref(), Some("test-call-1"));
+        assert_eq!(results[1].tool_call_id.as_deref(), Some("test-call-2"));
+        assert_eq!(results[1].name.as_deref(), Some("read_file"));
+        assert!(results[1].content.as_deref().unwrap().starts_with("Error"));
+    }
+
+
+    #[tokio::test]
+    async fn test_skipped_tool_runs_without_approval() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("trusted.txt");
+        let args = serde_json::json!({
+            "path": target.to_str().unwrap(),
+            "content": "ok",
+            "create_only": true,
+        });
+        let mut session = Session::new(None, None);
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("write_file", &args.to_string());
+
+        // Not yolo: without the override this would stop at the approval prompt
+        let agent = Agent::new(Box::new(mock_client), ToolRegistry::new(), AgentOptions {
+            max_steps: 5,
+            yolo: false,
+            step_timeout: Duration::from_secs(10),
+            observation_clip: 1000,
+        })
+        .with_approval_overrides(ApprovalOverrides::from_lists("", "write_file"));
+
+        agent.run_turn(&mut session).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "ok");
+    }
+
+
+    #[tokio::test]
+    async fn test_escape_sequences_kept_raw_in_session() {
+        let raw = "\u{1b}[2Jcleared\u{7}";
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_text_response(raw);
+        let agent = create_test_agent_with_client(mock_client);
+        let mut session = Session::new(None, None);
+
+        let mut handler = RecordingHandler::default();
+        agent
+            .run_agent_loop("hi".to_string(), &mut session, &mut handler)
+            .await
+            .unwrap();
+
+        // Display gets the neutralized text, the model's history the original
+        let shown: String = handler
+            .events
+            .iter()
+            .filter_map(|e| e.strip_prefix("content:"))
+            .map(crate::console::sanitize_for_display)
+            .collect();
+        assert_eq!(shown, "cleared^G");
+        let last = session.messages.last().unwrap();
+        assert_eq!(last.content.as_deref(), Some(raw));
+    }
+
+
+    #[tokio::test]
+    async fn test_large_observation_carries_truncation_header() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("big.txt");
+        let body: String = (1..=150).map(|i| format!("line number {}\n", i)).collect();
+        std::fs::write(&path, body).unwrap();
+
+        let mut mock_client = MockLlmClient::new();
+        let args = serde_json::json!({"path": path.to_str().unwrap()}).to_string();
+        mock_client.add_tool_call_response("read_file", &args);
+        mock_client.add_text_response("Read it");
+        let agent = Agent::new(Box::new(mock_client), ToolRegistry::new(), AgentOptions {
+            max_steps: 5,
+            yolo: true,
+            step_timeout: Duration::from_secs(10),
+            observation_clip: 200,
+        });
+
+        let mut session = Session::new(None, None);
+        let mut handler = RecordingHandler::default();
+        agent
+            .run_agent_loop("read".to_string(), &mut session, &mut handler)
+            .await
+            .unwrap();
+
+        let full = &handler.results[0];
+        let observation = session.messages.iter().find(|m| m.role == "tool").unwrap();
+        let content = observation.content.as_deref().unwrap();
+        let header = format!("[truncated: showing 200 of {} chars]\n", full.chars().count());
+        assert!(full.chars().count() > 200);
+        assert!(content.starts_with(&header));
+        assert_eq!(&content[header.len()..], &full[..200]);
+    }
+
+
+    #[tokio::test]
+    async fn test_context_length_error_compacts_and_retries() {
+        let mut session = Session::new(None, None);
+        session.add_message(Message {
+            role: "system".to_string(),
+            content: Some("You are a helpful assistant".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+        for i in 0..10 {
+            for role in ["user", "assistant"] {
+                session.add_message(Message {
+                    role: role.to_string(),
+                    content: Some(format!("{} message {}", role, i)),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    name: None,
+                    created_at: None,
+                });
+            }
+        }
+
+        let mut mock_client = MockLlmClient::new();
+        mock_client.fail_next(
+            "Provider stream produced no output: This model's maximum context length is 8192 tokens",
+        );
+        mock_client.add_text_response("Answered after compaction");
+        let agent = create_test_agent_with_client(mock_client.clone());
+
+        let result = agent
+            .run_agent_loop("final question".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+        assert_eq!(result.final_text.as_deref(), Some("Answered after compaction"));
+
+        let calls = mock_client.get_call_history();
+        assert_eq!(calls.len(), 2);
+        assert!(calls[1].len() < calls[0].len());
+        // The system prompt and the newest prompt survive; a note replaces the rest
+        let retried = &calls[1];
+        assert_eq!(retried[0].content.as_deref(), Some("You are a helpful assistant"));
+        assert!(retried[1].content.as_deref().unwrap().contains("dropped to fit the context window: 10 messages"));
+        assert_eq!(retried.last().unwrap().content.as_deref(), Some("final question"));
+    }
+
+    #[tokio::test]
+    async fn test_context_length_error_twice_gives_clear_error() {
+        let mut session = Session::new(None, None);
+        for i in 0..4 {
+            session.add_message(Message {
+                role: "user".to_string(),
+                content: Some(format!("message {}", i)),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+                created_at: None,
+            });
+        }
+        let mut mock_client = MockLlmClient::new();
+        mock_client.fail_next("context_length_exceeded");
+        mock_client.fail_next("context_length_exceeded");
+        mock_client.add_text_response("never reached");
+        let agent = create_test_agent_with_client(mock_client);
+
+        let err = agent
+            .run_agent_loop("again".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("still too long for the model's context window"));
+    }
+
+
+    #[tokio::test]
+    async fn test_transcript_logs_turn_in_order() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut session = Session::new(None, None);
+        let path = session.enable_transcript(temp_dir.path()).unwrap();
+
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_text_response("Found \u{1b}[31mthe files\u{1b}[0m");
+        let agent = create_test_agent_with_client(mock_client);
+        agent
+            .run_agent_loop("what is here?".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let prompt = log.find("You:\nwhat is here?").expect("user input logged");
+        let call = log.find(r#"Tool call: list_dir {"path": "."}"#).expect("tool call logged");
+        let result = log.find("Tool result (list_dir):").expect("tool result logged");
+        let answer = log.find("Agent:\nFound the files").expect("answer logged without escapes");
+        assert!(prompt < call && call < result && result < answer);
+        assert!(!log.contains('\u{1b}'));
+    }
+
+
+    #[tokio::test]
+    async fn test_change_log_records_write_and_edit() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let existing = temp_dir.path().join("lib.rs");
+        std::fs::write(&existing, "fn old() {}\n").unwrap();
+        let existing = existing.to_str().unwrap();
+        let created = temp_dir.path().join("new.rs");
+        let created = created.to_str().unwrap();
+
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_calls_response(&[
+            ("write_file", &serde_json::json!({"path": created, "content": "fn new() {}\n", "create_only": true}).to_string()),
+            ("edit_file", &serde_json::json!({"path": existing, "old_str": "old", "new_str": "renamed"}).to_string()),
+            ("edit_file", &serde_json::json!({"path": created, "old_str": "missing", "new_str": "x"}).to_string()),
+        ]);
+        mock_client.add_text_response("Done");
+
+        let agent = create_test_agent_with_client(mock_client);
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("change things".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        // The failed edit of new.rs doesn't turn its creation into an edit
+        assert_eq!(
+            session.changes.files,
+            vec![
+                (created.to_string(), FileChange::Created),
+                (existing.to_string(), FileChange::Edited),
+            ]
+        );
+        let report = format_change_report(&session.changes);
+        assert!(report.contains(&format!("Created (1):\n    {}", created)), "{}", report);
+        assert!(report.contains(&format!("Edited (1):\n    {}", existing)), "{}", report);
+    }
+
+
+    #[tokio::test]
+    async fn test_stringified_arguments_are_coerced_before_dispatch() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        std::fs::write(&file, "one\ntwo\nthree\nfour\n").unwrap();
+        let path = file.to_str().unwrap();
+
+        let args = serde_json::json!({"path": format!("\"{}\"", path), "start_line": "2", "end_line": "3"});
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("read_file", &args.to_string());
+        mock_client.add_text_response("Done");
+
+        let agent = create_test_agent_with_client(mock_client);
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("read it".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        let observation = session.messages.iter().find(|m| m.role == "tool").unwrap();
+        let content = observation.content.as_ref().unwrap();
+        assert!(content.starts_with("2: two\n3: three"), "{}", content);
+        assert!(!content.contains("four"), "{}", content);
+        assert!(content.contains(r#"start_line "2" -> 2"#), "{}", content);
+        assert!(content.contains("[arguments normalized:"), "{}", content);
+    }
+
+
+    #[tokio::test]
+    async fn test_task_complete_ends_run_with_summary() {
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_calls_response(&[
+            ("list_dir", r#"{"path": "."}"#),
+            ("task_complete", r#"{"summary": "Listed the project root."}"#),
+        ]);
+        // Never requested: the run ends at task_complete
+        mock_client.add_text_response("unreachable");
+
+        let agent = create_test_agent_with_client(mock_client);
+        let mut session = Session::new(None, None);
+        let mut handler = RecordingHandler::default();
+        let run = agent
+            .run_agent_loop("look around".to_string(), &mut session, &mut handler)
+            .await
+            .unwrap();
+
+        assert_eq!(run.final_text.as_deref(), Some("Listed the project root."));
+        assert!(run.completed);
+        assert_eq!(run.steps, 1);
+        assert_eq!(handler.events.last().unwrap(), "content:Listed the project root.");
+        // Both calls still get their results
+        assert_eq!(session.messages.iter().filter(|m| m.role == "tool").count(), 2);
+
+        // A plain reply ends the run too, but as waiting for the user
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_text_response("Which file do you mean?");
+        let run = create_test_agent_with_client(mock_client)
+            .run_agent_loop("fix it".to_string(), &mut Session::new(None, None), &mut RecordingHandler::default())
+            .await
+            .unwrap();
+        assert_eq!(run.final_text.as_deref(), Some("Which file do you mean?"));
+        assert!(!run.completed);
+    }
+
+    #[tokio::test]
+    async fn test_write_file_applies_configured_text_format() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        let path = file.to_str().unwrap();
+
+        // The agent adds the final newline by default; the call asks for LF
+        let args = serde_json::json!({
+            "path": path,
+            "content": "one\r\ntwo",
+            "create_only": true,
+            "line_endings": "lf"
+        });
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("write_file", &args.to_string());
+        mock_client.add_text_response("Done");
+
+        let agent = create_test_agent_with_client(mock_client).with_text_format(TextFormat {
+            ensure_trailing_newline: true,
+            ..Default::default()
+        });
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("write it".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "one\ntwo\n");
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_does_not_use_the_write_budget() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let missed = temp_dir.path().join("missed.txt");
+        let target = temp_dir.path().join("target.txt");
+        std::fs::write(&missed, "old").unwrap();
+        std::fs::write(&target, "old").unwrap();
+
+        let mut mock_client = MockLlmClient::new();
+        // The first edit finds nothing to replace, so only the second writes
+        for (path, old_str) in [(&missed, "absent"), (&target, "old")] {
+            let args = serde_json::json!({
+                "path": path.to_str().unwrap(),
+                "old_str": old_str,
+                "new_str": "new",
+            });
+            mock_client.add_tool_call_response("edit_file", &args.to_string());
+        }
+        mock_client.add_text_response("Done");
+        let agent = create_test_agent_with_client(mock_client).with_write_limits(WriteLimits {
+            max_files_written: Some(1),
+            max_write_bytes: None,
+        });
+
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("edit".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(session.write_stats.files.len(), 1);
+        assert!(session.write_stats.files.contains(target.to_str().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_replace_in_files_counts_against_write_limits() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().join("src");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("a.rs"), "old_name()").unwrap();
+        std::fs::write(dir.join("b.rs"), "old_name()").unwrap();
+        let other = temp_dir.path().join("other.txt");
+        std::fs::write(&other, "old").unwrap();
+
+        let mut mock_client = MockLlmClient::new();
+        let replace = serde_json::json!({
+            "pattern": "old_name",
+            "replacement": "new_name",
+            "path": dir.to_str().unwrap(),
+        });
+        mock_client.add_tool_call_response("replace_in_files", &replace.to_string());
+        let edit = serde_json::json!({
+            "path": other.to_str().unwrap(),
+            "old_str": "old",
+            "new_str": "new",
+        });
+        mock_client.add_tool_call_response("edit_file", &edit.to_string());
+        mock_client.add_text_response("Done");
+        let agent = create_test_agent_with_client(mock_client).with_write_limits(WriteLimits {
+            max_files_written: Some(2),
+            max_write_bytes: None,
+        });
+
+        let mut session = Session::new(None, None);
+        agent
+            .run_agent_loop("rename".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+
+        assert_eq!(session.write_stats.files.len(), 2);
+        assert_eq!(session.write_stats.bytes, 2 * "new_name".len());
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "old");
+        let observations: Vec<&Message> = session.messages.iter().filter(|m| m.role == "tool").collect();
+        assert!(observations[1].content.as_ref().unwrap().starts_with("Error: write limit reached"));
+    }
+
+
+    #[tokio::test]
+    async fn test_error_breaker_resets_after_a_success() {
+        let mut mock_client = MockLlmClient::new();
+        let fail = r#"{"path": "/nonexistent/file.rs", "old_str": "a", "new_str": "b"}"#;
+        mock_client.add_tool_call_response("edit_file", fail);
+        mock_client.add_tool_call_response("edit_file", fail);
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_tool_call_response("edit_file", fail);
+        mock_client.add_tool_call_response("edit_file", fail);
+        mock_client.add_text_response("Gave up on the edit");
+        let mut agent = create_test_agent_with_client(mock_client).with_error_threshold(2);
+        agent.set_max_steps(10);
+        let mut session = Session::new(None, None);
+
+        let run = agent
+            .run_agent_loop("fix it".to_string(), &mut session, &mut RecordingHandler::default())
+            .await
+            .unwrap();
+        assert_eq!(run.final_text.as_deref(), Some("Gave up on the edit"));
+
+        let nudge = repeated_failure_instruction(&vec!["edit_file".to_string(); 2]);
+        let nudges = session
+            .messages
+            .iter()
+            .filter(|m| m.content.as_deref() == Some(nudge.as_str()))
+            .count();
+        assert_eq!(nudges, 2);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::changes::{ChangeLog, FileChange, format_change_report};
+use serde_json::json;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_merge_per_file_and_skip_failures() {
+        let mut log = ChangeLog::default();
+        log.record("write_file", &json!({"path": "a.rs"}), "Created a.rs");
+        log.record(
+            "edit_file",
+            &json!({"path": "a.rs"}),
+            "Successfully edited file",
+        );
+        log.record(
+            "write_file",
+            &json!({"path": "b.rs"}),
+            "Successfully wrote to b.rs",
+        );
+        log.record(
+            "delete_file",
+            &json!({"path": "b.rs"}),
+            "Moved b.rs to trash",
+        );
+        log.record("write_file", &json!({"path": "tmp.rs"}), "Created tmp.rs");
+        log.record(
+            "delete_file",
+            &json!({"path": "tmp.rs"}),
+            "Moved tmp.rs to trash",
+        );
+        log.record(
+            "edit_file",
+            &json!({"path": "c.rs"}),
+            "Error: old_str not found",
+        );
+        log.record("run_shell", &json!({"command": "rm -rf /"}), "User denied");
+        log.record("read_file", &json!({"path": "d.rs"}), "1: fn main() {}");
+
+        assert_eq!(
+            log.files,
+            vec![
+                ("a.rs".to_string(), FileChange::Created),
+                ("b.rs".to_string(), FileChange::Deleted),
+            ]
+        );
+        assert!(log.commands.is_empty());
+    }
+
+    #[test]
+    fn test_multi_file_tools_and_commands_are_recorded() {
+        let mut log = ChangeLog::default();
+        log.record(
+            "replace_in_files",
+            &json!({"pattern": "foo", "replacement": "bar"}),
+            "Made 3 replacement(s) in 2 file(s)\nsrc/a.rs: 2 replacement(s)\nsrc/b.rs: 1 replacement(s)",
+        );
+        log.record(
+            "replace_in_files",
+            &json!({"pattern": "foo", "replacement": "bar", "dry_run": true}),
+            "Dry run: would make 1 replacement(s) in 1 file(s)\nsrc/c.rs: 1 replacement(s)",
+        );
+        log.record(
+            "format_code",
+            &json!({}),
+            "Formatted with cargo: 1 file(s) changed\n  - ./src/d.rs",
+        );
+        log.record(
+            "apply_changes",
+            &json!({"changes": [
+                {"op": "edit", "path": "src/a.rs"},
+                {"op": "delete", "path": "old.rs"},
+                {"op": "write", "path": "new.rs"}
+            ]}),
+            "Applied 3 change(s) to 3 file(s):\n  - edit src/a.rs\n  - delete old.rs\n  - create new.rs",
+        );
+        log.record(
+            "run_shell",
+            &json!({"command": "cargo test"}),
+            "exit code: 0",
+        );
+        log.record(
+            "run_shell",
+            &json!({"command": "cargo build"}),
+            "Error: error[E0308]: mismatched types\n[exit code 101]",
+        );
+        log.record(
+            "run_shell",
+            &json!({"command": "sudo ls"}),
+            "Error: Denied command",
+        );
+
+        let report = format_change_report(&log);
+        assert_eq!(
+            report,
+            "Changes this session:\n  Created (1):\n    new.rs\
+             \n  Edited (3):\n    src/a.rs\n    src/b.rs\n    ./src/d.rs\
+             \n  Deleted (1):\n    old.rs\n  Commands run (2):\n    $ cargo test\
+             \n    $ cargo build (exit code 101)"
+        );
+        assert_eq!(
+            format_change_report(&ChangeLog::default()),
+            "Changes this session: none"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use crate::agent::{Agent, AgentOptions};
+use crate::cli::{
+    CliArgs, SessionsCommand, json_report, parse_args, parse_step_count, resolve_prompt,
+    write_json_report,
+};
+use crate::console::SilentStreamHandler;
+use crate::mocks::mock_llm_client::MockLlmClient;
+use crate::session::Session;
+use crate::tool_registry::ToolRegistry;
+use crate::types::{FunctionCall, Message, ToolCall};
+use serde_json::Value;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(args(&[])).unwrap(), CliArgs::default());
+
+        let parsed = parse_args(args(&["-p", "fix the bug", "--json"])).unwrap();
+        assert_eq!(parsed.prompt, Some("fix the bug".to_string()));
+        assert!(parsed.json);
+
+        assert!(parse_args(args(&["--no-color"])).unwrap().no_color);
+        assert!(parse_args(args(&["--json"])).is_err());
+        assert!(parse_args(args(&["--prompt"])).is_err());
+        assert!(parse_args(args(&["--bogus"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_max_steps() {
+        let parsed = parse_args(args(&["-p", "hi", "--max-steps", "3"])).unwrap();
+        assert_eq!(parsed.max_steps, Some(3));
+
+        assert!(parse_args(args(&["--max-steps", "0"])).is_err());
+        assert!(parse_args(args(&["--max-steps", "lots"])).is_err());
+        assert!(parse_args(args(&["--max-steps"])).is_err());
+
+        assert_eq!(parse_step_count(" 20 "), Ok(20));
+        assert!(parse_step_count("-1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_json_report_for_tool_using_run() {
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_text_response("Found the sources");
+        let agent = Agent::new(
+            Box::new(mock_client),
+            ToolRegistry::new(),
+            AgentOptions {
+                max_steps: 5,
+                yolo: true,
+                step_timeout: Duration::from_secs(10),
+                observation_clip: 1000,
+            },
+        );
+
+        let mut session = Session::new(None, None);
+        let result = agent
+            .run_agent_loop(
+                "what is here?".to_string(),
+                &mut session,
+                &mut SilentStreamHandler,
+            )
+            .await
+            .unwrap();
+
+        let emitted = serde_json::to_string(&json_report(&result, &session)).unwrap();
+        let report: Value = serde_json::from_str(&emitted).unwrap();
+
+        assert_eq!(report["final"], "Found the sources");
+        assert_eq!(report["steps"], 2);
+        assert_eq!(report["session_id"], session.id.as_str());
+        assert_eq!(report["usage"]["llm_calls"], 2);
+        let calls = report["tool_calls"].as_array().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0]["name"], "list_dir");
+        assert_eq!(calls[0]["arguments"]["path"], ".");
+        assert!(calls[0]["result"].as_str().unwrap().contains("src"));
+    }
+
+    #[test]
+    fn test_parse_extra_instructions() {
+        let parsed =
+            parse_args(args(&["--extra-instructions", "Answer in British English"])).unwrap();
+        assert_eq!(
+            parsed.extra_instructions.as_deref(),
+            Some("Answer in British English")
+        );
+
+        assert!(parse_args(args(&["--extra-instructions"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_import() {
+        let parsed = parse_args(args(&["--import", "chat.json"])).unwrap();
+        assert_eq!(parsed.import.as_deref(), Some("chat.json"));
+
+        assert!(parse_args(args(&["--import"])).is_err());
+    }
+
+    #[test]
+    fn test_piped_stdin_becomes_one_shot_prompt() {
+        let piped = || Ok("  fix the failing test\n".to_string());
+        assert_eq!(
+            resolve_prompt(None, false, piped),
+            Ok(Some("fix the failing test".to_string()))
+        );
+
+        // A terminal keeps the REPL, and stdin isn't touched
+        let untouched = || -> std::io::Result<String> { panic!("stdin read") };
+        assert_eq!(resolve_prompt(None, true, untouched), Ok(None));
+        // An explicit prompt wins without reading the pipe
+        assert_eq!(
+            resolve_prompt(Some("from flag".to_string()), false, untouched),
+            Ok(Some("from flag".to_string()))
+        );
+
+        let err = resolve_prompt(None, false, || Ok("\n".to_string())).unwrap_err();
+        assert!(err.contains("--prompt"), "{}", err);
+    }
+
+    #[test]
+    fn test_subcommands_map_to_cli_args() {
+        // chat is the REPL: same as no arguments at all
+        assert_eq!(parse_args(args(&["chat"])).unwrap(), CliArgs::default());
+
+        let run = parse_args(args(&["run", "fix the bug", "--json", "--max-steps", "4"])).unwrap();
+        assert_eq!(run.prompt.as_deref(), Some("fix the bug"));
+        assert!(run.json);
+        assert_eq!(run.max_steps, Some(4));
+        assert!(parse_args(args(&["run"])).is_err());
+        assert!(parse_args(args(&["-p", "one", "run", "two"])).is_err());
+
+        let list = parse_args(args(&["sessions", "list"])).unwrap();
+        assert_eq!(list.sessions, Some(SessionsCommand::List));
+        let export = parse_args(args(&["sessions", "export", "ab12", "-o", "out.json"])).unwrap();
+        assert_eq!(
+            export.sessions,
+            Some(SessionsCommand::Export {
+                id: "ab12".to_string(),
+                output: Some("out.json".to_string())
+            })
+        );
+
+        // Resuming starts the REPL (or a one-shot run) from the saved session
+        let resume = parse_args(args(&["sessions", "resume", "ab12"])).unwrap();
+        assert_eq!(resume.resume.as_deref(), Some("ab12"));
+        assert_eq!(resume.sessions, None);
+        let resumed_run = parse_args(args(&["--resume", "ab12", "-p", "continue"])).unwrap();
+        assert_eq!(resumed_run.resume.as_deref(), Some("ab12"));
+        assert_eq!(resumed_run.prompt.as_deref(), Some("continue"));
+        assert!(parse_args(args(&["sessions"])).is_err());
+    }
+
+    #[test]
+    fn test_help_and_version_are_reported_not_parsed() {
+        use clap::error::ErrorKind;
+
+        let help = parse_args(args(&["--help"])).unwrap_err();
+        assert_eq!(help.kind(), ErrorKind::DisplayHelp);
+        for subcommand in ["chat", "run", "sessions"] {
+            assert!(help.to_string().contains(subcommand), "{}", help);
+        }
+
+        let version = parse_args(args(&["--version"])).unwrap_err();
+        assert_eq!(version.kind(), ErrorKind::DisplayVersion);
+        assert!(version.to_string().contains(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[tokio::test]
+    async fn test_json_report_survives_context_shrink() {
+        // A long earlier conversation, including a tool call of its own
+        let mut session = Session::new(None, None);
+        for i in 0..10 {
+            session.add_message(Message {
+                role: "user".to_string(),
+                content: Some(format!("earlier question {}", i)),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+                created_at: None,
+            });
+            session.add_message(Message {
+                role: "assistant".to_string(),
+                content: None,
+                tool_calls: Some(vec![ToolCall {
+                    id: format!("old-{}", i),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "read_file".to_string(),
+                        arguments: r#"{"path": "old.txt"}"#.to_string(),
+                    },
+                }]),
+                tool_call_id: None,
+                name: None,
+                created_at: None,
+            });
+            session.add_message(Message {
+                role: "tool".to_string(),
+                content: Some("old contents".to_string()),
+                tool_calls: None,
+                tool_call_id: Some(format!("old-{}", i)),
+                name: Some("read_file".to_string()),
+                created_at: None,
+            });
+        }
+
+        let mut mock_client = MockLlmClient::new();
+        mock_client.fail_next("This model's maximum context length is 8192 tokens");
+        mock_client.add_tool_call_response("list_dir", r#"{"path": "."}"#);
+        mock_client.add_text_response("Found the sources");
+        let agent = Agent::new(
+            Box::new(mock_client),
+            ToolRegistry::new(),
+            AgentOptions {
+                max_steps: 5,
+                yolo: true,
+                step_timeout: Duration::from_secs(10),
+                observation_clip: 1000,
+            },
+        );
+        let result = agent
+            .run_agent_loop(
+                "what is here?".to_string(),
+                &mut session,
+                &mut SilentStreamHandler,
+            )
+            .await
+            .unwrap();
+
+        let report = json_report(&result, &session);
+        let calls = report["tool_calls"].as_array().unwrap();
+        assert_eq!(calls.len(), 1, "{}", report);
+        assert_eq!(calls[0]["name"], "list_dir");
+        assert!(calls[0]["result"].as_str().unwrap().contains("src"));
+        assert_eq!(report["usage"]["tool_calls"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_json_mode_refuses_gated_tools_and_prints_one_document() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("out.txt");
+        let mut mock_client = MockLlmClient::new();
+        mock_client.add_tool_call_response(
+            "write_file",
+            &serde_json::json!({"path": target.to_str().unwrap(), "content": "hi"}).to_string(),
+        );
+        mock_client.add_text_response("Could not write it");
+        let agent = Agent::new(
+            Box::new(mock_client),
+            ToolRegistry::new(),
+            AgentOptions {
+                max_steps: 5,
+                yolo: false,
+                step_timeout: Duration::from_secs(10),
+                observation_clip: 1000,
+            },
+        )
+        .with_approval_prompts(false);
+        let mut session = Session::new(None, None);
+        let result = agent
+            .run_agent_loop(
+                "write it".to_string(),
+                &mut session,
+                &mut SilentStreamHandler,
+            )
+            .await
+            .unwrap();
+
+        let mut out = Vec::new();
+        write_json_report(&mut out, &result, &session).unwrap();
+        let documents: Vec<Value> = serde_json::Deserializer::from_slice(&out)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(documents.len(), 1);
+        let result = documents[0]["tool_calls"][0]["result"].as_str().unwrap();
+        assert!(result.contains("needs approval"), "{}", result);
+        assert!(!target.exists());
+    }
+}
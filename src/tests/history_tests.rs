@@ -0,0 +1,91 @@
+use crate::history::PromptHistory;
+use std::fs;
+use tempfile::TempDir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_missing_file_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let history = PromptHistory::load(&temp_dir.path().join("history"), 10);
+
+        assert!(history.entries().is_empty());
+    }
+
+    #[test]
+    fn test_history_append_persists_across_loads() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(".termx/history");
+
+        let mut history = PromptHistory::load(&path, 10);
+        history.append("fix the build").unwrap();
+        history.append("add a test").unwrap();
+
+        let reloaded = PromptHistory::load(&path, 10);
+        assert_eq!(reloaded.entries(), ["fix the build", "add a test"]);
+    }
+
+    #[test]
+    fn test_history_dedup_moves_prompt_to_end() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history");
+
+        let mut history = PromptHistory::load(&path, 10);
+        history.append("one").unwrap();
+        history.append("two").unwrap();
+        history.append("one").unwrap();
+
+        assert_eq!(history.entries(), ["two", "one"]);
+        assert_eq!(PromptHistory::load(&path, 10).entries(), ["two", "one"]);
+    }
+
+    #[test]
+    fn test_history_keeps_only_the_newest_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history");
+
+        let mut history = PromptHistory::load(&path, 3);
+        for prompt in ["a", "b", "c", "d", "e", "f", "g"] {
+            history.append(prompt).unwrap();
+        }
+
+        assert_eq!(history.entries(), ["e", "f", "g"]);
+        assert_eq!(PromptHistory::load(&path, 3).entries(), ["e", "f", "g"]);
+        // Compaction kept the file itself bounded
+        assert!(fs::read_to_string(&path).unwrap().lines().count() < 6);
+    }
+
+    #[test]
+    fn test_history_keeps_prompts_from_other_instances() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history");
+
+        let mut first = PromptHistory::load(&path, 10);
+        let mut second = PromptHistory::load(&path, 10);
+        first.append("from first").unwrap();
+        second.append("from second").unwrap();
+
+        assert_eq!(
+            PromptHistory::load(&path, 10).entries(),
+            ["from first", "from second"]
+        );
+    }
+
+    #[test]
+    fn test_history_flattens_multiline_prompts() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history");
+
+        let mut history = PromptHistory::load(&path, 10);
+        history.append("  line one\nline two  ").unwrap();
+        history.append("   ").unwrap();
+
+        assert_eq!(
+            PromptHistory::load(&path, 10).entries(),
+            ["line one line two"]
+        );
+    }
+}
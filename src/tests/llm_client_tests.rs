@@ -0,0 +1,482 @@
+use crate::agent::StreamDelta;
+use crate::llm_client::{
+    DEFAULT_REDACTION_PATTERNS, LlmClient, LlmParams, RequestLimiter, ToolChoice,
+    is_context_length_error, parse_extra_headers,
+};
+use crate::mocks::mock_http_server::{MockHttpServer, MockResponse};
+use crate::types::Message;
+use regex::Regex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPLY: &str = r#"{"choices":[{"message":{"role":"assistant","content":"hi"}}]}"#;
+
+    fn user_message() -> Vec<Message> {
+        vec![Message {
+            role: "user".to_string(),
+            content: Some("hello".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_bearer_auth_by_default() {
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", REPLY)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string()).unwrap();
+
+        client.chat_once_no_stream(&user_message()).await.unwrap();
+
+        let request = server.requests()[0].to_lowercase();
+        assert!(request.contains("authorization: bearer secret"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_auth_header_replaces_bearer() {
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", REPLY)]).await;
+        let mut extra = HashMap::new();
+        extra.insert("x-api-version".to_string(), "2024-06-01".to_string());
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string())
+            .unwrap()
+            .with_auth_header(Some("api-key".to_string()))
+            .with_extra_headers(extra);
+
+        let reply = client.chat_once_no_stream(&user_message()).await.unwrap();
+        assert_eq!(reply.content, Some("hi".to_string()));
+
+        let request = server.requests()[0].to_lowercase();
+        assert!(request.contains("api-key: secret"));
+        assert!(request.contains("x-api-version: 2024-06-01"));
+        assert!(!request.contains("authorization:"));
+    }
+
+    #[tokio::test]
+    async fn test_auth_header_with_prefix() {
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", REPLY)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string())
+            .unwrap()
+            .with_auth_header(Some("Authorization: Token".to_string()));
+
+        client.chat_once_no_stream(&user_message()).await.unwrap();
+
+        let request = server.requests()[0].to_lowercase();
+        assert!(request.contains("authorization: token secret"));
+    }
+
+    #[test]
+    fn test_parse_extra_headers() {
+        let headers =
+            parse_extra_headers("x-api-version=2024-06-01, OpenAI-Organization = org-1,bogus");
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["x-api-version"], "2024-06-01");
+        assert_eq!(headers["OpenAI-Organization"], "org-1");
+    }
+
+    #[tokio::test]
+    async fn test_error_only_stream_is_an_error() {
+        let body = "data: {\"error\":{\"message\":\"rate limit exceeded\"}}\n\ndata: [DONE]\n\n";
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string()).unwrap();
+
+        let err = client
+            .chat_once(&user_message(), &serde_json::json!([]))
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("produced no output"), "{}", err);
+        assert!(err.contains("rate limit exceeded"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_empty_stream_is_an_error() {
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(500, "application/json", "")]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string()).unwrap();
+
+        let err = client
+            .chat_once(&user_message(), &serde_json::json!([]))
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("empty response"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_stream_with_content_succeeds() {
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+        );
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string()).unwrap();
+
+        let mut chunks = Vec::new();
+        let reply = client
+            .chat_once_streaming(&user_message(), &serde_json::json!([]), &mut |d| {
+                if let StreamDelta::Content(c) = d {
+                    chunks.push(c.to_string())
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(reply.content, Some("Hello".to_string()));
+        assert_eq!(chunks, vec!["Hel", "lo"]);
+    }
+
+    #[tokio::test]
+    async fn test_min_request_interval_spaces_calls() {
+        let body = "data: {\"choices\":[{\"delta\":{\"content\":\"ok\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n";
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string())
+            .unwrap()
+            .with_min_request_interval(Some(Duration::from_millis(200)));
+
+        let started = Instant::now();
+        client
+            .chat_once(&user_message(), &serde_json::json!([]))
+            .await
+            .unwrap();
+        client
+            .chat_once(&user_message(), &serde_json::json!([]))
+            .await
+            .unwrap();
+
+        // The second request waited out the interval
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_json_mode_uses_non_stream_request_with_response_format() {
+        let reply = r#"{"choices":[{"message":{"role":"assistant","content":"{\"ok\":true}"}}]}"#;
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", reply)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string())
+            .unwrap()
+            .with_params(LlmParams {
+                json_mode: true,
+                ..Default::default()
+            });
+
+        let mut streamed = String::new();
+        let message = client
+            .chat_once_streaming(&user_message(), &serde_json::json!([]), &mut |d| {
+                if let StreamDelta::Content(c) = d {
+                    streamed.push_str(c)
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(message.content.as_deref(), Some(r#"{"ok":true}"#));
+        assert_eq!(streamed, r#"{"ok":true}"#);
+
+        let request = &server.requests()[0];
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        assert_eq!(
+            body["response_format"],
+            serde_json::json!({"type": "json_object"})
+        );
+        assert_eq!(body["stream"], false);
+    }
+
+    #[tokio::test]
+    async fn test_response_format_omitted_by_default() {
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", REPLY)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string()).unwrap();
+
+        client.chat_once_no_stream(&user_message()).await.unwrap();
+
+        assert!(!server.requests()[0].contains("response_format"));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_key_fails_over_and_cools_down() {
+        let server = MockHttpServer::start(vec![
+            MockResponse::new(
+                429,
+                "application/json",
+                r#"{"error":{"message":"slow down"}}"#,
+            ),
+            MockResponse::new(200, "application/json", REPLY),
+        ])
+        .await;
+        let client =
+            LlmClient::new(server.url(""), "key-a, key-b".to_string(), "m".to_string()).unwrap();
+
+        let reply = client.chat_once_no_stream(&user_message()).await.unwrap();
+        assert_eq!(reply.content.as_deref(), Some("hi"));
+
+        // The second call skips the cooling key entirely
+        client.chat_once_no_stream(&user_message()).await.unwrap();
+
+        let auth: Vec<String> = server
+            .requests()
+            .iter()
+            .map(|r| {
+                r.lines()
+                    .find(|l| l.to_lowercase().starts_with("authorization:"))
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(auth.len(), 3);
+        assert!(auth[0].ends_with("Bearer key-a"));
+        assert!(auth[1].ends_with("Bearer key-b"));
+        assert!(auth[2].ends_with("Bearer key-b"));
+    }
+
+    #[tokio::test]
+    async fn test_single_key_rejection_is_not_retried() {
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(401, "application/json", "{}")]).await;
+        let client = LlmClient::new(server.url(""), "only".to_string(), "m".to_string()).unwrap();
+
+        assert!(client.chat_once_no_stream(&user_message()).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_deltas_are_streamed_separately() {
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"reasoning_content\":\"Let me think\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Answer\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+        );
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string()).unwrap();
+
+        let mut deltas = Vec::new();
+        let reply = client
+            .chat_once_streaming(&user_message(), &serde_json::json!([]), &mut |d| {
+                deltas.push(format!("{:?}", d))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            deltas,
+            vec!["Reasoning(\"Let me think\")", "Content(\"Answer\")"]
+        );
+        assert_eq!(reply.content.as_deref(), Some("Answer"));
+    }
+
+    #[test]
+    fn test_is_context_length_error_matches_provider_wording() {
+        assert!(is_context_length_error(
+            "This model's maximum context length is 128000 tokens. However, your messages resulted in 130512 tokens."
+        ));
+        assert!(is_context_length_error(
+            r#"{"error":{"code":"context_length_exceeded"}}"#
+        ));
+        assert!(is_context_length_error(
+            "prompt is too long: 210000 tokens > 200000 maximum"
+        ));
+        assert!(!is_context_length_error("Rate limit reached for requests"));
+    }
+
+    #[test]
+    fn test_rate_limit_on_tokens_is_not_a_context_length_error() {
+        let body = r#"HTTP 429 Too Many Requests: {"error":{"message":"Too many tokens per minute: limit 30000, requested 41000. Please try again in 20s.","type":"tokens","code":"rate_limit_exceeded"}}"#;
+        assert!(!is_context_length_error(body));
+        assert!(!is_context_length_error(
+            "HTTP 502 Bad Gateway: upstream closed the connection (context window of proxy exhausted)"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_request_limiter_caps_main_and_oracle_requests_together() {
+        let stream = "data: {\"choices\":[{\"delta\":{\"content\":\"ok\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n";
+        let main_server =
+            MockHttpServer::start(vec![MockResponse::new(200, "text/event-stream", stream)]).await;
+        let oracle_server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", REPLY)]).await;
+        let limiter = RequestLimiter::new(2);
+        let main = LlmClient::new(main_server.url(""), "k".to_string(), "m".to_string())
+            .unwrap()
+            .with_request_limiter(limiter.clone());
+        let oracle = LlmClient::new(oracle_server.url(""), "k".to_string(), "m".to_string())
+            .unwrap()
+            .with_request_limiter(limiter.clone());
+
+        let messages = user_message();
+        let tools = serde_json::json!([]);
+        let main_calls =
+            (0..4).map(|_| async { main.chat_once(&messages, &tools).await.map(|_| ()) });
+        let oracle_calls =
+            (0..4).map(|_| async { oracle.chat_once_no_stream(&messages).await.map(|_| ()) });
+        let (main_results, oracle_results) = tokio::join!(
+            futures::future::join_all(main_calls),
+            futures::future::join_all(oracle_calls)
+        );
+
+        assert!(
+            main_results
+                .iter()
+                .chain(&oracle_results)
+                .all(Result::is_ok)
+        );
+        assert_eq!(
+            main_server.requests().len() + oracle_server.requests().len(),
+            8
+        );
+        // Requests overlapped, but never more than the limit at once
+        assert_eq!(limiter.peak(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tool_choice_serializes_into_request_body() {
+        let stream = "data: {\"choices\":[{\"delta\":{\"content\":\"ok\"},\"finish_reason\":null}]}\n\ndata: [DONE]\n\n";
+        let cases = [
+            (None, serde_json::Value::Null),
+            (Some(ToolChoice::Auto), serde_json::json!("auto")),
+            (Some(ToolChoice::None), serde_json::json!("none")),
+            (Some(ToolChoice::Required), serde_json::json!("required")),
+            (
+                Some(ToolChoice::Function("read_file".to_string())),
+                serde_json::json!({"type": "function", "function": {"name": "read_file"}}),
+            ),
+        ];
+        for (choice, expected) in cases {
+            let server =
+                MockHttpServer::start(vec![MockResponse::new(200, "text/event-stream", stream)])
+                    .await;
+            let client = LlmClient::new(server.url(""), "k".to_string(), "m".to_string())
+                .unwrap()
+                .with_params(LlmParams {
+                    tool_choice: choice.clone(),
+                    ..Default::default()
+                });
+            client
+                .chat_once(&user_message(), &serde_json::json!([]))
+                .await
+                .unwrap();
+
+            let request = &server.requests()[0];
+            let body: serde_json::Value =
+                serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+            assert_eq!(body["tool_choice"], expected, "{:?}", choice);
+        }
+    }
+
+    #[test]
+    fn test_tool_choice_parse() {
+        assert_eq!(ToolChoice::parse("none"), Some(ToolChoice::None));
+        assert_eq!(ToolChoice::parse(" required "), Some(ToolChoice::Required));
+        assert_eq!(
+            ToolChoice::parse("run_tests"),
+            Some(ToolChoice::Function("run_tests".to_string()))
+        );
+        assert_eq!(ToolChoice::parse(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_resent_stream_chunks_are_not_doubled() {
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Here is the plan\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Here is the plan\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\": ha\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"ha\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"ha\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"function\":{\"name\":\"read_file\",\"arguments\":\"{\\\"path\\\": \"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"src/main.rs\\\"}\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"src/main.rs\\\"}\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+        );
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "text/event-stream", body)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string()).unwrap();
+
+        let mut streamed = String::new();
+        let reply = client
+            .chat_once_streaming(&user_message(), &serde_json::json!([]), &mut |d| {
+                if let StreamDelta::Content(c) = d {
+                    streamed.push_str(c)
+                }
+            })
+            .await
+            .unwrap();
+
+        // The repeated sentence is dropped; the short repeated token is kept
+        assert_eq!(reply.content.as_deref(), Some("Here is the plan: hahaha"));
+        assert_eq!(streamed, "Here is the plan: hahaha");
+        let calls = reply.tool_calls.unwrap();
+        assert_eq!(calls[0].function.arguments, r#"{"path": "src/main.rs"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_redacted_on_the_wire_only() {
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", REPLY)]).await;
+        let patterns = DEFAULT_REDACTION_PATTERNS
+            .iter()
+            .map(|p| Regex::new(p).unwrap())
+            .collect();
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string())
+            .unwrap()
+            .with_redaction(patterns);
+
+        let secret = "sk-proj-4f9aQ2xT7mLw8RzK1bYc";
+        let mut messages = user_message();
+        messages[0].content = Some(format!("why does OPENAI_API_KEY={} fail?", secret));
+        let before = messages[0].content.clone();
+
+        client.chat_once_no_stream(&messages).await.unwrap();
+
+        let request = &server.requests()[0];
+        assert!(!request.contains(secret), "{}", request);
+        assert!(
+            request.contains("OPENAI_API_KEY=[REDACTED] fail?"),
+            "{}",
+            request
+        );
+        assert_eq!(messages[0].content, before);
+    }
+
+    #[tokio::test]
+    async fn test_json_mode_still_returns_tool_calls() {
+        let reply = r#"{"choices":[{"message":{"role":"assistant","content":null,"tool_calls":[{"id":"c1","type":"function","function":{"name":"read_file","arguments":"{\"path\":\"a\"}"}}]}}]}"#;
+        let server =
+            MockHttpServer::start(vec![MockResponse::new(200, "application/json", reply)]).await;
+        let client = LlmClient::new(server.url(""), "secret".to_string(), "m".to_string())
+            .unwrap()
+            .with_params(LlmParams {
+                json_mode: true,
+                ..Default::default()
+            });
+        let tools = serde_json::json!([{"type": "function", "function": {"name": "read_file"}}]);
+
+        let message = client.chat_once(&user_message(), &tools).await.unwrap();
+
+        let calls = message.tool_calls.expect("tool calls");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "c1");
+        assert_eq!(calls[0].function.name, "read_file");
+        assert_eq!(calls[0].function.arguments, r#"{"path":"a"}"#);
+
+        let request = &server.requests()[0];
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        assert_eq!(body["tools"], tools);
+        assert_eq!(body["stream"], false);
+    }
+}
@@ -1,3 +1,11 @@
 pub mod session_tests;
 pub mod agent_tests;
-pub mod tool_tests;
\ No newline at end of file
+pub mod changes_tests;
+pub mod cli_tests;
+pub mod console_tests;
+pub mod history_tests;
+pub mod style_tests;
+pub mod transcript_tests;
+pub mod llm_client_tests;
+pub mod tool_tests;
+pub mod workspace_tests;
@@ -1,5 +1,9 @@
-use crate::session::Session;
-use crate::types::Message;
+use crate::prompt::{SYSTEM_PROMPT, initial_session};
+use crate::session::{
+    ResultRetention, Session, discard_unfinished, find_saved, find_unfinished, saved_sessions,
+};
+use std::time::Duration;
+use crate::types::{FunctionCall, Message, ToolCall};
 use chrono::Utc;
 
 #[cfg(test)]
@@ -38,6 +42,8 @@ mod tests {
             content: Some("Hello".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         };
         
         session.add_message(message);
@@ -59,6 +65,8 @@ mod tests {
             content: Some("Initial".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Replace all messages
@@ -68,12 +76,16 @@ mod tests {
                 content: Some("System prompt".to_string()),
                 tool_calls: None,
                 tool_call_id: None,
+                name: None,
+                created_at: None,
             },
             Message {
                 role: "user".to_string(),
                 content: Some("New message".to_string()),
                 tool_calls: None,
                 tool_call_id: None,
+                name: None,
+                created_at: None,
             },
         ];
         
@@ -116,6 +128,8 @@ mod tests {
             content: Some("Test message".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            name: None,
+            created_at: None,
         });
         
         // Test that session can be serialized to JSON
@@ -139,6 +153,8 @@ mod tests {
                 content: Some(format!("Message {}", i)),
                 tool_calls: None,
                 tool_call_id: None,
+                name: None,
+                created_at: None,
             });
         }
         
@@ -149,4 +165,433 @@ mod tests {
             assert_eq!(message.content, Some(format!("Message {}", i + 1)));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_close_dangling_tool_calls() {
+        let mut session = Session::new(None, None);
+        session.add_message(Message {
+            role: "assistant".to_string(),
+            content: None,
+            tool_calls: Some(vec![
+                ToolCall {
+                    id: "call-1".to_string(),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "list_dir".to_string(),
+                        arguments: "{}".to_string(),
+                    },
+                },
+                ToolCall {
+                    id: "call-2".to_string(),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "read_file".to_string(),
+                        arguments: "{}".to_string(),
+                    },
+                },
+            ]),
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+        session.add_message(Message {
+            role: "tool".to_string(),
+            content: Some("src".to_string()),
+            tool_calls: None,
+            tool_call_id: Some("call-1".to_string()),
+            name: None,
+            created_at: None,
+        });
+
+        session.close_dangling_tool_calls("Cancelled by user");
+
+        assert_eq!(session.messages.len(), 3);
+        let closed = &session.messages[2];
+        assert_eq!(closed.role, "tool");
+        assert_eq!(closed.tool_call_id, Some("call-2".to_string()));
+        assert_eq!(closed.content, Some("Cancelled by user".to_string()));
+    }
+
+    #[test]
+    fn test_session_save() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut session = Session::new(Some("Saved"), None);
+        session.add_message(Message {
+            role: "user".to_string(),
+            content: Some("Persist me".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+
+        let path = session.save(temp_dir.path()).unwrap();
+
+        assert_eq!(path, temp_dir.path().join(format!("{}.json", session.id)));
+        let loaded: Session = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(loaded.id, session.id);
+        assert_eq!(loaded.messages[0].content, Some("Persist me".to_string()));
+    }
+
+    #[test]
+    fn test_add_message_stamps_created_at() {
+        let mut session = Session::new(None, None);
+        let before = Utc::now();
+
+        session.add_message(Message {
+            role: "user".to_string(),
+            content: Some("Hello".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+
+        let stamped = session.messages[0].created_at.expect("timestamp set");
+        assert!(stamped >= before && stamped <= Utc::now());
+
+        // Round-trips through JSON, and older messages without it still load
+        let json = serde_json::to_string(&session.messages[0]).unwrap();
+        let restored: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.created_at, Some(stamped));
+        let legacy: Message = serde_json::from_str(r#"{"role":"user","content":"old"}"#).unwrap();
+        assert_eq!(legacy.created_at, None);
+    }
+
+    #[test]
+    fn test_wire_messages_strip_created_at() {
+        let mut session = Session::new(None, None);
+        session.add_message(Message {
+            role: "user".to_string(),
+            content: Some("Hello".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+
+        let wire = crate::llm_client::wire_messages(&session.messages);
+
+        assert_eq!(wire[0], serde_json::json!({"role": "user", "content": "Hello"}));
+    }
+
+
+    #[test]
+    fn test_pin_and_unpin() {
+        let mut session = Session::new(None, None);
+        assert!(session.pin("src/main.rs"));
+        assert!(!session.pin("src/main.rs"));
+        assert_eq!(session.pinned_files, vec!["src/main.rs".to_string()]);
+
+        assert!(session.unpin("src/main.rs"));
+        assert!(!session.unpin("src/main.rs"));
+        assert!(session.pinned_files.is_empty());
+    }
+
+
+    fn session_with_tool_result(content: &str) -> Session {
+        let mut session = Session::new(None, None);
+        session.add_message(Message {
+            role: "tool".to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: Some("call-1".to_string()),
+            name: Some("read_file".to_string()),
+            created_at: None,
+        });
+        session
+    }
+
+    fn long_output() -> String {
+        (1..=30)
+            .map(|n| format!("{}: line {}", n, n))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_retain_tool_result_full() {
+        let mut session = session_with_tool_result(&long_output());
+        assert!(session.retain_tool_result("call-1", ResultRetention::Full));
+        assert_eq!(session.messages[0].content, Some(long_output()));
+    }
+
+    #[test]
+    fn test_retain_tool_result_summary() {
+        let mut session = session_with_tool_result(&long_output());
+        assert!(session.retain_tool_result("call-1", ResultRetention::Summary));
+
+        let content = session.messages[0].content.clone().unwrap();
+        assert!(content.starts_with("1: line 1\n"));
+        assert!(content.contains("10: line 10\n"));
+        assert!(!content.contains("11: line 11"));
+        assert!(content.ends_with("[summary: first 10 of 30 lines kept; run the tool again for the rest]"));
+
+        // Short results are left alone
+        let mut short = session_with_tool_result("just one line");
+        short.retain_tool_result("call-1", ResultRetention::Summary);
+        assert_eq!(short.messages[0].content.as_deref(), Some("just one line"));
+    }
+
+    #[test]
+    fn test_retain_tool_result_drop() {
+        let mut session = session_with_tool_result(&long_output());
+        assert!(session.retain_tool_result("call-1", ResultRetention::Drop));
+
+        // The message stays so the tool call is still answered
+        let message = &session.messages[0];
+        assert_eq!(message.tool_call_id.as_deref(), Some("call-1"));
+        let content = message.content.as_deref().unwrap();
+        assert!(content.starts_with("[output dropped to save context"));
+        assert!(!content.contains("line 1"));
+
+        assert!(!session.retain_tool_result("missing", ResultRetention::Drop));
+    }
+
+
+    fn user_message(content: &str) -> Message {
+        Message {
+            role: "user".to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_auto_title_from_first_user_message() {
+        let mut session = Session::new(None, None);
+        session.add_message(Message {
+            role: "system".to_string(),
+            content: Some("You are a coding assistant".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        });
+        session.add_message(user_message("\n  Fix the   failing parser test\nDetails: it panics on empty input"));
+        session.add_message(user_message("second prompt"));
+
+        session.ensure_title();
+        assert_eq!(session.title.as_deref(), Some("Fix the failing parser test"));
+
+        // An explicit title is never replaced
+        session.set_title(Some("Parser work"));
+        session.ensure_title();
+        assert_eq!(session.title.as_deref(), Some("Parser work"));
+    }
+
+    #[test]
+    fn test_auto_title_truncates_at_word_boundary() {
+        let mut session = Session::new(None, None);
+        session.add_message(user_message(
+            "Refactor the session persistence layer so that saving and loading share one code path",
+        ));
+
+        let title = session.derive_title().unwrap();
+        assert_eq!(title, "Refactor the session persistence layer so that…");
+        assert!(title.chars().count() <= 49);
+
+        assert_eq!(Session::new(None, None).derive_title(), None);
+    }
+
+
+    #[test]
+    fn test_initial_session_appends_extra_instructions_last() {
+        let session = initial_session(
+            "test-model",
+            Some("Project scan of .".to_string()),
+            Some("Always answer in haiku."),
+        );
+
+        let system: Vec<&str> = session
+            .messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| m.content.as_deref().unwrap())
+            .collect();
+        assert_eq!(system, [SYSTEM_PROMPT, "Project scan of .", "Always answer in haiku."]);
+        assert_eq!(session.messages.len(), 3);
+    }
+
+    #[test]
+    fn test_initial_session_without_extras() {
+        let session = initial_session("test-model", None, Some("   "));
+
+        assert_eq!(session.messages.len(), 1);
+        assert_eq!(session.messages[0].content.as_deref(), Some(SYSTEM_PROMPT));
+        assert_eq!(session.model.as_deref(), Some("test-model"));
+    }
+
+
+    fn text_message(role: &str, text: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: Some(text.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        }
+    }
+
+    fn saved_message_count(dir: &std::path::Path, session: &Session) -> usize {
+        Session::load(&dir.join(format!("{}.json", session.id)))
+            .unwrap()
+            .messages
+            .len()
+    }
+
+    #[test]
+    fn test_autosave_is_debounced_until_flush() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut session = Session::new(None, None);
+        session.add_message(text_message("system", "be brief"));
+
+        session.enable_autosave(temp_dir.path(), Duration::from_secs(3600)).unwrap();
+        assert_eq!(saved_message_count(temp_dir.path(), &session), 1);
+
+        // Within the interval, changes wait for the flush
+        session.add_message(text_message("user", "hi"));
+        session.add_message(text_message("assistant", "hello"));
+        assert_eq!(saved_message_count(temp_dir.path(), &session), 1);
+
+        session.flush_autosave();
+        assert_eq!(saved_message_count(temp_dir.path(), &session), 3);
+    }
+
+    #[test]
+    fn test_autosave_saves_every_change_without_debounce() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut session = Session::new(None, None);
+        session.enable_autosave(temp_dir.path(), Duration::ZERO).unwrap();
+
+        session.add_message(text_message("user", "hi"));
+        assert_eq!(saved_message_count(temp_dir.path(), &session), 1);
+        session.add_message(text_message("assistant", "hello"));
+        assert_eq!(saved_message_count(temp_dir.path(), &session), 2);
+        // No temp file is left behind by the atomic write
+        assert!(!temp_dir.path().join(format!("{}.json.tmp", session.id)).exists());
+    }
+
+    #[test]
+    fn test_unfinished_session_is_found_after_crash() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let id = {
+            let mut session = Session::new(None, None);
+            session.enable_autosave(temp_dir.path(), Duration::ZERO).unwrap();
+            session.add_message(text_message("user", "refactor the parser"));
+            session.id.clone()
+            // Dropped without finish_autosave, as in a crash
+        };
+        // ...of a process that is gone by now
+        let mut gone = std::process::Command::new("true").spawn().unwrap();
+        gone.wait().unwrap();
+        std::fs::write(temp_dir.path().join(format!("{}.lock", id)), gone.id().to_string()).unwrap();
+
+        let path = find_unfinished(temp_dir.path()).unwrap();
+        let recovered = Session::load(&path).unwrap();
+        assert_eq!(recovered.id, id);
+        assert_eq!(
+            recovered.messages[0].content.as_deref(),
+            Some("refactor the parser")
+        );
+
+        discard_unfinished(&path).unwrap();
+        assert!(find_unfinished(temp_dir.path()).is_none());
+    }
+
+    #[test]
+    fn test_finished_session_is_not_unfinished() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut session = Session::new(None, None);
+        session.enable_autosave(temp_dir.path(), Duration::from_secs(3600)).unwrap();
+        session.add_message(text_message("user", "hi"));
+
+        session.finish_autosave();
+
+        assert!(find_unfinished(temp_dir.path()).is_none());
+        assert_eq!(saved_message_count(temp_dir.path(), &session), 1);
+        assert!(session.autosave.is_none());
+    }
+
+
+    #[test]
+    fn test_drop_oldest_half_keeps_tool_results_with_their_calls() {
+        let message = |role: &str, content: &str| Message {
+            role: role.to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            created_at: None,
+        };
+        let mut session = Session::new(None, None);
+        session.add_message(message("system", "prompt"));
+        session.add_message(message("user", "first"));
+        let mut call = message("assistant", "");
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call-1".to_string(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: "list_dir".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        session.add_message(call);
+        let mut result = message("tool", "a.txt");
+        result.tool_call_id = Some("call-1".to_string());
+        session.add_message(result);
+        session.add_message(message("user", "second"));
+
+        // Half of the 4 non-system messages would cut between call and result
+        assert_eq!(session.drop_oldest_half(), 3);
+        let roles: Vec<&str> = session.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["system", "system", "user"]);
+        assert!(session.messages[1].content.as_deref().unwrap().contains(": 3 messages"));
+
+        // A second drop has nothing left to remove
+        assert_eq!(session.drop_oldest_half(), 0);
+    }
+
+    #[test]
+    fn test_saved_sessions_are_listed_and_found_by_id_prefix() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut older = Session::new(Some("Older"), None);
+        older.id = "aaaa-1111".to_string();
+        older.updated_at = Utc::now() - chrono::Duration::hours(1);
+        let mut newer = Session::new(Some("Newer"), None);
+        newer.id = "aaab-2222".to_string();
+        older.save(temp_dir.path()).unwrap();
+        newer.save(temp_dir.path()).unwrap();
+        std::fs::write(temp_dir.path().join("junk.json"), "not a session").unwrap();
+
+        let listed: Vec<String> = saved_sessions(temp_dir.path())
+            .into_iter()
+            .map(|(_, s)| s.id)
+            .collect();
+        assert_eq!(listed, vec!["aaab-2222", "aaaa-1111"]);
+
+        let dir = temp_dir.path();
+        assert_eq!(find_saved(dir, "aaaa-1111").unwrap(), dir.join("aaaa-1111.json"));
+        assert_eq!(find_saved(dir, "aaab").unwrap(), dir.join("aaab-2222.json"));
+        assert!(find_saved(dir, "aaa").unwrap_err().contains("matches 2"));
+        assert!(find_saved(dir, "zzz").is_err());
+        assert!(find_saved(dir, "").is_err());
+    }
+
+
+    #[test]
+    fn test_session_of_a_running_process_is_not_unfinished() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut session = Session::new(None, None);
+        // The lock names this (live) process, like a second termx would see
+        session.enable_autosave(temp_dir.path(), Duration::ZERO).unwrap();
+        session.add_message(text_message("user", "still working"));
+
+        assert!(find_unfinished(temp_dir.path()).is_none());
+        assert!(temp_dir.path().join(format!("{}.lock", session.id)).exists());
+    }
+}
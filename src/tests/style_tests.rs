@@ -0,0 +1,20 @@
+use crate::style::{GREEN, RED, paint_with};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paint_emits_raw_text_without_color() {
+        assert_eq!(paint_with(false, RED, "error"), "error");
+        assert_eq!(paint_with(false, GREEN, ""), "");
+    }
+
+    #[test]
+    fn test_paint_wraps_text_with_color() {
+        assert_eq!(
+            paint_with(true, RED, "error"),
+            "\u{001b}[31merror\u{001b}[0m"
+        );
+    }
+}
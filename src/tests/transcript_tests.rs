@@ -0,0 +1,114 @@
+use crate::session::Session;
+use crate::transcript::parse_transcript;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPENAI_TRANSCRIPT: &str = r#"[
+        {"role": "system", "content": "You are helpful."},
+        {"role": "user", "content": "What's in Cargo.toml?"},
+        {"role": "assistant", "content": null, "tool_calls": [
+            {"id": "call_1", "type": "function",
+             "function": {"name": "read_file", "arguments": "{\"path\":\"Cargo.toml\"}"}}
+        ]},
+        {"role": "tool", "tool_call_id": "call_1", "content": "[package]\nname = \"demo\""},
+        {"role": "assistant", "content": "It defines the package 'demo'."}
+    ]"#;
+
+    #[test]
+    fn test_openai_transcript_maps_roles_and_tool_calls() {
+        let messages = parse_transcript(OPENAI_TRANSCRIPT).unwrap();
+
+        let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool", "assistant"]);
+        assert_eq!(messages[0].content.as_deref(), Some("You are helpful."));
+        assert_eq!(
+            messages[1].content.as_deref(),
+            Some("What's in Cargo.toml?")
+        );
+
+        assert!(messages[2].content.is_none());
+        let calls = messages[2].tool_calls.as_ref().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].call_type, "function");
+        assert_eq!(calls[0].function.name, "read_file");
+        assert_eq!(calls[0].function.arguments, r#"{"path":"Cargo.toml"}"#);
+
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("call_1"));
+        assert_eq!(messages[3].name.as_deref(), Some("read_file"));
+        assert_eq!(
+            messages[3].content.as_deref(),
+            Some("[package]\nname = \"demo\"")
+        );
+        assert!(messages[4].tool_calls.is_none());
+    }
+
+    #[test]
+    fn test_anthropic_transcript_maps_content_blocks() {
+        let json = r#"{
+            "system": "Be brief.",
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "List src"}]},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "Looking."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "list_dir", "input": {"path": "src"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "main.rs"}
+                ]}
+            ]
+        }"#;
+
+        let messages = parse_transcript(json).unwrap();
+
+        let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool"]);
+        assert_eq!(messages[0].content.as_deref(), Some("Be brief."));
+        assert_eq!(messages[2].content.as_deref(), Some("Looking."));
+        let call = &messages[2].tool_calls.as_ref().unwrap()[0];
+        assert_eq!(call.id, "toolu_1");
+        assert_eq!(call.function.name, "list_dir");
+        assert_eq!(call.function.arguments, r#"{"path":"src"}"#);
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("toolu_1"));
+        assert_eq!(messages[3].name.as_deref(), Some("list_dir"));
+        assert_eq!(messages[3].content.as_deref(), Some("main.rs"));
+    }
+
+    #[test]
+    fn test_unknown_role_falls_back_to_labelled_user_message() {
+        let messages =
+            parse_transcript(r#"[{"role": "critic", "content": "Too verbose"}]"#).unwrap();
+
+        assert_eq!(messages[0].role, "user");
+        assert_eq!(messages[0].content.as_deref(), Some("[critic] Too verbose"));
+    }
+
+    #[test]
+    fn test_malformed_transcripts_are_rejected() {
+        assert!(parse_transcript("not json").is_err());
+        assert!(parse_transcript(r#"{"conversation": []}"#).is_err());
+        assert!(parse_transcript(r#"[{"content": "no role"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_session_import_appends_and_closes_unanswered_calls() {
+        let json = r#"[
+            {"role": "user", "content": "Run the tests"},
+            {"role": "assistant", "tool_calls": [
+                {"id": "call_9", "type": "function",
+                 "function": {"name": "run_tests", "arguments": "{}"}}
+            ]}
+        ]"#;
+        let mut session = Session::new(None, None);
+
+        assert_eq!(session.import_transcript(json).unwrap(), 2);
+
+        // The dangling call gets a stub result so the provider accepts it
+        assert_eq!(session.messages.len(), 3);
+        assert_eq!(session.messages[2].role, "tool");
+        assert_eq!(session.messages[2].tool_call_id.as_deref(), Some("call_9"));
+        assert_eq!(session.title.as_deref(), Some("Run the tests"));
+    }
+}
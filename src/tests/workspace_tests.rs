@@ -0,0 +1,42 @@
+use crate::workspace::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_agent_dir_writes_gitignore() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let dir = init_agent_dir(temp_dir.path()).unwrap();
+
+        assert_eq!(dir, temp_dir.path().join(AGENT_DIR));
+        let gitignore = fs::read_to_string(dir.join(".gitignore")).unwrap();
+        assert_eq!(gitignore, "*\n");
+        for name in [SESSIONS_DIR, UNDO_DIR, TRASH_DIR] {
+            assert!(dir.join(name).is_dir());
+        }
+    }
+
+    #[test]
+    fn test_init_agent_dir_is_idempotent() {
+        let temp_dir = TempDir::new().unwrap();
+        init_agent_dir(temp_dir.path()).unwrap();
+        fs::write(
+            temp_dir.path().join(AGENT_DIR).join(".gitignore"),
+            "custom\n",
+        )
+        .unwrap();
+
+        // Second run must not clobber an existing .gitignore or fail on existing dirs
+        init_agent_dir(temp_dir.path()).unwrap();
+        let subdir = ensure_subdir(temp_dir.path(), SESSIONS_DIR).unwrap();
+
+        let gitignore =
+            fs::read_to_string(temp_dir.path().join(AGENT_DIR).join(".gitignore")).unwrap();
+        assert_eq!(gitignore, "custom\n");
+        assert!(subdir.is_dir());
+    }
+}
@@ -0,0 +1,60 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+// Tools whose result only depends on their arguments and the filesystem
+// state, which doesn't change between reads within one turn
+const CACHEABLE_TOOLS: &[&str] = &[
+    "read_file",
+    "list_dir",
+    "search_in_files",
+    "tree",
+    "glob",
+    "file_stats",
+    "path_exists",
+];
+
+type Key = (String, String);
+
+/// Per-turn memoization of read-only tool results. Identical calls in the
+/// same turn (even concurrent ones) run once and share the observation.
+#[derive(Default)]
+pub struct ToolCache {
+    entries: Mutex<HashMap<Key, Arc<OnceCell<String>>>>,
+    misses: AtomicUsize,
+}
+
+impl ToolCache {
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        self.misses.store(0, Ordering::SeqCst);
+    }
+
+    /// Runs `run` unless an identical cacheable call already did this turn.
+    pub async fn get_or_run<F>(&self, name: &str, args: &Value, run: F) -> String
+    where
+        F: Future<Output = String>,
+    {
+        if !CACHEABLE_TOOLS.contains(&name) {
+            return run.await;
+        }
+        // serde_json maps are sorted, so this is a canonical form
+        let key = (name.to_string(), args.to_string());
+        let cell = self.entries.lock().unwrap().entry(key).or_default().clone();
+        cell.get_or_init(|| async {
+            self.misses.fetch_add(1, Ordering::SeqCst);
+            run.await
+        })
+        .await
+        .clone()
+    }
+
+    // Calls that actually ran since the last clear
+    #[cfg(test)]
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::SeqCst)
+    }
+}
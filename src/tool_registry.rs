@@ -1,5 +1,12 @@
 use serde_json::Value;
 
+/// A configured change to a tool's description.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DescriptionEdit {
+    Replace(String),
+    Append(String),
+}
+
 #[derive(Clone)]
 pub struct ToolRegistry {
     schemas: Value,
@@ -27,6 +34,86 @@ impl ToolRegistry {
                     }
                 }
             },
+            {
+                "type": "function",
+                "function": {
+                    "name": "tree",
+                    "description": "Compact indented overview of a directory tree with relative names. Skips hidden and gitignored files. Use first to get oriented in a project.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "Directory to render (default '.')"
+                            },
+                            "max_depth": {
+                                "type": "number",
+                                "description": "Levels to descend (default 3)"
+                            },
+                            "max_entries": {
+                                "type": "number",
+                                "description": "Entries shown per directory before truncating (default 20)"
+                            }
+                        },
+                        "required": []
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "file_stats",
+                    "description": "Size up a path before reading it: lines, words and bytes of a file, or file count and total bytes of a directory (skipping hidden and gitignored files).",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "File or directory to measure"
+                            }
+                        },
+                        "required": ["path"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "path_exists",
+                    "description": "Check whether a path exists before reading or writing it. Reports 'directory', 'file, N bytes' or 'does not exist'.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "Path to check"
+                            }
+                        },
+                        "required": ["path"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "glob",
+                    "description": "Find files by name pattern, e.g. '**/*_test.rs' or 'src/*.toml'. Returns relative paths, skipping hidden and gitignored files.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "pattern": {
+                                "type": "string",
+                                "description": "Glob matched against paths relative to root; '*' stays within one directory, '**' spans any number"
+                            },
+                            "root": {
+                                "type": "string",
+                                "description": "Directory to search (default '.')"
+                            }
+                        },
+                        "required": ["pattern"]
+                    }
+                }
+            },
             {
                 "type": "function",
                 "function": {
@@ -48,38 +135,176 @@ impl ToolRegistry {
                             "end_line": {
                                 "type": "number",
                                 "description":
-                                    "Ending line (optional, default start+200)"
+                                    "Ending line (optional). Without it the file is read to the end, at most 200 lines (or the configured window) at a time"
+                            },
+                            "strict": {
+                                "type": "boolean",
+                                "description":
+                                    "Fail on any invalid UTF-8 line instead of marking it unreadable, and skip decoding other encodings such as UTF-16 or Latin-1 (default false)"
+                            },
+                            "hexdump": {
+                                "type": "boolean",
+                                "description":
+                                    "Return a hex + ASCII dump instead of text; use for binary files or magic bytes"
+                            },
+                            "start_byte": {
+                                "type": "number",
+                                "description":
+                                    "Hexdump only: byte offset to start at (default 0)"
+                            },
+                            "len": {
+                                "type": "number",
+                                "description":
+                                    "Hexdump only: bytes to dump (default 256, max 4096)"
                             }
                         },
                         "required": ["path"]
                     }
                 }
             },
+            {
+                "type": "function",
+                "function": {
+                    "name": "delete_file",
+                    "description": "Delete a file. By default it is moved to the project trash and can be brought back with restore_file.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "File to delete"
+                            },
+                            "safe_delete": {
+                                "type": "boolean",
+                                "description": "Move to the trash instead of deleting permanently (default true)"
+                            }
+                        },
+                        "required": ["path"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "restore_file",
+                    "description": "Restore a file deleted with delete_file to its original path. Call without an id to list the trash.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "id": {
+                                "type": "string",
+                                "description": "Trash entry to restore, as listed ('<timestamp>/<path>')"
+                            }
+                        },
+                        "required": []
+                    }
+                }
+            },
             {
                 "type": "function",
                 "function": {
                     "name": "run_shell",
                     "description":
                         "Executes a shell command with a 30-second timeout. \
-                         Dangerous commands like rm, sudo, dd are blocked.",
+                         Dangerous commands like rm, sudo, dd are blocked. \
+                         Each call starts a fresh shell, but a leading `cd dir` \
+                         (alone or followed by && or ;) changes the working \
+                         directory for this and all later run_shell calls in the \
+                         session; a bare `cd` returns to the project root.",
                     "parameters": {
                         "type": "object",
                         "properties": {
                             "command": {
                                 "type": "string",
                                 "description": "The shell command to execute"
+                            },
+                            "env": {
+                                "type": "object",
+                                "additionalProperties": { "type": "string" },
+                                "description": "Extra environment variables, e.g. {\"RUST_LOG\": \"debug\"}"
+                            },
+                            "clear_env": {
+                                "type": "boolean",
+                                "description": "Start from an empty environment (only env is set). Default: false"
+                            },
+                            "stdin": {
+                                "type": "string",
+                                "description": "Text written to the command's standard input, which is then closed, e.g. a patch for `git apply`"
                             }
                         },
                         "required": ["command"]
                     }
                 }
             },
+            {
+                "type": "function",
+                "function": {
+                    "name": "cargo_check",
+                    "description":
+                        "Runs `cargo check` in a Rust project and returns its errors \
+                         and warnings, one per line with file:line:column and message. \
+                         Prefer this over run_shell for checking that Rust code compiles.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "all_targets": {
+                                "type": "boolean",
+                                "description":
+                                    "Also check tests, examples and benches (default false)"
+                            }
+                        },
+                        "required": []
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "run_tests",
+                    "description":
+                        "Runs the project's tests and returns a pass/fail summary \
+                         with failing test names. Detects cargo, npm or pytest \
+                         from the project manifest unless a command is given.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "command": {
+                                "type": "string",
+                                "description":
+                                    "Test command to run (optional, auto-detected)"
+                            }
+                        },
+                        "required": []
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "format_code",
+                    "description":
+                        "Formats a file, or the whole project, with the formatter for \
+                         its language (rustfmt/cargo fmt, prettier or black) and lists \
+                         the files that changed.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description":
+                                    "File to format (optional, defaults to the whole project)"
+                            }
+                        },
+                        "required": []
+                    }
+                }
+            },
             {
                 "type": "function",
                 "function": {
                     "name": "write_file",
                     "description":
-                        "Writes content to a file. Creates a file if absent.",
+                        "Writes content to a file. Creates a file if absent; replacing an existing file requires approval as an overwrite.",
                     "parameters": {
                         "type": "object",
                         "properties": {
@@ -91,6 +316,22 @@ impl ToolRegistry {
                                 "type": "string",
                                 "description":
                                     "Content to write into the file"
+                            },
+                            "create_only": {
+                                "type": "boolean",
+                                "description":
+                                    "Fail if the file already exists; use when creating a new file (default false)"
+                            },
+                            "ensure_trailing_newline": {
+                                "type": "boolean",
+                                "description":
+                                    "End the file with exactly one newline if the content lacks one (optional, default from configuration)"
+                            },
+                            "line_endings": {
+                                "type": "string",
+                                "enum": ["keep", "lf", "crlf"],
+                                "description":
+                                    "Convert line endings before writing (optional, default from configuration, usually keep)"
                             }
                         },
                         "required": ["path", "content"]
@@ -121,6 +362,21 @@ impl ToolRegistry {
                                 "type": "boolean",
                                 "description":
                                     "Case-sensitive match (default true)"
+                            },
+                            "word_boundary": {
+                                "type": "boolean",
+                                "description":
+                                    "Match whole words only, e.g. 'get' matches 'get(' but not 'target' (default false)"
+                            },
+                            "min_line_len": {
+                                "type": "number",
+                                "description":
+                                    "Skip matching lines shorter than this many characters after trimming"
+                            },
+                            "multiline": {
+                                "type": "boolean",
+                                "description":
+                                    "Match across lines: each file is searched as a whole, '.' matches newlines and '^'/'$' match at line ends. Use for patterns spanning lines, e.g. a signature broken over several lines (default false)"
                             }
                         },
                         "required": ["pattern", "path"]
@@ -147,12 +403,112 @@ impl ToolRegistry {
                             "new_str": {
                                 "type": "string",
                                 "description": "Replacement string"
+                            },
+                            "occurrence": {
+                                "type": "number",
+                                "description": "Optional 1-based index of the single match to replace when old_str appears more than once. Omit to replace every match."
+                            },
+                            "within_start": {
+                                "type": "number",
+                                "description": "Optional first line (1-based, as numbered by read_file) of the region to edit. Matches outside the region are left alone."
+                            },
+                            "within_end": {
+                                "type": "number",
+                                "description": "Optional last line (inclusive) of the region to edit."
                             }
                         },
                         "required": ["path", "old_str", "new_str"]
                     }
                 }
             },
+            {
+                "type": "function",
+                "function": {
+                    "name": "edit_file_batch",
+                    "description": "Apply several string replacements to one file atomically. Edits run in order; if any old_str is missing, nothing is written.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "Path to the file"
+                            },
+                            "edits": {
+                                "type": "array",
+                                "description": "Replacements to apply in order; each replaces every match of old_str",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "old_str": {
+                                            "type": "string",
+                                            "description": "String to be replaced"
+                                        },
+                                        "new_str": {
+                                            "type": "string",
+                                            "description": "Replacement string"
+                                        }
+                                    },
+                                    "required": ["old_str", "new_str"]
+                                }
+                            }
+                        },
+                        "required": ["path", "edits"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "apply_changes",
+                    "description": "Apply changes to several files as one transaction: they run in order, and if any fails, every file is restored to how it was before. Use for refactors that must land together.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "changes": {
+                                "type": "array",
+                                "description": "Changes to apply in order",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "op": {
+                                            "type": "string",
+                                            "enum": ["write", "edit", "insert", "delete"],
+                                            "description": "write: replace the whole file with content; edit: replace every old_str with new_str; insert: add content before/after line; delete: remove the file"
+                                        },
+                                        "path": {
+                                            "type": "string",
+                                            "description": "File to change"
+                                        },
+                                        "content": {
+                                            "type": "string",
+                                            "description": "write/insert: the text to write or insert"
+                                        },
+                                        "old_str": {
+                                            "type": "string",
+                                            "description": "edit: string to be replaced (must be present)"
+                                        },
+                                        "new_str": {
+                                            "type": "string",
+                                            "description": "edit: replacement string"
+                                        },
+                                        "line": {
+                                            "type": "number",
+                                            "description": "insert: 1-based line number"
+                                        },
+                                        "position": {
+                                            "type": "string",
+                                            "enum": ["before", "after"],
+                                            "description": "insert: before or after the line (default after)"
+                                        }
+                                    },
+                                    "required": ["op", "path"]
+                                }
+                            }
+                        },
+                        "required": ["changes"]
+                    }
+                }
+            },
             {
                 "type": "function",
                 "function": {
@@ -182,12 +538,175 @@ impl ToolRegistry {
                                 "type": "boolean",
                                 "description": "Add newlines around the inserted content. Default: true",
                                 "default": true
+                            },
+                            "fuzzy": {
+                                "type": "boolean",
+                                "description": "If the exact anchor isn't found, retry ignoring indentation and whitespace differences. Default: false"
                             }
                         },
                         "required": ["path", "anchor", "content", "position"]
                     }
                 }
             },
+            {
+                "type": "function",
+                "function": {
+                    "name": "insert_at_line",
+                    "description": "Insert content before or after a 1-based line number (as shown by read_file). Use when the anchor text is not unique.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "The file path to modify"
+                            },
+                            "line": {
+                                "type": "number",
+                                "description": "1-based line number to insert relative to"
+                            },
+                            "content": {
+                                "type": "string",
+                                "description": "The content to insert into the file."
+                            },
+                            "position": {
+                                "type": "string",
+                                "enum": ["before", "after"],
+                                "description": "Whether to insert content before or after the line."
+                            }
+                        },
+                        "required": ["path", "line", "content", "position"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "replace_lines",
+                    "description": "Replace an inclusive 1-based line range (as shown by read_file) with new content. More robust than edit_file for multi-line blocks.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "The file path to modify"
+                            },
+                            "start_line": {
+                                "type": "number",
+                                "description": "First line to replace (1-based)"
+                            },
+                            "end_line": {
+                                "type": "number",
+                                "description": "Last line to replace (inclusive)"
+                            },
+                            "new_content": {
+                                "type": "string",
+                                "description": "Replacement content. Empty string deletes the lines."
+                            }
+                        },
+                        "required": ["path", "start_line", "end_line", "new_content"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "replace_between",
+                    "description": "Replace everything between a begin and an end marker (e.g. '// BEGIN GENERATED' and '// END GENERATED'), keeping the markers. Markers on their own lines keep those lines whole. Re-running with the same content is a no-op.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "The file path to modify"
+                            },
+                            "begin_marker": {
+                                "type": "string",
+                                "description": "Text marking the start of the region (first occurrence)"
+                            },
+                            "end_marker": {
+                                "type": "string",
+                                "description": "Text marking the end of the region (first occurrence after the begin marker)"
+                            },
+                            "new_content": {
+                                "type": "string",
+                                "description": "New content for the region. Empty string clears it."
+                            }
+                        },
+                        "required": ["path", "begin_marker", "end_marker", "new_content"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "replace_in_files",
+                    "description": "Replace a pattern in every matching file under a path (hidden and gitignored files are skipped). Reports per-file replacement counts. Use dry_run to preview.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "pattern": {
+                                "type": "string",
+                                "description": "Text to find (literal unless regex is true)"
+                            },
+                            "replacement": {
+                                "type": "string",
+                                "description": "Replacement text; $1-style groups are expanded in regex mode"
+                            },
+                            "path": {
+                                "type": "string",
+                                "description": "File or directory to process (default '.')"
+                            },
+                            "regex": {
+                                "type": "boolean",
+                                "description": "Treat pattern as a regular expression. Default: false"
+                            },
+                            "dry_run": {
+                                "type": "boolean",
+                                "description": "Only report what would change. Default: false"
+                            }
+                        },
+                        "required": ["pattern", "replacement"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "http_fetch",
+                    "description": "Fetch a public http(s) URL (docs, RFCs, API references) and return its text. HTML is converted to readable text. Private and local addresses are blocked.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "url": {
+                                "type": "string",
+                                "description": "The http or https URL to fetch"
+                            },
+                            "max_bytes": {
+                                "type": "number",
+                                "description": "Maximum bytes to read (optional, default 102400)"
+                            }
+                        },
+                        "required": ["url"]
+                    }
+                }
+            },
+            {
+                "type": "function",
+                "function": {
+                    "name": "task_complete",
+                    "description": "Signal that the user's task is finished. Ends the run and shows the summary to the user. Don't call it to ask a question: reply with plain text instead and the user will answer.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "summary": {
+                                "type": "string",
+                                "description": "What was done and anything the user should know or check"
+                            }
+                        },
+                        "required": ["summary"]
+                    }
+                }
+            },
             {
                 "type": "function",
                 "function": {
@@ -209,7 +728,183 @@ impl ToolRegistry {
         Self { schemas }
     }
 
+    /// Offers the `web_search` tool too (built with the `web-search`
+    /// feature and enabled by configuration).
+    #[cfg(feature = "web-search")]
+    pub fn with_web_search(mut self) -> Self {
+        if let Some(tools) = self.schemas.as_array_mut() {
+            tools.push(serde_json::json!({
+                "type": "function",
+                "function": {
+                    "name": "web_search",
+                    "description": "Search the web for current information (release notes, error messages, library versions). Returns the top results' titles, URLs and snippets; follow up with http_fetch to read a page.",
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "query": {
+                                "type": "string",
+                                "description": "The search query"
+                            },
+                            "max_results": {
+                                "type": "number",
+                                "description": "Number of results (optional, default 5, max 10)"
+                            }
+                        },
+                        "required": ["query"]
+                    }
+                }
+            }));
+        }
+        self
+    }
+
     pub fn schemas(&self) -> &Value {
         &self.schemas
     }
+
+    /// Replaces or extends the description of tool `name`, to steer the
+    /// model for a particular project. Returns false when no tool has that
+    /// name.
+    pub fn set_description(&mut self, name: &str, edit: DescriptionEdit) -> bool {
+        let Some(function) = self
+            .schemas
+            .as_array_mut()
+            .and_then(|tools| tools.iter_mut().find(|t| t["function"]["name"] == name))
+            .map(|t| &mut t["function"])
+        else {
+            return false;
+        };
+        let description = match edit {
+            DescriptionEdit::Replace(text) => text,
+            DescriptionEdit::Append(text) => {
+                let current = function["description"].as_str().unwrap_or("").trim_end();
+                format!("{} {}", current, text).trim_start().to_string()
+            }
+        };
+        function["description"] = Value::String(description);
+        true
+    }
+
+    /// Parameters the schema of tool `name` marks as required; empty for
+    /// unknown tools.
+    pub fn required_params(&self, name: &str) -> Vec<&str> {
+        self.schemas
+            .as_array()
+            .and_then(|tools| tools.iter().find(|t| t["function"]["name"] == name))
+            .and_then(|t| t["function"]["parameters"]["required"].as_array())
+            .map(|req| req.iter().filter_map(|p| p.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Fixes arguments sent with the wrong JSON type for tool `name`'s schema:
+    /// numbers and booleans given as strings, arrays and objects given as
+    /// JSON text, and paths wrapped in an extra pair of quotes. Returns a
+    /// note per argument changed, e.g. `start_line "10" -> 10`.
+    pub fn normalize_args(&self, name: &str, args: &mut Value) -> Vec<String> {
+        let Some(properties) = self
+            .schemas
+            .as_array()
+            .and_then(|tools| tools.iter().find(|t| t["function"]["name"] == name))
+            .and_then(|t| t["function"]["parameters"]["properties"].as_object())
+        else {
+            return Vec::new();
+        };
+        let Some(args) = args.as_object_mut() else {
+            return Vec::new();
+        };
+
+        let mut notes = Vec::new();
+        for (param, value) in args.iter_mut() {
+            let Some(text) = value.as_str() else {
+                continue;
+            };
+            let expected = properties.get(param).and_then(|p| p["type"].as_str());
+            let fixed = match expected {
+                Some("number" | "integer") => parse_number(text.trim()),
+                Some("boolean") => match text.trim().to_ascii_lowercase().as_str() {
+                    "true" => Some(Value::Bool(true)),
+                    "false" => Some(Value::Bool(false)),
+                    _ => None,
+                },
+                Some(kind @ ("array" | "object")) => {
+                    serde_json::from_str::<Value>(text).ok().filter(|v| {
+                        if kind == "array" {
+                            v.is_array()
+                        } else {
+                            v.is_object()
+                        }
+                    })
+                }
+                Some("string") if param == "path" || param.ends_with("_path") => {
+                    unquote(text).map(|inner| Value::String(inner.to_string()))
+                }
+                _ => None,
+            };
+            if let Some(fixed) = fixed {
+                let shown = if text.chars().count() > 40 {
+                    format!("{} text", expected.unwrap_or("JSON"))
+                } else {
+                    fixed.to_string()
+                };
+                notes.push(format!("{} {:?} -> {}", param, text, shown));
+                *value = fixed;
+            }
+        }
+        notes
+    }
+
+    /// Names of the tools offered to the model, in schema order.
+    pub fn names(&self) -> Vec<&str> {
+        self.schemas
+            .as_array()
+            .map(|tools| {
+                tools
+                    .iter()
+                    .filter_map(|t| t["function"]["name"].as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Parses `name=text` entries separated by `;`, as in
+/// `TERMX_TOOL_HINTS="write_file=Prefer edit_file for existing files"`.
+/// Returns the notes, then the entries that aren't `name=text` so the
+/// caller can report them.
+pub fn parse_tool_notes(spec: &str) -> (Vec<(String, String)>, Vec<String>) {
+    let mut notes = Vec::new();
+    let mut malformed = Vec::new();
+    for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=') {
+            Some((name, text)) if !name.trim().is_empty() && !text.trim().is_empty() => {
+                notes.push((name.trim().to_string(), text.trim().to_string()))
+            }
+            _ => malformed.push(entry.to_string()),
+        }
+    }
+    (notes, malformed)
+}
+
+fn parse_number(text: &str) -> Option<Value> {
+    if let Ok(n) = text.parse::<u64>() {
+        return Some(n.into());
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Some(n.into());
+    }
+    text.parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .map(Value::from)
+}
+
+// `"src/main.rs"` or `'src/main.rs'` with the quotes inside the string
+fn unquote(text: &str) -> Option<&str> {
+    let trimmed = text.trim();
+    ['"', '\'', '`'].iter().find_map(|&q| {
+        trimmed
+            .strip_prefix(q)?
+            .strip_suffix(q)
+            .filter(|inner| !inner.is_empty())
+    })
 }
@@ -0,0 +1,165 @@
+use super::ToolError;
+use super::edit_file::edit_file;
+use super::insert_at_line::insert_at_line;
+use super::write_file::write_file;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One file mutation in an `apply_changes` batch.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    /// Create or replace the whole file.
+    Write { path: String, content: String },
+    /// Replace every match of `old_str`.
+    Edit {
+        path: String,
+        old_str: String,
+        new_str: String,
+    },
+    /// Insert before or after a 1-based line (default after).
+    Insert {
+        path: String,
+        line: usize,
+        content: String,
+        #[serde(default = "default_position")]
+        position: String,
+    },
+    /// Remove the file.
+    Delete { path: String },
+}
+
+fn default_position() -> String {
+    "after".to_string()
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Write { path, .. }
+            | Change::Edit { path, .. }
+            | Change::Insert { path, .. }
+            | Change::Delete { path } => path,
+        }
+    }
+
+    // `created`: the file didn't exist before the batch
+    fn describe(&self, created: bool) -> String {
+        let op = match self {
+            Change::Write { .. } if created => "create",
+            Change::Write { .. } => "write",
+            Change::Edit { .. } => "edit",
+            Change::Insert { .. } => "insert",
+            Change::Delete { .. } => "delete",
+        };
+        format!("{} {}", op, self.path())
+    }
+
+    fn apply(&self) -> Result<String, ToolError> {
+        match self {
+            Change::Write { path, content } => write_file(path, content, false),
+            Change::Edit {
+                path,
+                old_str,
+                new_str,
+            } => edit_file(path, old_str, new_str, None, None),
+            Change::Insert {
+                path,
+                line,
+                content,
+                position,
+            } => insert_at_line(path, *line, content, position),
+            Change::Delete { path } => {
+                if !Path::new(path).is_file() {
+                    return Err(ToolError::NotFound(format!("Not a file: {}", path)));
+                }
+                fs::remove_file(path)
+                    .map_err(|e| ToolError::io(format!("Failed to delete {}", path), e))?;
+                Ok(format!("Deleted {}", path))
+            }
+        }
+    }
+}
+
+/// Applies `changes` in order as one transaction: either all of them take
+/// effect, or, when one fails, every file touched so far is put back the
+/// way it was and the error says which change failed.
+pub fn apply_changes(changes: &[Change]) -> Result<String, ToolError> {
+    if changes.is_empty() {
+        return Err(ToolError::InvalidArgument("No changes given".to_string()));
+    }
+
+    // Each file's contents before its first change; None if it didn't exist
+    let mut originals: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+    for (i, change) in changes.iter().enumerate() {
+        let path = PathBuf::from(change.path());
+        if !originals.iter().any(|(p, _)| *p == path) {
+            let before = match fs::read(&path) {
+                Ok(bytes) => Some(bytes),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    let err = ToolError::io(format!("Failed to read {}", path.display()), e);
+                    return Err(rolled_back(err, i, change, &originals));
+                }
+            };
+            originals.push((path, before));
+        }
+        if let Err(err) = change.apply() {
+            return Err(rolled_back(err, i, change, &originals));
+        }
+    }
+
+    let mut summary = format!(
+        "Applied {} change(s) to {} file(s):",
+        changes.len(),
+        originals.len()
+    );
+    for change in changes {
+        let created = originals
+            .iter()
+            .any(|(path, before)| path.as_path() == Path::new(change.path()) && before.is_none());
+        summary.push_str(&format!("\n  - {}", change.describe(created)));
+    }
+    Ok(summary)
+}
+
+// Restore every captured original (newest first) and explain the failure
+fn rolled_back(
+    err: ToolError,
+    index: usize,
+    change: &Change,
+    originals: &[(PathBuf, Option<Vec<u8>>)],
+) -> ToolError {
+    let mut failures = Vec::new();
+    for (path, before) in originals.iter().rev() {
+        let restored = match before {
+            Some(bytes) => fs::write(path, bytes),
+            None if path.exists() => fs::remove_file(path),
+            None => Ok(()),
+        };
+        if let Err(e) = restored {
+            failures.push(format!("{}: {}", path.display(), e));
+        }
+    }
+
+    let mut message = format!(
+        "Change {} ({}) failed: {}. ",
+        index + 1,
+        change.describe(false),
+        err
+    );
+    if failures.is_empty() {
+        message.push_str("All changes were rolled back; no files were modified.");
+    } else {
+        message.push_str(&format!("Rollback failed for: {}", failures.join(", ")));
+    }
+    match err {
+        ToolError::NotFound(_) => ToolError::NotFound(message),
+        ToolError::PermissionDenied(_) => ToolError::PermissionDenied(message),
+        ToolError::InvalidArgument(_) => ToolError::InvalidArgument(message),
+        ToolError::Io(_) => ToolError::Io(message),
+        ToolError::Timeout(_) => ToolError::Timeout(message),
+        ToolError::Blocked(_) => ToolError::Blocked(message),
+    }
+}
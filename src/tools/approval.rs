@@ -1,40 +1,173 @@
+use crate::session::ResultRetention;
+use crate::style::{BRIGHT_RED, GRAY, YELLOW, paint};
+use serde_json::Value;
 use std::io::{self, Write};
 
 /// Categories of tools by risk level
-const DESTRUCTIVE_TOOLS: &[&str] = &["write_file", "run_shell", "edit_file", "insert_in_file"];
+const DESTRUCTIVE_TOOLS: &[&str] = &[
+    "write_file",
+    "run_shell",
+    "edit_file",
+    "edit_file_batch",
+    "insert_in_file",
+    "insert_at_line",
+    "replace_lines",
+    "replace_between",
+    "apply_changes",
+    "run_tests",
+    "cargo_check",
+    "format_code",
+    "http_fetch",
+    "web_search",
+    "replace_in_files",
+    "delete_file",
+    "restore_file",
+];
 // const SAFE_TOOLS: &[&str] = &["list_dir", "read_file"];
 
 /// Get user approval with colored output
 pub fn get_user_approval(prompt: &str) -> Result<bool, String> {
-    print!("\u{001b}[93m⚠️  {} (y/n): \u{001b}[0m", prompt);
+    print!("{}", paint(YELLOW, &format!("⚠️  {} (y/n): ", prompt)));
     io::stdout().flush().unwrap();
 
     let mut input = String::new();
-    io::stdin()
+    let read = io::stdin()
         .read_line(&mut input)
         .map_err(|e| format!("Failed to read input: {}", e))?;
+    // Piped or closed stdin: nobody can answer, so don't re-ask forever
+    if read == 0 {
+        println!();
+        return Err(
+            "no input to answer the approval prompt (stdin is closed); list the tool in \
+             TERMX_SKIP_APPROVAL to run it non-interactively"
+                .to_string(),
+        );
+    }
 
     match input.trim().to_lowercase().as_str() {
         "y" | "yes" => Ok(true),
         "n" | "no" => Ok(false),
         _ => {
-            println!("\u{001b}[91mInvalid input. Please enter 'y' or 'n'\u{001b}[0m");
+            println!(
+                "{}",
+                paint(BRIGHT_RED, "Invalid input. Please enter 'y' or 'n'")
+            );
             get_user_approval(prompt) // Retry
         }
     }
 }
 
+/// Ask what to keep of a large tool result. Enter (or anything
+/// unrecognised) keeps it all.
+pub fn ask_result_retention(tool_name: &str, chars: usize) -> ResultRetention {
+    print!(
+        "{}",
+        paint(
+            GRAY,
+            &format!(
+                "   {} returned {} chars. Keep [f]ull / [s]ummary / [d]rop? [f]: ",
+                tool_name, chars
+            )
+        )
+    );
+    io::stdout().flush().unwrap();
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return ResultRetention::Full;
+    }
+    match input.trim().to_lowercase().as_str() {
+        "s" | "summary" => ResultRetention::Summary,
+        "d" | "drop" => ResultRetention::Drop,
+        _ => ResultRetention::Full,
+    }
+}
+
 /// Format tool call nicely for approval prompt
 pub fn format_tool_approval() -> String {
-    format!(
-        "\n\u{001b}[93m╔════════════════════════════════════╗\n\
-         ║ APPROVAL REQUIRED                  ║\n\
-         ╚════════════════════════════════════╝\u{001b}[0m\n\
-         ",
-    )
+    let banner = "╔════════════════════════════════════╗\n\
+                  ║ APPROVAL REQUIRED                  ║\n\
+                  ╚════════════════════════════════════╝";
+    format!("\n{}\n", paint(YELLOW, banner))
 }
 
 /// Check if a tool requires approval
 pub fn requires_approval(tool_name: &str) -> bool {
     DESTRUCTIVE_TOOLS.contains(&tool_name)
 }
+
+/// User changes to which tools ask before running, layered over the
+/// built-in set (`requires_approval`). A tool in both lists still asks.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ApprovalOverrides {
+    pub require: Vec<String>, // also ask for these
+    pub skip: Vec<String>,    // run these without asking
+}
+
+impl ApprovalOverrides {
+    /// Builds overrides from comma-separated tool names, e.g. the values of
+    /// `TERMX_REQUIRE_APPROVAL` and `TERMX_SKIP_APPROVAL`.
+    pub fn from_lists(require: &str, skip: &str) -> Self {
+        let names = |list: &str| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        Self {
+            require: names(require),
+            skip: names(skip),
+        }
+    }
+
+    pub fn requires_approval(&self, tool_name: &str) -> bool {
+        if self.require.iter().any(|t| t == tool_name) {
+            return true;
+        }
+        if self.skip.iter().any(|t| t == tool_name) {
+            return false;
+        }
+        requires_approval(tool_name)
+    }
+}
+
+/// Question asked in the approval prompt. Replacing an existing file is
+/// called out explicitly rather than hidden behind a generic "Proceed".
+pub fn approval_question(tool_name: &str, args: &Value) -> String {
+    if tool_name == "write_file" && !args["create_only"].as_bool().unwrap_or(false) {
+        let path = args["path"].as_str().unwrap_or("");
+        let content = args["content"].as_str().unwrap_or("");
+        if super::write_file::overwrite_diff(path, content).is_some() {
+            return format!("Overwrite {}", path);
+        }
+    }
+    "Proceed".to_string()
+}
+
+/// Extra context shown in the approval prompt for tools whose impact isn't
+/// obvious from the arguments alone.
+pub fn approval_details(tool_name: &str, args: &Value) -> Option<String> {
+    match tool_name {
+        "write_file" if !args["create_only"].as_bool().unwrap_or(false) => {
+            let path = args["path"].as_str().unwrap_or("");
+            let content = args["content"].as_str().unwrap_or("");
+            let diff = super::write_file::overwrite_diff(path, content)?;
+            Some(format!(
+                "OVERWRITE: {} already exists and will be replaced\n{}",
+                path, diff
+            ))
+        }
+        "replace_in_files" => {
+            let pattern = args["pattern"].as_str().unwrap_or("");
+            let path = args["path"].as_str().unwrap_or(".");
+            let regex = args["regex"].as_bool().unwrap_or(false);
+            let details = match super::replace_in_files::affected_file_count(pattern, path, regex) {
+                Ok(n) => format!("Affects {} file(s) under {}", n, path),
+                Err(e) => format!("Could not count affected files: {}", e),
+            };
+            Some(details)
+        }
+        _ => None,
+    }
+}
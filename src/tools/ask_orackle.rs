@@ -1,16 +1,53 @@
+use super::ToolError;
 use crate::llm_client::LlmClient;
 use crate::types::Message;
+use serde_json::Value;
 use std::env;
+use std::sync::OnceLock;
 
-pub async fn ask_orackle(query: &str) -> Result<String, String> {
+// Oracles that may be running inside one another, unless
+// set_max_oracle_depth says otherwise
+const DEFAULT_MAX_ORACLE_DEPTH: usize = 1;
+
+static MAX_ORACLE_DEPTH: OnceLock<usize> = OnceLock::new();
+
+tokio::task_local! {
+    /// How many `ask_orackle` calls the current task is nested in.
+    pub static ORACLE_DEPTH: usize;
+}
+
+/// Sets how deeply oracles may nest. Call once at startup; later calls are
+/// ignored.
+pub fn set_max_oracle_depth(max: usize) {
+    let _ = MAX_ORACLE_DEPTH.set(max);
+}
+
+/// Asks a separate model for insight into a problem the agent is stuck on.
+/// Refuses when already `max` oracles deep, so a sub-oracle can't spawn
+/// sub-oracles without bound. The depth is task-local: anything an oracle
+/// spawns onto a new task must carry `ORACLE_DEPTH` along.
+pub async fn ask_orackle(query: &str) -> Result<String, ToolError> {
+    let depth = ORACLE_DEPTH.try_with(|depth| *depth).unwrap_or(0);
+    let max = *MAX_ORACLE_DEPTH.get().unwrap_or(&DEFAULT_MAX_ORACLE_DEPTH);
+    if depth >= max {
+        return Err(ToolError::Blocked(
+            "oracle nesting limit reached".to_string(),
+        ));
+    }
+    ORACLE_DEPTH.scope(depth + 1, consult(query)).await
+}
+
+async fn consult(query: &str) -> Result<String, ToolError> {
     let base_url = env::var("OPENAI_BASE_URL").expect("OPENAI_BASE_URL not set");
     let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set");
     let model = env::var("OPENAI_MODEL").unwrap_or_else(|_| "glm-4.5-air".to_string());
 
     // Create LLM client for orackle
     let llm = match LlmClient::new(base_url, api_key, model) {
-        Ok(client) => client,
-        Err(e) => return Err(format!("Failed to create LLM client: {}", e)),
+        Ok(client) => client.with_env_headers().with_env_redaction(),
+        Err(e) => {
+            return Err(ToolError::Io(format!("Failed to create LLM client: {}", e)));
+        }
     };
 
     // Create system message for orackle - it's a specialized agent for providing insights
@@ -33,6 +70,8 @@ pub async fn ask_orackle(query: &str) -> Result<String, String> {
         ),
         tool_calls: None,
         tool_call_id: None,
+        name: None,
+        created_at: None,
     };
 
     let user_message = Message {
@@ -43,85 +82,19 @@ pub async fn ask_orackle(query: &str) -> Result<String, String> {
         )),
         tool_calls: None,
         tool_call_id: None,
+        name: None,
+        created_at: None,
     };
 
     let messages = vec![system_message, user_message];
 
-    // Define available tools for orackle (read-only tools)
-    let _tools = serde_json::json!([
-        {
-            "type": "function",
-            "function": {
-                "name": "read_file",
-                "description": "Read the content of a file to understand the codebase",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Path to the file to read"
-                        },
-                        "start_line": {
-                            "type": "number",
-                            "description": "Starting line number (optional)"
-                        },
-                        "end_line": {
-                            "type": "number",
-                            "description": "Ending line number (optional)"
-                        }
-                    },
-                    "required": ["path"]
-                }
-            }
-        },
-        {
-            "type": "function",
-            "function": {
-                "name": "list_dir",
-                "description": "List the contents of a directory to understand project structure",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Directory path to list"
-                        }
-                    },
-                    "required": ["path"]
-                }
-            }
-        },
-        {
-            "type": "function",
-            "function": {
-                "name": "search_in_files",
-                "description": "Search for patterns in the codebase to understand the problem",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "pattern": {
-                            "type": "string",
-                            "description": "Regular expression pattern to search for"
-                        },
-                        "path": {
-                            "type": "string",
-                            "description": "Path to search in"
-                        },
-                        "case_sensitive": {
-                            "type": "boolean",
-                            "description": "Case sensitive search"
-                        }
-                    },
-                    "required": ["pattern", "path"]
-                }
-            }
-        }
-    ]);
+    // Tools orackle may use once it runs its own tool loop
+    let _tools = oracle_tools();
 
     // Make the LLM call directly (already in async context)
     let response = match llm.chat_once_no_stream(&messages).await {
         Ok(response) => response,
-        Err(e) => return Err(format!("LLM call failed: {}", e)),
+        Err(e) => return Err(ToolError::Io(format!("LLM call failed: {}", e))),
     };
 
     // Extract the content from the response
@@ -224,3 +197,77 @@ pub async fn ask_orackle(query: &str) -> Result<String, String> {
         }
     }
 }
+
+/// Read-only tools offered to orackle. `ask_orackle` is never among them,
+/// so an oracle can't consult another one directly.
+pub fn oracle_tools() -> Value {
+    serde_json::json!([
+        {
+            "type": "function",
+            "function": {
+                "name": "read_file",
+                "description": "Read the content of a file to understand the codebase",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the file to read"
+                        },
+                        "start_line": {
+                            "type": "number",
+                            "description": "Starting line number (optional)"
+                        },
+                        "end_line": {
+                            "type": "number",
+                            "description": "Ending line number (optional)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "list_dir",
+                "description": "List the contents of a directory to understand project structure",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory path to list"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "search_in_files",
+                "description": "Search for patterns in the codebase to understand the problem",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "pattern": {
+                            "type": "string",
+                            "description": "Regular expression pattern to search for"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Path to search in"
+                        },
+                        "case_sensitive": {
+                            "type": "boolean",
+                            "description": "Case sensitive search"
+                        }
+                    },
+                    "required": ["pattern", "path"]
+                }
+            }
+        }
+    ])
+}
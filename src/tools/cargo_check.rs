@@ -0,0 +1,159 @@
+use super::ToolError;
+use super::project::{ProjectKind, detect_project};
+use super::run_shell::run_command;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+const TIMEOUT_SECONDS: u64 = 300;
+const MAX_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+const MAX_LISTED_DIAGNOSTICS: usize = 50;
+
+/// One compiler error or warning from `cargo check`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: String,
+    pub code: Option<String>,
+    /// Primary span as (file, line, column), when the message has one
+    pub location: Option<(String, usize, usize)>,
+    pub message: String,
+}
+
+/// Runs `cargo check` on the project in the current directory and returns
+/// its errors and warnings, one line each with file, line and message.
+///
+/// # Arguments
+///
+/// * `all_targets` - Also check tests, examples and benches.
+///
+pub fn cargo_check(all_targets: bool) -> Result<String, ToolError> {
+    if detect_project(Path::new(".")) != Some(ProjectKind::Cargo) {
+        return Err(ToolError::InvalidArgument(
+            "No Cargo.toml in the current directory; cargo_check only works on Rust projects"
+                .to_string(),
+        ));
+    }
+    let mut command = "cargo check --message-format=json-diagnostic-short".to_string();
+    if all_targets {
+        command.push_str(" --all-targets");
+    }
+
+    let output = run_command(
+        &command,
+        MAX_OUTPUT_BYTES,
+        Duration::from_secs(TIMEOUT_SECONDS),
+        &HashMap::new(),
+        false,
+        None,
+        None,
+    )?;
+
+    let Some(diagnostics) = parse_cargo_diagnostics(&output.stdout) else {
+        // Not JSON (cargo failed before compiling anything): hand back the raw output
+        let raw = format!("{}\n{}", output.stdout, output.stderr);
+        return if output.success {
+            Ok(raw)
+        } else {
+            Err(ToolError::Io(raw))
+        };
+    };
+    Ok(summarize_diagnostics(&diagnostics, output.success))
+}
+
+/// Extracts compiler messages from `cargo --message-format=json` output.
+/// Duplicates (the same message for several targets) and the closing
+/// "aborting due to …" lines are dropped. Returns `None` when the output
+/// holds no cargo JSON at all.
+pub fn parse_cargo_diagnostics(output: &str) -> Option<Vec<Diagnostic>> {
+    let mut saw_json = false;
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for line in output.lines() {
+        let Ok(value) = serde_json::from_str::<Value>(line.trim()) else {
+            continue;
+        };
+        let Some(reason) = value["reason"].as_str() else {
+            continue;
+        };
+        saw_json = true;
+        if reason != "compiler-message" {
+            continue;
+        }
+
+        let message = &value["message"];
+        let level = message["level"].as_str().unwrap_or("unknown");
+        let text = message["message"].as_str().unwrap_or("").to_string();
+        if !matches!(level, "error" | "warning")
+            || text.starts_with("aborting due to")
+            || text.ends_with("warning emitted")
+            || text.ends_with("warnings emitted")
+        {
+            continue;
+        }
+        let location = message["spans"]
+            .as_array()
+            .and_then(|spans| spans.iter().find(|s| s["is_primary"] == true))
+            .map(|span| {
+                (
+                    span["file_name"].as_str().unwrap_or("?").to_string(),
+                    span["line_start"].as_u64().unwrap_or(0) as usize,
+                    span["column_start"].as_u64().unwrap_or(0) as usize,
+                )
+            });
+        let diagnostic = Diagnostic {
+            level: level.to_string(),
+            code: message["code"]["code"].as_str().map(String::from),
+            location,
+            message: text,
+        };
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    saw_json.then_some(diagnostics)
+}
+
+fn summarize_diagnostics(diagnostics: &[Diagnostic], success: bool) -> String {
+    let errors = diagnostics.iter().filter(|d| d.level == "error").count();
+    let warnings = diagnostics.len() - errors;
+    if diagnostics.is_empty() {
+        return if success {
+            "cargo check passed: no errors or warnings".to_string()
+        } else {
+            "cargo check failed without compiler diagnostics (see a build script or dependency)"
+                .to_string()
+        };
+    }
+
+    let mut summary = format!(
+        "cargo check {}: {} error(s), {} warning(s)",
+        if success { "passed" } else { "failed" },
+        errors,
+        warnings
+    );
+    // Errors first, in the order cargo reported them
+    let ordered = diagnostics
+        .iter()
+        .filter(|d| d.level == "error")
+        .chain(diagnostics.iter().filter(|d| d.level != "error"));
+    for d in ordered.take(MAX_LISTED_DIAGNOSTICS) {
+        let code = d
+            .code
+            .as_ref()
+            .map_or(String::new(), |c| format!("[{}]", c));
+        let location = d
+            .location
+            .as_ref()
+            .map_or(String::new(), |(file, line, col)| {
+                format!(" {}:{}:{}", file, line, col)
+            });
+        summary.push_str(&format!("\n{}{}{}: {}", d.level, code, location, d.message));
+    }
+    if diagnostics.len() > MAX_LISTED_DIAGNOSTICS {
+        summary.push_str(&format!(
+            "\n… and {} more",
+            diagnostics.len() - MAX_LISTED_DIAGNOSTICS
+        ));
+    }
+    summary
+}
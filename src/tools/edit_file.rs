@@ -1,5 +1,18 @@
+use super::ToolError;
+use crate::utils::unified_diff;
+use serde::Deserialize;
 use std::fs;
 
+const MAX_DIFF_LINES: usize = 40;
+// Lines of context shown around the closest match of a missing old_str
+const HINT_CONTEXT_LINES: usize = 2;
+// Most lines of file content attached to a not-found error
+const MAX_HINT_LINES: usize = 20;
+// Characters shown per hint line
+const HINT_LINE_CHARS: usize = 200;
+// Below this similarity (0..1) nothing is attached
+const MIN_HINT_SIMILARITY: f64 = 0.5;
+
 /// Edits a file by replacing all occurrences of a string with a new one.
 ///
 /// # Arguments
@@ -7,16 +20,233 @@ use std::fs;
 /// * `path` - The path to the file to edit.
 /// * `old_str` - The string to be replaced.
 /// * `new_str` - The new string to replace with.
+/// * `occurrence` - Replace only the nth match (1-based) instead of all.
+/// * `within` - Inclusive 1-based line range; matches outside it are ignored
+///   and `occurrence` counts from the start of the range.
 ///
-pub fn edit_file(path: &str, old_str: &str, new_str: &str) -> Result<String, String> {
+/// The success message includes a compact unified diff of the change.
+pub fn edit_file(
+    path: &str,
+    old_str: &str,
+    new_str: &str,
+    occurrence: Option<usize>,
+    within: Option<(usize, usize)>,
+) -> Result<String, ToolError> {
     // Read the file's content into a string.
-    let content = fs::read_to_string(&path).map_err(|e| format!("Faield to read File : {}", e))?;
+    let content = fs::read_to_string(path).map_err(|e| ToolError::io("Faield to read File ", e))?;
+
+    // Only the bounded region is searched; the rest is kept as is.
+    let (lo, hi) = match within {
+        Some((start, end)) => line_span(&content, start, end)?,
+        None => (0, content.len()),
+    };
+    let region = &content[lo..hi];
+    if !region.contains(old_str) {
+        let scope = match within {
+            Some((start, end)) => format!("within lines {}-{} of {}", start, end, path),
+            None => format!("in {}", path),
+        };
+        return Err(ToolError::NotFound(format!(
+            "old_str not found {}{}",
+            scope,
+            closest_match_hint(&content, old_str)
+        )));
+    }
 
-    // Replace the old string with the new one.
-    let new_content = content.replace(old_str, new_str);
+    // Replace the old string (or just its nth occurrence) with the new one.
+    let new_region = match occurrence {
+        None => region.replace(old_str, new_str),
+        Some(0) => {
+            return Err(ToolError::InvalidArgument(
+                "occurrence is 1-based; 0 is not valid".to_string(),
+            ));
+        }
+        Some(n) => {
+            let matches: Vec<usize> = region.match_indices(old_str).map(|(i, _)| i).collect();
+            let Some(&start) = matches.get(n - 1) else {
+                return Err(ToolError::InvalidArgument(format!(
+                    "Occurrence {} requested but old_str appears {} time(s) in {}",
+                    n,
+                    matches.len(),
+                    path
+                )));
+            };
+            format!(
+                "{}{}{}",
+                &region[..start],
+                new_str,
+                &region[start + old_str.len()..]
+            )
+        }
+    };
+    let new_content = format!("{}{}{}", &content[..lo], new_region, &content[hi..]);
 
     // Write the modified content back to the file.
-    fs::write(&path, new_content).map_err(|e| format!("Failed to write file: {}", e))?;
+    fs::write(path, &new_content).map_err(|e| ToolError::io("Failed to write file", e))?;
+
+    let diff = unified_diff(&content, &new_content, MAX_DIFF_LINES);
+    if diff.is_empty() {
+        return Ok(format!("Successfully edited file  {}", path));
+    }
+    Ok(format!("Successfully edited file  {}\n{}", path, diff))
+}
+
+// Byte span of lines `start..=end` (1-based), clamping `end` to the file
+fn line_span(content: &str, start: usize, end: usize) -> Result<(usize, usize), ToolError> {
+    if start == 0 || end < start {
+        return Err(ToolError::InvalidArgument(format!(
+            "Invalid line range {}-{}: lines are 1-based and start must not exceed end",
+            start, end
+        )));
+    }
+    let mut offsets = vec![0];
+    offsets.extend(content.match_indices('\n').map(|(i, _)| i + 1));
+    if offsets.last() == Some(&content.len()) && !content.is_empty() {
+        offsets.pop();
+    }
+    let total = offsets.len();
+    if start > total {
+        return Err(ToolError::InvalidArgument(format!(
+            "Line {} is past the end of the file ({} lines)",
+            start, total
+        )));
+    }
+    let hi = offsets.get(end).copied().unwrap_or(content.len());
+    Ok((offsets[start - 1], hi))
+}
+
+// For a not-found error: the lines of `content` most like `old_str`, with a
+// little context and line numbers, so the model can see how its text
+// differs from the file. Empty when nothing is reasonably close.
+fn closest_match_hint(content: &str, old_str: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let wanted: Vec<&str> = old_str.trim_matches('\n').lines().collect();
+    if wanted.is_empty() || lines.len() < wanted.len() {
+        return String::new();
+    }
+
+    let wanted_grams: Vec<Vec<(char, char)>> = wanted.iter().map(|l| bigrams(l)).collect();
+    let line_grams: Vec<Vec<(char, char)>> = lines.iter().map(|l| bigrams(l)).collect();
+    let mut best = (0.0, 0);
+    for start in 0..=lines.len() - wanted.len() {
+        let score = wanted_grams
+            .iter()
+            .zip(&line_grams[start..])
+            .map(|(w, l)| dice(w, l))
+            .sum::<f64>()
+            / wanted.len() as f64;
+        if score > best.0 {
+            best = (score, start);
+        }
+    }
+    let (score, start) = best;
+    if score < MIN_HINT_SIMILARITY {
+        return String::new();
+    }
+
+    let end = start + wanted.len(); // exclusive
+    let from = start.saturating_sub(HINT_CONTEXT_LINES);
+    let to = (end + HINT_CONTEXT_LINES)
+        .min(lines.len())
+        .min(from + MAX_HINT_LINES);
+    let mut hint = format!(
+        ". Closest match is lines {}-{} ({:.0}% similar); the file there reads:",
+        start + 1,
+        end,
+        score * 100.0
+    );
+    for (i, line) in lines.iter().enumerate().take(to).skip(from) {
+        let marker = if (start..end).contains(&i) { '>' } else { ' ' };
+        let text: String = line.chars().take(HINT_LINE_CHARS).collect();
+        hint.push_str(&format!("\n{}{:>5} | {}", marker, i + 1, text));
+    }
+    hint.push_str("\nCopy old_str exactly from the file (whitespace included) and retry.");
+    hint
+}
+
+// Character pairs of a line with whitespace runs collapsed, sorted
+fn bigrams(line: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = line
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect();
+    let mut grams: Vec<(char, char)> = chars.windows(2).map(|w| (w[0], w[1])).collect();
+    grams.sort_unstable();
+    grams
+}
+
+// Dice coefficient of two sorted bigram lists: 1.0 is identical
+fn dice(a: &[(char, char)], b: &[(char, char)]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
+
+/// One replacement in an `edit_file_batch` call.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Edit {
+    pub old_str: String,
+    pub new_str: String,
+}
+
+/// Applies several edits to one file atomically. Edits run in order on the
+/// in-memory content, each replacing every match of its `old_str`; if any
+/// `old_str` is missing the file is left untouched.
+///
+/// The success message reports the match count per edit and a diff.
+pub fn edit_file_batch(path: &str, edits: &[Edit]) -> Result<String, ToolError> {
+    if edits.is_empty() {
+        return Err(ToolError::InvalidArgument("No edits given".to_string()));
+    }
+    let content = fs::read_to_string(path).map_err(|e| ToolError::io("Failed to read file", e))?;
+
+    let mut new_content = content.clone();
+    let mut report = Vec::new();
+    for (i, edit) in edits.iter().enumerate() {
+        if edit.old_str.is_empty() {
+            return Err(ToolError::InvalidArgument(format!(
+                "Edit {}: old_str must not be empty",
+                i + 1
+            )));
+        }
+        let count = new_content.matches(&edit.old_str).count();
+        if count == 0 {
+            return Err(ToolError::NotFound(format!(
+                "Edit {}: old_str not found in {}; no edits were applied{}",
+                i + 1,
+                path,
+                closest_match_hint(&new_content, &edit.old_str)
+            )));
+        }
+        new_content = new_content.replace(&edit.old_str, &edit.new_str);
+        report.push(format!("  edit {}: {} replacement(s)", i + 1, count));
+    }
+
+    fs::write(path, &new_content).map_err(|e| ToolError::io("Failed to write file", e))?;
 
-    Ok(format!("Successfully edited file  {}", path))
+    let diff = unified_diff(&content, &new_content, MAX_DIFF_LINES);
+    Ok(format!(
+        "Applied {} edit(s) to {}\n{}\n{}",
+        edits.len(),
+        path,
+        report.join("\n"),
+        diff
+    )
+    .trim_end()
+    .to_string())
 }
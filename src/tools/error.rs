@@ -0,0 +1,55 @@
+use std::fmt;
+use std::io;
+
+/// Why a tool call failed. `Display` gives just the message, which is what
+/// the agent sends back to the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolError {
+    /// The file, directory or other target doesn't exist.
+    NotFound(String),
+    /// The OS refused access.
+    PermissionDenied(String),
+    /// The call itself is wrong: bad range, empty pattern, file too large, …
+    InvalidArgument(String),
+    /// Any other I/O or transport failure; retrying may help.
+    Io(String),
+    /// The operation ran out of time.
+    Timeout(String),
+    /// Refused by policy (deny list, private address, outside the project).
+    Blocked(String),
+}
+
+impl ToolError {
+    /// Classifies an I/O error by its kind, prefixing `context` to the message.
+    pub fn io(context: impl fmt::Display, e: io::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        match e.kind() {
+            io::ErrorKind::NotFound => ToolError::NotFound(message),
+            io::ErrorKind::PermissionDenied => ToolError::PermissionDenied(message),
+            io::ErrorKind::TimedOut => ToolError::Timeout(message),
+            io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => {
+                ToolError::InvalidArgument(message)
+            }
+            _ => ToolError::Io(message),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ToolError::NotFound(m)
+            | ToolError::PermissionDenied(m)
+            | ToolError::InvalidArgument(m)
+            | ToolError::Io(m)
+            | ToolError::Timeout(m)
+            | ToolError::Blocked(m) => m,
+        }
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for ToolError {}
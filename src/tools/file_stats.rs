@@ -0,0 +1,48 @@
+use super::ToolError;
+use std::fs;
+use std::path::Path;
+
+/// Reports the size of `path` without reading it into context: lines, words
+/// and bytes for a file; file count and total bytes for a directory (hidden
+/// and gitignored files skipped).
+///
+/// # Arguments
+///
+/// * `path` - File or directory to measure.
+///
+pub fn file_stats(path: &str) -> Result<String, ToolError> {
+    let target = Path::new(path);
+    if target.is_file() {
+        let bytes =
+            fs::read(target).map_err(|e| ToolError::io(format!("Failed to read {}", path), e))?;
+        let text = String::from_utf8_lossy(&bytes);
+        // Like `wc -l`, but a last line without a trailing newline counts too
+        let lines = text.lines().count();
+        let words = text.split_whitespace().count();
+        return Ok(format!(
+            "{}: {} lines, {} words, {} bytes",
+            path,
+            lines,
+            words,
+            bytes.len()
+        ));
+    }
+    if !target.is_dir() {
+        return Err(ToolError::NotFound(format!(
+            "No such file or directory: {}",
+            path
+        )));
+    }
+
+    let mut files = 0;
+    let mut total: u64 = 0;
+    for entry in ignore::WalkBuilder::new(target).require_git(false).build() {
+        let entry = entry.map_err(|e| ToolError::Io(format!("walk error: {}", e)))?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        files += 1;
+        total += entry.metadata().map(|m| m.len()).unwrap_or(0);
+    }
+    Ok(format!("{}: {} files, {} bytes total", path, files, total))
+}
@@ -0,0 +1,201 @@
+use super::ToolError;
+use super::project::{ProjectKind, detect_project};
+use super::run_shell::run_command;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const TIMEOUT_SECONDS: u64 = 120;
+const MAX_OUTPUT_BYTES: usize = 256 * 1024;
+const MAX_LISTED_FILES: usize = 20;
+
+/// A formatter invocation: the program and its arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Formatter {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl Formatter {
+    fn new(program: impl Into<String>, args: &[&str]) -> Self {
+        Self {
+            program: program.into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    fn command_line(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Formats `path` (or the whole project when `None`) with the formatter for
+/// its language and lists the files that changed.
+///
+/// # Arguments
+///
+/// * `path` - File to format. When `None` the project in the current
+///   directory is formatted with `cargo fmt`, `prettier` or `black`.
+///
+pub fn format_code(path: Option<&str>) -> Result<String, ToolError> {
+    let root = Path::new(".");
+    let formatter = select_formatter(root, path)?;
+    if !is_available(&formatter.program) {
+        return Err(ToolError::NotFound(format!(
+            "{} is not installed; can't format {}",
+            formatter.program,
+            path.unwrap_or("the project")
+        )));
+    }
+
+    let target = path.map_or(root, Path::new);
+    let before = fingerprint(target);
+    let output = run_command(
+        &formatter.command_line(),
+        MAX_OUTPUT_BYTES,
+        Duration::from_secs(TIMEOUT_SECONDS),
+        &HashMap::new(),
+        false,
+        None,
+        None,
+    )?;
+    if !output.success {
+        return Err(ToolError::Io(format!(
+            "{} failed:\n{}{}",
+            formatter.program, output.stdout, output.stderr
+        )));
+    }
+
+    let after = fingerprint(target);
+    let mut changed: Vec<String> = after
+        .iter()
+        .filter(|(file, hash)| before.get(*file) != Some(hash))
+        .map(|(file, _)| file.display().to_string())
+        .collect();
+    changed.sort();
+
+    if changed.is_empty() {
+        return Ok(format!(
+            "Formatted with {}: already formatted, nothing changed",
+            formatter.program
+        ));
+    }
+    let mut summary = format!(
+        "Formatted with {}: {} file(s) changed",
+        formatter.program,
+        changed.len()
+    );
+    for file in changed.iter().take(MAX_LISTED_FILES) {
+        summary.push_str(&format!("\n  - {}", file));
+    }
+    if changed.len() > MAX_LISTED_FILES {
+        summary.push_str(&format!(
+            "\n  … and {} more",
+            changed.len() - MAX_LISTED_FILES
+        ));
+    }
+    Ok(summary)
+}
+
+/// Picks the formatter for `path` by its extension, or for the project at
+/// `root` by its manifest when no path is given.
+pub fn select_formatter(root: &Path, path: Option<&str>) -> Result<Formatter, ToolError> {
+    let Some(path) = path else {
+        return match detect_project(root) {
+            Some(ProjectKind::Cargo) => Ok(Formatter::new("cargo", &["fmt"])),
+            Some(ProjectKind::Npm) => Ok(Formatter::new(prettier(root), &["--write", "."])),
+            Some(ProjectKind::Python) => Ok(Formatter::new("black", &["."])),
+            None => Err(ToolError::InvalidArgument(
+                "Could not detect project type; pass the path of a file to format".to_string(),
+            )),
+        };
+    };
+
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    match extension {
+        "rs" => {
+            let edition = format!("--edition={}", cargo_edition(root));
+            Ok(Formatter::new("rustfmt", &[&edition, path]))
+        }
+        "py" | "pyi" => Ok(Formatter::new("black", &[path])),
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "json" | "css" | "scss" | "html" | "md"
+        | "yaml" | "yml" => Ok(Formatter::new(prettier(root), &["--write", path])),
+        _ => Err(ToolError::InvalidArgument(format!(
+            "No formatter known for {}",
+            path
+        ))),
+    }
+}
+
+// The project's own prettier when installed, else one on PATH
+fn prettier(root: &Path) -> String {
+    let local = root.join("node_modules").join(".bin").join("prettier");
+    if local.is_file() {
+        local.to_string_lossy().into_owned()
+    } else {
+        "prettier".to_string()
+    }
+}
+
+// rustfmt defaults to the 2015 edition, so pass the crate's
+fn cargo_edition(root: &Path) -> String {
+    fs::read_to_string(root.join("Cargo.toml"))
+        .ok()
+        .and_then(|manifest| {
+            manifest.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("edition")?.trim_start();
+                let value = value.strip_prefix('=')?.trim();
+                Some(value.trim_matches(|c| c == '"' || c == '\'').to_string())
+            })
+        })
+        .unwrap_or_else(|| "2021".to_string())
+}
+
+fn is_available(program: &str) -> bool {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        return program.is_file();
+    }
+    let Some(paths) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&paths).any(|dir| {
+        let candidate = dir.join(program);
+        candidate.is_file() || candidate.with_extension("exe").is_file()
+    })
+}
+
+// Content hash of every non-ignored file under `target`
+fn fingerprint(target: &Path) -> HashMap<PathBuf, u64> {
+    ignore::WalkBuilder::new(target)
+        .require_git(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|entry| {
+            let bytes = fs::read(entry.path()).ok()?;
+            let mut hasher = DefaultHasher::new();
+            bytes.hash(&mut hasher);
+            Some((entry.into_path(), hasher.finish()))
+        })
+        .collect()
+}
+
+fn quote(arg: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("\"{}\"", arg)
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
@@ -0,0 +1,49 @@
+use super::ToolError;
+use globset::GlobBuilder;
+use std::path::Path;
+
+const MAX_RESULTS: usize = 500;
+
+/// Returns paths under `root` (relative to it, sorted) whose relative path
+/// matches the glob `pattern`, e.g. `**/*_test.rs`. Hidden and gitignored
+/// files are skipped. At most 500 paths are returned; when there are more,
+/// the last entry is a "… (N more)" note.
+///
+/// # Arguments
+///
+/// * `pattern` - Glob matched against the path relative to `root`; `*` does not cross `/`.
+/// * `root` - Directory to search (default ".").
+///
+pub fn glob(pattern: &str, root: Option<&str>) -> Result<Vec<String>, ToolError> {
+    let root = root.unwrap_or(".");
+    if !Path::new(root).is_dir() {
+        return Err(ToolError::NotFound(format!("Not a directory: {}", root)));
+    }
+    let matcher = GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| ToolError::InvalidArgument(format!("Invalid glob '{}': {}", pattern, e)))?
+        .compile_matcher();
+
+    let mut matches = Vec::new();
+    for entry in ignore::WalkBuilder::new(root).require_git(false).build() {
+        let entry = entry.map_err(|e| ToolError::Io(format!("walk error: {}", e)))?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        if matcher.is_match(rel) {
+            matches.push(rel.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    matches.sort();
+
+    if matches.len() > MAX_RESULTS {
+        let more = matches.len() - MAX_RESULTS;
+        matches.truncate(MAX_RESULTS);
+        matches.push(format!("… ({} more)", more));
+    }
+    Ok(matches)
+}
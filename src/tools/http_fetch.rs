@@ -0,0 +1,226 @@
+use super::ToolError;
+use regex::Regex;
+use reqwest::Url;
+use std::net::{IpAddr, SocketAddr};
+use tokio::time::Duration;
+
+const DEFAULT_MAX_BYTES: usize = 100 * 1024;
+const TIMEOUT_SECONDS: u64 = 20;
+const MAX_REDIRECTS: usize = 5;
+
+/// Fetches an http(s) URL and returns its body as text, with HTML reduced to
+/// readable text. Private, loopback and link-local hosts are refused.
+///
+/// # Arguments
+///
+/// * `url` - The http or https URL to GET.
+/// * `max_bytes` - Maximum body bytes to read (default 100KB).
+///
+pub async fn http_fetch(url: &str, max_bytes: Option<usize>) -> Result<String, ToolError> {
+    fetch_url(url, max_bytes, false).await
+}
+
+/// `http_fetch` with the private-address guard optionally disabled.
+pub async fn fetch_url(
+    url: &str,
+    max_bytes: Option<usize>,
+    allow_private: bool,
+) -> Result<String, ToolError> {
+    let max_bytes = max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+    let mut target =
+        Url::parse(url).map_err(|e| ToolError::InvalidArgument(format!("Invalid URL: {}", e)))?;
+
+    // Redirects are followed by hand so every hop passes the same checks
+    let mut redirects = 0;
+    let mut resp = loop {
+        let addrs = check_url(&target, allow_private).await?;
+
+        let resp = client_for(&target, &addrs)?
+            .get(target.clone())
+            .send()
+            .await
+            .map_err(|e| request_error("Request failed", e))?;
+
+        if !resp.status().is_redirection() {
+            break resp;
+        }
+        redirects += 1;
+        if redirects > MAX_REDIRECTS {
+            return Err(ToolError::Io("Too many redirects".to_string()));
+        }
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or(ToolError::Io(
+                "Redirect without a Location header".to_string(),
+            ))?;
+        target = target
+            .join(location)
+            .map_err(|e| ToolError::Io(format!("Invalid redirect URL: {}", e)))?;
+    };
+
+    if !resp.status().is_success() {
+        let message = format!("HTTP {} for {}", resp.status(), target);
+        return Err(match resp.status() {
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE => {
+                ToolError::NotFound(message)
+            }
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                ToolError::PermissionDenied(message)
+            }
+            _ => ToolError::Io(message),
+        });
+    }
+
+    let is_html = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("html"));
+
+    // Read incrementally so a huge body never lands in memory
+    let mut body: Vec<u8> = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|e| request_error("Failed to read body", e))?
+    {
+        let remaining = max_bytes - body.len();
+        if chunk.len() > remaining {
+            body.extend_from_slice(&chunk[..remaining]);
+            truncated = true;
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let text = String::from_utf8_lossy(&body);
+    let mut text = if is_html {
+        html_to_text(&text)
+    } else {
+        text.to_string()
+    };
+    if truncated {
+        text.push_str(&format!("\n… [truncated at {} bytes]", max_bytes));
+    }
+    Ok(text)
+}
+
+// A client that connects only to `addrs`, the addresses `check_url` vetted,
+// so a second DNS answer (rebinding) or a proxy from the environment can't
+// take the request somewhere else
+fn client_for(url: &Url, addrs: &[SocketAddr]) -> Result<reqwest::Client, ToolError> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECONDS))
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy();
+    if let Some(domain) = url.domain()
+        && !addrs.is_empty()
+    {
+        builder = builder.resolve_to_addrs(domain, addrs);
+    }
+    builder
+        .build()
+        .map_err(|e| request_error("Failed to build HTTP client", e))
+}
+
+// Checks the scheme and, unless `allow_private`, that the host resolves only
+// to public addresses, which are returned for the request to connect to
+async fn check_url(url: &Url, allow_private: bool) -> Result<Vec<SocketAddr>, ToolError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ToolError::InvalidArgument(format!(
+            "Unsupported URL scheme '{}': only http and https are allowed",
+            url.scheme()
+        )));
+    }
+    if allow_private {
+        return Ok(Vec::new());
+    }
+
+    let host = url
+        .host_str()
+        .ok_or(ToolError::InvalidArgument("URL has no host".to_string()))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.eq_ignore_ascii_case("localhost") || host.ends_with(".localhost") {
+        return Err(ToolError::Blocked(format!(
+            "Refusing to fetch private address '{}'",
+            host
+        )));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| ToolError::io(format!("Failed to resolve '{}'", host), e))?
+        .collect();
+    if addrs.iter().any(|addr| is_private_ip(addr.ip())) {
+        return Err(ToolError::Blocked(format!(
+            "Refusing to fetch private address '{}'",
+            host
+        )));
+    }
+    Ok(addrs)
+}
+
+fn request_error(context: &str, e: reqwest::Error) -> ToolError {
+    let message = format!("{}: {}", context, e);
+    if e.is_timeout() {
+        ToolError::Timeout(message)
+    } else {
+        ToolError::Io(message)
+    }
+}
+
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                // carrier-grade NAT 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_private_ip(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                // unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+// Reduce an HTML document to readable text
+fn html_to_text(html: &str) -> String {
+    let script = Regex::new(r"(?is)<script\b.*?</script>").unwrap();
+    let style = Regex::new(r"(?is)<style\b.*?</style>").unwrap();
+    let block = Regex::new(r"(?i)<br\s*/?>|</(p|div|li|tr|h[1-6]|pre|section|article)>").unwrap();
+    let tag = Regex::new(r"(?s)<[^>]*>").unwrap();
+
+    let text = script.replace_all(html, "");
+    let text = style.replace_all(&text, "");
+    let text = block.replace_all(&text, "\n");
+    let text = tag.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    text.lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,79 @@
+use super::ToolError;
+use std::fs;
+
+/// Inserts content relative to a 1-based line number.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file.
+/// * `line` - The 1-based line number, as shown by `read_file`.
+/// * `content` - The content to insert.
+/// * `position` - "before" or "after" the line.
+///
+pub fn insert_at_line(
+    path: &str,
+    line: usize,
+    content: &str,
+    position: &str,
+) -> Result<String, ToolError> {
+    let file_content =
+        fs::read_to_string(path).map_err(|e| ToolError::io("Failed to read file", e))?;
+
+    let lines: Vec<&str> = file_content.split_inclusive('\n').collect();
+    // An empty file has no lines yet, but line 1 is where content goes
+    if line == 0 || line > lines.len().max(1) {
+        return Err(ToolError::InvalidArgument(format!(
+            "Line {} is out of range (file has {} lines)",
+            line,
+            lines.len()
+        )));
+    }
+
+    let insert_at = match position {
+        "before" => line - 1,
+        "after" => line,
+        _ => {
+            return Err(ToolError::InvalidArgument(
+                "Position must be 'before' or 'after'".to_string(),
+            ));
+        }
+    };
+
+    let insert_at = insert_at.min(lines.len());
+
+    // Match the file's line endings so an insert doesn't mix them
+    let eol = if file_content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut block = content.replace("\r\n", "\n").replace('\n', eol);
+    if !block.ends_with('\n') {
+        block.push_str(eol);
+    }
+
+    let mut new_content = String::with_capacity(file_content.len() + block.len() + 1);
+    for (idx, l) in lines.iter().enumerate() {
+        if idx == insert_at {
+            new_content.push_str(&block);
+        }
+        new_content.push_str(l);
+    }
+    if insert_at == lines.len() {
+        if !new_content.is_empty() && !new_content.ends_with('\n') {
+            new_content.push_str(eol);
+        }
+        new_content.push_str(&block);
+        // Keep the file's original "no newline at EOF" convention
+        if !file_content.is_empty() && !file_content.ends_with('\n') {
+            new_content.truncate(new_content.len() - eol.len());
+        }
+    }
+
+    fs::write(path, new_content).map_err(|e| ToolError::io("Failed to write file", e))?;
+
+    Ok(format!(
+        "Successfully inserted content {} line {} in {}",
+        position, line, path
+    ))
+}
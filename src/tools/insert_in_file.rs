@@ -1,4 +1,6 @@
+use super::ToolError;
 use std::fs;
+
 /// Inserts content at a specific location in a file.
 ///
 /// # Arguments
@@ -7,27 +9,118 @@ use std::fs;
 /// * `anchor` - A unique string in the file to locate the insertion point.
 /// * `content` - The content to insert.
 /// * `position` - "before" or "after" the anchor.
+/// * `fuzzy` - If the exact anchor is missing, retry ignoring whitespace
+///   differences. Ambiguous fuzzy matches are reported, not guessed.
 ///
 pub fn insert_in_file(
     path: &str,
     anchor: &str,
     content: &str,
     position: &str,
-) -> Result<String, String> {
+    fuzzy: bool,
+) -> Result<String, ToolError> {
     let file_content =
-        fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))?;
+        fs::read_to_string(path).map_err(|e| ToolError::io("Failed to read file", e))?;
+
+    if position != "before" && position != "after" {
+        return Err(ToolError::InvalidArgument(
+            "Position must be 'before' or 'after'".to_string(),
+        ));
+    }
+
+    if file_content.contains(anchor) {
+        let new_content = match position {
+            "before" => file_content.replace(anchor, &format!("{}\n{}", content, anchor)),
+            _ => file_content.replace(anchor, &format!("{}\n{}", anchor, content)),
+        };
+        fs::write(path, new_content).map_err(|e| ToolError::io("Failed to write file", e))?;
+        return Ok(format!("Successfully inserted content in {}", path));
+    }
 
-    if !file_content.contains(anchor) {
-        return Err(format!("Anchor '{}' not found in file", anchor));
+    if !fuzzy {
+        return Err(ToolError::NotFound(format!(
+            "Anchor '{}' not found in file",
+            anchor
+        )));
     }
 
-    let new_content = match position {
-        "before" => file_content.replace(anchor, &format!("{}\n{}", content, anchor)),
-        "after" => file_content.replace(anchor, &format!("{}\n{}", anchor, content)),
-        _ => return Err("Position must be 'before' or 'after'".to_string()),
+    let candidates = fuzzy_matches(&file_content, anchor);
+    let (line, start, end) = match candidates.as_slice() {
+        [] => {
+            return Err(ToolError::NotFound(format!(
+                "Anchor '{}' not found in file (even fuzzily)",
+                anchor
+            )));
+        }
+        [only] => *only,
+        many => {
+            let listing: Vec<String> = many
+                .iter()
+                .map(|(line, start, end)| {
+                    format!("  line {}: {}", line, &file_content[*start..*end])
+                })
+                .collect();
+            return Err(ToolError::InvalidArgument(format!(
+                "Anchor '{}' fuzzily matches {} places; use a more specific anchor:\n{}",
+                anchor,
+                many.len(),
+                listing.join("\n")
+            )));
+        }
+    };
+
+    let matched = &file_content[start..end];
+    let replacement = match position {
+        "before" => format!("{}\n{}", content, matched),
+        _ => format!("{}\n{}", matched, content),
     };
+    let new_content = format!(
+        "{}{}{}",
+        &file_content[..start],
+        replacement,
+        &file_content[end..]
+    );
+    fs::write(path, new_content).map_err(|e| ToolError::io("Failed to write file", e))?;
 
-    fs::write(&path, new_content).map_err(|e| format!("Failed to write file: {}", e))?;
+    Ok(format!(
+        "Successfully inserted content in {} (fuzzy anchor match at line {})",
+        path, line
+    ))
+}
 
-    Ok(format!("Successfully inserted content in {}", path))
+// Collapse runs of whitespace and trim, so indentation and trailing spaces
+// don't matter
+fn normalize(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Line windows whose normalized text equals the normalized anchor, as
+// (1-based line, byte start, byte end without the final newline)
+fn fuzzy_matches(file_content: &str, anchor: &str) -> Vec<(usize, usize, usize)> {
+    let wanted: Vec<String> = anchor.trim().lines().map(normalize).collect();
+    if wanted.is_empty() || wanted.iter().all(|l| l.is_empty()) {
+        return Vec::new();
+    }
+
+    // Byte offset of each line's start and end (excluding the line break)
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for raw in file_content.split_inclusive('\n') {
+        let text = raw.trim_end_matches(['\n', '\r']);
+        spans.push((offset, offset + text.len()));
+        offset += raw.len();
+    }
+
+    let mut matches = Vec::new();
+    for i in 0..spans.len().saturating_sub(wanted.len() - 1) {
+        let window = &spans[i..i + wanted.len()];
+        let same = window
+            .iter()
+            .zip(&wanted)
+            .all(|((s, e), w)| normalize(&file_content[*s..*e]) == *w);
+        if same {
+            matches.push((i + 1, window[0].0, window[window.len() - 1].1));
+        }
+    }
+    matches
 }
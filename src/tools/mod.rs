@@ -1,18 +1,72 @@
-pub use self::approval::{format_tool_approval, get_user_approval, requires_approval};
-pub use self::ask_orackle::ask_orackle;
-pub use self::edit_file::edit_file;
+pub use self::apply_changes::{Change, apply_changes};
+pub use self::approval::{
+    ApprovalOverrides, approval_details, approval_question, ask_result_retention,
+    format_tool_approval, get_user_approval,
+};
+pub use self::ask_orackle::{ask_orackle, set_max_oracle_depth};
+pub use self::cargo_check::cargo_check;
+pub use self::edit_file::{Edit, edit_file, edit_file_batch};
+pub use self::error::ToolError;
+pub use self::file_stats::file_stats;
+pub use self::format_code::format_code;
+pub use self::glob::glob;
+pub use self::http_fetch::http_fetch;
+pub use self::insert_at_line::insert_at_line;
 pub use self::insert_in_file::insert_in_file;
 pub use self::list_dir::list_dir;
-pub use self::read_file::read_file;
+pub use self::path_exists::path_exists;
+pub use self::project::scan_project;
+pub use self::read_file::{read_file, read_file_hex};
+pub use self::replace_between::replace_between;
+pub use self::replace_in_files::replace_in_files;
+pub use self::replace_lines::replace_lines;
 pub use self::run_shell::run_shell;
+pub use self::run_tests::run_tests;
 pub use self::search_in_file::search_in_files;
-pub use self::write_file::write_file;
+pub use self::trash::{delete_file, list_trash, restore_from_trash};
+pub use self::tree::tree;
+#[cfg(feature = "web-search")]
+pub use self::web_search::{WebSearchConfig, configure_web_search, web_search};
+pub use self::write_file::{LineEndings, TextFormat, write_file};
+mod apply_changes;
 mod approval;
 mod ask_orackle;
+mod cargo_check;
 mod edit_file;
+mod error;
+mod file_stats;
+mod format_code;
+mod glob;
+mod http_fetch;
+mod insert_at_line;
 mod insert_in_file;
 mod list_dir;
+mod path_exists;
+mod project;
 mod read_file;
+mod replace_between;
+mod replace_in_files;
+mod replace_lines;
 mod run_shell;
+mod run_tests;
 mod search_in_file;
+mod trash;
+mod tree;
+#[cfg(feature = "web-search")]
+mod web_search;
 mod write_file;
+
+// Internals exercised directly by the test suite
+#[cfg(all(test, feature = "web-search"))]
+pub use self::web_search::{SearchResult, parse_search_results, search_with};
+#[cfg(test)]
+pub use self::{
+    approval::requires_approval,
+    ask_orackle::{ORACLE_DEPTH, oracle_tools},
+    cargo_check::{Diagnostic, parse_cargo_diagnostics},
+    format_code::{Formatter, select_formatter},
+    http_fetch::fetch_url,
+    project::{ProjectKind, detect_project},
+    run_tests::summarize_test_output,
+    write_file::overwrite_diff,
+};
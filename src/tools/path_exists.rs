@@ -0,0 +1,24 @@
+use super::ToolError;
+use std::fs;
+use std::io;
+
+/// Cheaply checks whether `path` exists, and if so whether it is a file
+/// (with its size) or a directory. A missing path is a normal answer, not
+/// an error.
+///
+/// # Arguments
+///
+/// * `path` - Path to probe.
+///
+pub fn path_exists(path: &str) -> Result<String, ToolError> {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => Ok(format!("{}: directory", path)),
+        Ok(meta) if meta.is_file() => Ok(format!("{}: file, {} bytes", path, meta.len())),
+        Ok(_) => Ok(format!(
+            "{}: exists (not a regular file or directory)",
+            path
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(format!("{}: does not exist", path)),
+        Err(e) => Err(ToolError::io(format!("Failed to check {}", path), e)),
+    }
+}
@@ -0,0 +1,79 @@
+use super::tree::tree;
+use std::path::Path;
+
+// Depth of the directory overview in `scan_project`
+const SCAN_TREE_DEPTH: usize = 1;
+
+/// Project ecosystems we know how to drive (tests, formatting, …).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectKind {
+    Cargo,
+    Npm,
+    Python,
+}
+
+impl ProjectKind {
+    pub fn language(&self) -> &'static str {
+        match self {
+            ProjectKind::Cargo => "Rust",
+            ProjectKind::Npm => "JavaScript/TypeScript",
+            ProjectKind::Python => "Python",
+        }
+    }
+
+    pub fn test_command(&self) -> &'static str {
+        match self {
+            ProjectKind::Cargo => "cargo test",
+            ProjectKind::Npm => "npm test",
+            ProjectKind::Python => "pytest",
+        }
+    }
+}
+
+// Manifest files checked in priority order
+const MANIFESTS: &[(&str, ProjectKind)] = &[
+    ("Cargo.toml", ProjectKind::Cargo),
+    ("package.json", ProjectKind::Npm),
+    ("pyproject.toml", ProjectKind::Python),
+    ("setup.py", ProjectKind::Python),
+    ("pytest.ini", ProjectKind::Python),
+    ("requirements.txt", ProjectKind::Python),
+];
+
+/// Detects the project type from the manifests present in `root`.
+pub fn detect_project(root: &Path) -> Option<ProjectKind> {
+    MANIFESTS
+        .iter()
+        .find(|(manifest, _)| root.join(manifest).is_file())
+        .map(|(_, kind)| *kind)
+}
+
+/// A short orientation summary of the project at `root`: language, manifests,
+/// test command and the top-level layout. Given to the model once at session
+/// start so it needs fewer exploratory tool calls.
+pub fn scan_project(root: &Path) -> String {
+    let manifests: Vec<&str> = MANIFESTS
+        .iter()
+        .map(|(manifest, _)| *manifest)
+        .filter(|manifest| root.join(manifest).is_file())
+        .collect();
+    let kind = detect_project(root);
+
+    let mut out = vec![format!("Project scan of {}", root.display())];
+    out.push(format!(
+        "Language: {}",
+        kind.map_or("unknown", |k| k.language())
+    ));
+    if manifests.is_empty() {
+        out.push("Manifests: none found".to_string());
+    } else {
+        out.push(format!("Manifests: {}", manifests.join(", ")));
+    }
+    if let Some(kind) = kind {
+        out.push(format!("Test command: {}", kind.test_command()));
+    }
+    if let Ok(layout) = tree(&root.to_string_lossy(), Some(SCAN_TREE_DEPTH), None) {
+        out.push(format!("Top level:\n{}", layout.trim_end()));
+    }
+    out.join("\n")
+}
@@ -1,49 +1,239 @@
-use std::fs::{File, metadata};
-use std::io::{BufRead, BufReader};
+use super::ToolError;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use std::fs::{self, File, metadata};
+use std::io::{Read, Seek, SeekFrom};
 
-const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024; //10MB
+pub const DEFAULT_MAX_READ_BYTES: u64 = 10 * 1024 * 1024; //10MB
 const DEFAULT_MAX_LINES: usize = 200;
+const DEFAULT_HEXDUMP_BYTES: usize = 256;
+const MAX_HEXDUMP_BYTES: usize = 4096;
+const BINARY_SNIFF_BYTES: usize = 8192;
+const BINARY_ERROR: &str = "Binary or invalid UTF-8 content detected (use hexdump to inspect it)";
 
+/// Returns numbered lines `start_line..=end_line` of a text file.
+///
+/// A line that isn't valid UTF-8 (e.g. half-written in a live log) is shown
+/// as `[unreadable line N]` so the rest of the file still comes back; with
+/// `strict` it fails the whole read instead. Files that look binary up front
+/// are always refused.
+///
+/// Unless `strict`, text that isn't UTF-8 at all (UTF-16, Latin-1, …) is
+/// decoded from the encoding it appears to use, named on a first line.
+///
+/// Without `end_line`, the file is read from `start_line` to the end, up to
+/// `window` lines (default 200). When that cap cuts the read short, a note
+/// at the end gives the remaining range and how to continue.
+///
+/// Files larger than `max_bytes` (default 10MB) are refused, since the
+/// whole file is loaded into memory.
 pub fn read_file(
     path: &str,
     start_line: Option<usize>,
     end_line: Option<usize>,
-) -> Result<String, String> {
-    let metadata = metadata(path).map_err(|e| format!("Failed to get Metadata: {}", e))?;
+    strict: bool,
+    window: Option<usize>,
+    max_bytes: Option<u64>,
+) -> Result<String, ToolError> {
+    let metadata = metadata(path).map_err(|e| ToolError::io("Failed to get Metadata", e))?;
     //check size
-    if metadata.len() > MAX_FILE_SIZE {
-        return Err(format!(
+    let max_bytes = max_bytes.unwrap_or(DEFAULT_MAX_READ_BYTES);
+    if metadata.len() > max_bytes {
+        return Err(ToolError::InvalidArgument(format!(
             "File Size too Large: {} bytes (max: {} bytes) ",
             metadata.len(),
-            MAX_FILE_SIZE
-        ));
+            max_bytes
+        )));
     }
-    let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let raw = fs::read(path).map_err(|e| ToolError::io("Failed to read file", e))?;
+
+    let decoded = if strict { None } else { decode_non_utf8(&raw) };
+    let (content, encoding) = match decoded {
+        Some((text, encoding)) => (text.into_bytes(), Some(encoding)),
+        None => {
+            // NUL bytes near the start mean binary, not a torn line
+            if raw[..raw.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+                return Err(ToolError::InvalidArgument(BINARY_ERROR.to_string()));
+            }
+            (raw, None)
+        }
+    };
+    // Like BufRead::split: no empty piece after a final newline
+    let body = content.strip_suffix(b"\n").unwrap_or(&content);
+    let pieces = (!content.is_empty())
+        .then(|| body.split(|&b| b == b'\n'))
+        .into_iter()
+        .flatten();
 
-    let reader = BufReader::new(file);
     let start = start_line.unwrap_or(1);
-    let end = end_line.unwrap_or(start + DEFAULT_MAX_LINES - 1);
+    let window = window.unwrap_or(DEFAULT_MAX_LINES).max(1);
+    let end = end_line.unwrap_or(start + window - 1);
 
     let mut lines = Vec::new();
-    let mut line_num = 1;
+    // Lines past a capped read, counted so the note can say what's left
+    let mut unread = 0;
 
-    for line in reader.lines() {
+    for (idx, bytes) in pieces.enumerate() {
+        let line_num = idx + 1;
         if line_num > end {
-            break;
+            if end_line.is_some() {
+                break;
+            }
+            unread += 1;
+            continue;
         }
 
-        let line = line.map_err(|_| "Binary or invalid UTF-8 content detected".to_string())?;
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        let line = match std::str::from_utf8(bytes) {
+            Ok(line) => line.to_string(),
+            Err(_) if strict => return Err(ToolError::InvalidArgument(BINARY_ERROR.to_string())),
+            Err(_) => format!("[unreadable line {}]", line_num),
+        };
 
         if line_num >= start {
             lines.push(format!("{}: {}", line_num, line));
         }
-
-        line_num += 1;
     }
 
     if lines.is_empty() {
-        return Err(format!("No lines found in range {}-{}", start, end));
+        return Err(ToolError::InvalidArgument(format!(
+            "No lines found in range {}-{}",
+            start, end
+        )));
     }
 
+    let mut out = lines.join("\n");
+    if let Some(encoding) = encoding {
+        out.insert_str(0, &format!("[decoded from {}]\n", encoding));
+    }
+    if unread > 0 {
+        let total = end + unread;
+        out.push_str(&format!(
+            "\n[Showing lines {}-{} of {}. Lines {}-{} remain; continue with start_line={}]",
+            start,
+            end,
+            total,
+            end + 1,
+            total,
+            end + 1
+        ));
+    }
+    Ok(out)
+}
+
+/// Returns an `xxd`-style hex + ASCII dump of `len` bytes (default 256,
+/// capped at 4KB) starting at `start_byte`. Works on any file, binary or not.
+pub fn read_file_hex(
+    path: &str,
+    start_byte: Option<u64>,
+    len: Option<usize>,
+) -> Result<String, ToolError> {
+    let start = start_byte.unwrap_or(0);
+    let len = len.unwrap_or(DEFAULT_HEXDUMP_BYTES).min(MAX_HEXDUMP_BYTES);
+
+    let mut file = File::open(path).map_err(|e| ToolError::io("Failed to open file", e))?;
+    file.seek(SeekFrom::Start(start))
+        .map_err(|e| ToolError::io("Failed to seek", e))?;
+    let mut bytes = Vec::with_capacity(len);
+    file.take(len as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|e| ToolError::io("Failed to read file", e))?;
+
+    if bytes.is_empty() {
+        return Err(ToolError::InvalidArgument(format!(
+            "No bytes at offset {}",
+            start
+        )));
+    }
+
+    let lines: Vec<String> = bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk
+                .chunks(2)
+                .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect())
+                .collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}: {:<40} {}",
+                start + (i * 16) as u64,
+                hex.join(" "),
+                ascii
+            )
+        })
+        .collect();
     Ok(lines.join("\n"))
 }
+
+// Text that isn't UTF-8: UTF-16 (by its BOM, or the NULs ASCII leaves in
+// every other byte), or a legacy encoding guessed from the bytes. `None` for
+// UTF-8, including files whose only bad lines were cut off mid-character
+// (see `only_torn_lines`), and for anything that looks binary.
+fn decode_non_utf8(bytes: &[u8]) -> Option<(String, &'static str)> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        if encoding == UTF_8 {
+            return None;
+        }
+        return decode_strict(encoding, &bytes[bom_len..]);
+    }
+    if let Some(encoding) = utf16_without_bom(&bytes[..bytes.len().min(BINARY_SNIFF_BYTES)]) {
+        return decode_strict(encoding, bytes);
+    }
+    if bytes.contains(&0) || std::str::from_utf8(bytes).is_ok() || only_torn_lines(bytes) {
+        return None;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, false);
+    decode_strict(encoding, bytes)
+}
+
+fn decode_strict(encoding: &'static Encoding, bytes: &[u8]) -> Option<(String, &'static str)> {
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    (!had_errors).then(|| (text.into_owned(), encoding.name()))
+}
+
+// ASCII-heavy UTF-16 has a NUL in nearly every high byte and almost none
+// in the low bytes
+fn utf16_without_bom(head: &[u8]) -> Option<&'static Encoding> {
+    let pairs = head.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let zeros_at = |offset: usize| {
+        head.chunks_exact(2)
+            .filter(|pair| pair[offset] == 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd * 10 >= pairs * 7 && even * 20 <= pairs {
+        Some(UTF_16LE)
+    } else if even * 10 >= pairs * 7 && odd * 20 <= pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+// True when every invalid line is only missing the end of its last
+// character, as in a log caught mid-write, rather than being text in
+// another encoding
+fn only_torn_lines(bytes: &[u8]) -> bool {
+    bytes.split(|&b| b == b'\n').all(|line| {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        match std::str::from_utf8(line) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        }
+    })
+}
@@ -0,0 +1,80 @@
+use super::ToolError;
+use std::fs;
+
+/// Replaces everything between `begin_marker` and `end_marker` with
+/// `new_content`, keeping both markers. Meant for generated sections, so
+/// running it again with the same content changes nothing.
+///
+/// When the markers sit on lines of their own (e.g. `// BEGIN GENERATED`),
+/// the lines holding them are kept whole and `new_content` fills the lines
+/// in between; markers on one line have just the text between them replaced.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file.
+/// * `begin_marker` - Text marking the start of the region (first occurrence).
+/// * `end_marker` - Text marking its end (first occurrence after the begin).
+/// * `new_content` - Replacement for the region; empty clears it.
+///
+pub fn replace_between(
+    path: &str,
+    begin_marker: &str,
+    end_marker: &str,
+    new_content: &str,
+) -> Result<String, ToolError> {
+    if begin_marker.is_empty() || end_marker.is_empty() {
+        return Err(ToolError::InvalidArgument(
+            "begin_marker and end_marker must not be empty".to_string(),
+        ));
+    }
+    let content = fs::read_to_string(path).map_err(|e| ToolError::io("Failed to read file", e))?;
+
+    let missing = |marker: &str| {
+        ToolError::InvalidArgument(format!("Marker '{}' not found in {}", marker, path))
+    };
+    let begin = content
+        .find(begin_marker)
+        .ok_or_else(|| missing(begin_marker))?;
+    let after_begin = begin + begin_marker.len();
+    let end = match content[after_begin..].find(end_marker) {
+        Some(offset) => after_begin + offset,
+        None if content.contains(end_marker) => {
+            return Err(ToolError::InvalidArgument(format!(
+                "Markers are in the wrong order in {}: '{}' comes before '{}'",
+                path, end_marker, begin_marker
+            )));
+        }
+        None => return Err(missing(end_marker)),
+    };
+
+    let (start, stop, block) = match content[after_begin..end].find('\n') {
+        // Line mode: keep the rest of the begin line and the start of the end line
+        Some(offset) => {
+            let start = after_begin + offset + 1;
+            let stop = content[..end].rfind('\n').map_or(end, |nl| nl + 1);
+            let mut block = new_content.to_string();
+            if !block.is_empty() && !block.ends_with('\n') {
+                block.push('\n');
+            }
+            (start, stop.max(start), block)
+        }
+        None => (after_begin, end, new_content.to_string()),
+    };
+
+    if content[start..stop] == block {
+        return Ok(format!(
+            "Region between markers in {} is already up to date",
+            path
+        ));
+    }
+    let mut output = String::with_capacity(content.len() + block.len());
+    output.push_str(&content[..start]);
+    output.push_str(&block);
+    output.push_str(&content[stop..]);
+    fs::write(path, output).map_err(|e| ToolError::io("Failed to write file", e))?;
+
+    Ok(format!(
+        "Successfully replaced the region between markers in {}",
+        path
+    ))
+}
@@ -0,0 +1,100 @@
+use super::ToolError;
+use regex::{NoExpand, Regex};
+use std::fs;
+use std::path::PathBuf;
+
+/// Replaces `pattern` with `replacement` in every file under `path`,
+/// skipping hidden and gitignored files.
+///
+/// # Arguments
+///
+/// * `pattern` - Literal text, or a regex when `regex` is true.
+/// * `replacement` - Replacement text; `$1`-style groups work in regex mode.
+/// * `path` - File or directory to process.
+/// * `regex` - Treat `pattern` as a regular expression.
+/// * `dry_run` - Report what would change without writing anything.
+///
+pub fn replace_in_files(
+    pattern: &str,
+    replacement: &str,
+    path: &str,
+    regex: bool,
+    dry_run: bool,
+) -> Result<String, ToolError> {
+    let re = build_regex(pattern, regex)?;
+    let changes = plan(&re, path)?;
+    if changes.is_empty() {
+        return Err(ToolError::NotFound(format!(
+            "No matches for '{}' under {}",
+            pattern, path
+        )));
+    }
+
+    let mut report = Vec::new();
+    let mut total = 0;
+    for (file, count, content) in &changes {
+        if !dry_run {
+            let new_content = if regex {
+                re.replace_all(content, replacement)
+            } else {
+                re.replace_all(content, NoExpand(replacement))
+            };
+            fs::write(file, new_content.as_ref())
+                .map_err(|e| ToolError::io(format!("Failed to write {}", file.display()), e))?;
+        }
+        total += count;
+        report.push(format!("{}: {} replacement(s)", file.display(), count));
+    }
+
+    let header = if dry_run {
+        format!(
+            "Dry run: would make {} replacement(s) in {} file(s)",
+            total,
+            changes.len()
+        )
+    } else {
+        format!("Made {} replacement(s) in {} file(s)", total, changes.len())
+    };
+    Ok(format!("{}\n{}", header, report.join("\n")))
+}
+
+/// Number of files `replace_in_files` would modify, for the approval prompt.
+pub fn affected_file_count(pattern: &str, path: &str, regex: bool) -> Result<usize, ToolError> {
+    let re = build_regex(pattern, regex)?;
+    Ok(plan(&re, path)?.len())
+}
+
+fn build_regex(pattern: &str, regex: bool) -> Result<Regex, ToolError> {
+    if pattern.is_empty() {
+        return Err(ToolError::InvalidArgument(
+            "Pattern must not be empty".to_string(),
+        ));
+    }
+    let source = if regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    Regex::new(&source).map_err(|e| ToolError::InvalidArgument(format!("Invalid regex: {}", e)))
+}
+
+// Files with at least one match, with their match count and current content
+fn plan(re: &Regex, path: &str) -> Result<Vec<(PathBuf, usize, String)>, ToolError> {
+    let mut changes = Vec::new();
+    for entry in ignore::WalkBuilder::new(path).require_git(false).build() {
+        let entry = entry.map_err(|e| ToolError::Io(format!("walk error: {}", e)))?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        // Binary or unreadable files are left alone
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let count = re.find_iter(&content).count();
+        if count > 0 {
+            changes.push((entry.path().to_path_buf(), count, content));
+        }
+    }
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(changes)
+}
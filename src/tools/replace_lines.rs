@@ -0,0 +1,54 @@
+use super::ToolError;
+use std::fs;
+
+/// Replaces an inclusive, 1-based line range with new content.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file.
+/// * `start_line` - First line to replace.
+/// * `end_line` - Last line to replace (inclusive).
+/// * `new_content` - Replacement text; empty deletes the range.
+///
+pub fn replace_lines(
+    path: &str,
+    start_line: usize,
+    end_line: usize,
+    new_content: &str,
+) -> Result<String, ToolError> {
+    let file_content =
+        fs::read_to_string(path).map_err(|e| ToolError::io("Failed to read file", e))?;
+
+    let lines: Vec<&str> = file_content.split_inclusive('\n').collect();
+    if start_line == 0 || start_line > end_line || end_line > lines.len() {
+        return Err(ToolError::InvalidArgument(format!(
+            "Invalid line range {}-{} (file has {} lines)",
+            start_line,
+            end_line,
+            lines.len()
+        )));
+    }
+
+    let mut block = new_content.to_string();
+    // The last replaced line keeps its original newline convention
+    let replaced_eol = lines[end_line - 1].ends_with('\n');
+    if !block.is_empty() && replaced_eol && !block.ends_with('\n') {
+        block.push('\n');
+    }
+
+    let mut output = String::with_capacity(file_content.len() + block.len());
+    for l in &lines[..start_line - 1] {
+        output.push_str(l);
+    }
+    output.push_str(&block);
+    for l in &lines[end_line..] {
+        output.push_str(l);
+    }
+
+    fs::write(path, output).map_err(|e| ToolError::io("Failed to write file", e))?;
+
+    Ok(format!(
+        "Successfully replaced lines {}-{} in {}",
+        start_line, end_line, path
+    ))
+}
@@ -1,52 +1,318 @@
+use super::ToolError;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use wait_timeout::ChildExt;
 const TIMEOUT_SECONDS: u64 = 30;
+const MAX_OUTPUT_BYTES: usize = 1024 * 1024; // 1MB per output stream
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
 const DENIED_COMMANDS: &[&str] = &["rm", "dd", "mkfs", ":(", "sudo", "su"];
+const DENIED_WINDOWS_COMMANDS: &[&str] = &["del", "erase", "format"];
+
+/// Runs `command` through the shell with a timeout. The child is killed once
+/// either output stream exceeds `max_output` bytes (default 1MB). `env` is
+/// layered over the inherited environment, or over an empty one when
+/// `clear_env` is set.
+///
+/// Every call gets a fresh shell, so a leading `cd dir` is handled here: it
+/// moves `cwd`, which later calls run in, and the rest of the command (after
+/// `&&` or `;`) runs there. A bare `cd` goes back to termx's own directory.
+///
+/// `stdin`, when given, is written to the command's standard input, which is
+/// then closed, so content can be piped in without a heredoc.
+pub fn run_shell(
+    command: &str,
+    max_output: Option<usize>,
+    env: &HashMap<String, String>,
+    clear_env: bool,
+    cwd: &mut Option<PathBuf>,
+    stdin: Option<&str>,
+) -> Result<String, ToolError> {
+    let mut command = command;
+    if let Some((target, rest)) = split_cd(command) {
+        *cwd = change_dir(cwd.as_deref(), target)?;
+        match rest {
+            Some(rest) => command = rest,
+            None => {
+                return Ok(match cwd {
+                    Some(dir) => format!("Working directory is now {}", dir.display()),
+                    None => "Working directory is back to the project root".to_string(),
+                });
+            }
+        }
+    }
 
-pub fn run_shell(command: &str) -> Result<String, String> {
     // 1. Check denylist
-    let mut parts = command.split_whitespace();
-    let command_name = parts.next().ok_or("Empty command".to_string())?;
+    if is_denied(command)? {
+        return Err(ToolError::Blocked("Denied command".to_string()));
+    }
 
-    if DENIED_COMMANDS.contains(&command_name) {
-        return Err("Denied command".to_string());
+    let max_output = max_output.unwrap_or(MAX_OUTPUT_BYTES);
+    let output = run_command(
+        command,
+        max_output,
+        Duration::from_secs(TIMEOUT_SECONDS),
+        env,
+        clear_env,
+        cwd.as_deref(),
+        stdin,
+    )?;
+
+    let note = |stream: &str, truncated: bool| {
+        if truncated {
+            format!("… [{} truncated at {}]", stream, format_size(max_output))
+        } else {
+            String::new()
+        }
+    };
+    // Being stopped for flooding stdout isn't a failure; anything else is
+    let stdout_flood = output.killed && output.stdout_truncated && !output.stderr_truncated;
+    if output.success || stdout_flood {
+        Ok(output.stdout + &note("stdout", output.stdout_truncated))
+    } else {
+        // The exit marker tells a command that ran and failed apart from
+        // one that never started
+        let exit = match output.code {
+            Some(code) => format!("[exit code {}]", code),
+            None => "[killed]".to_string(),
+        };
+        let stderr = output.stderr + &note("stderr", output.stderr_truncated);
+        Err(ToolError::Io(if stderr.is_empty() {
+            exit
+        } else {
+            format!("{}\n{}", stderr.trim_end(), exit)
+        }))
     }
+}
+
+pub(super) struct CommandOutput {
+    pub success: bool,
+    pub code: Option<i32>, // None when ended by a signal
+    pub stdout: String,
+    pub stderr: String,
+    /// Stopped because a stream went over the cap
+    pub killed: bool,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+}
 
+// Spawn the platform shell, feed it `stdin` if any, capture up to
+// `max_output` bytes of each stream and enforce `timeout`. No denylist check; callers do that.
+pub(super) fn run_command(
+    command: &str,
+    max_output: usize,
+    timeout: Duration,
+    env: &HashMap<String, String>,
+    clear_env: bool,
+    cwd: Option<&Path>,
+    stdin: Option<&str>,
+) -> Result<CommandOutput, ToolError> {
     // 2. Spawn process (don't wait yet)
-    let mut child = Command::new("sh")
-        .arg("-c")
+    let (shell, shell_flag) = if cfg!(target_os = "windows") {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut cmd = Command::new(shell);
+    if clear_env {
+        cmd.env_clear();
+    }
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+    if stdin.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    let mut child = cmd
+        .envs(env)
+        .arg(shell_flag)
         .arg(command)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .map_err(|e| format!("Failed to spawn: {}", e))?;
-
-    // 3. Wait with timeout
-    let timeout = Duration::from_secs(TIMEOUT_SECONDS);
-    match child
-        .wait_timeout(timeout)
-        .map_err(|e| format!("Wait error: {}", e))?
-    {
-        Some(status) => {
-            // Process finished within timeout
-            let output = child
-                .wait_with_output()
-                .map_err(|e| format!("Failed to get output: {}", e))?;
-
-            if status.success() {
-                Ok(String::from_utf8_lossy(&output.stdout).to_string())
-            } else {
-                Err(String::from_utf8_lossy(&output.stderr).to_string())
-            }
+        .map_err(|e| ToolError::io("Failed to spawn", e))?;
+
+    // Written from a thread so a command that only reads after filling its
+    // output pipe can't deadlock us; dropping the pipe closes its stdin
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        let input = input.to_string();
+        thread::spawn(move || {
+            // A command that exits without reading everything breaks the pipe
+            let _ = pipe.write_all(input.as_bytes());
+        });
+    }
+
+    // 3. Drain output incrementally so a chatty command can't exhaust memory
+    // Each stream has its own cap so a flood on stdout can't hide stderr
+    let out_bytes = Arc::new(AtomicUsize::new(0));
+    let err_bytes = Arc::new(AtomicUsize::new(0));
+    let over_cap = |bytes: &AtomicUsize| bytes.load(Ordering::SeqCst) > max_output;
+    let reaped = Arc::new(AtomicBool::new(false));
+    let stdout = spawn_capped_reader(
+        child.stdout.take(),
+        max_output,
+        out_bytes.clone(),
+        reaped.clone(),
+    );
+    let stderr = spawn_capped_reader(
+        child.stderr.take(),
+        max_output,
+        err_bytes.clone(),
+        reaped.clone(),
+    );
+
+    // 4. Wait with timeout, watching the output budget
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if over_cap(&out_bytes) || over_cap(&err_bytes) {
+            child
+                .kill()
+                .map_err(|e| ToolError::io("Failed to kill", e))?;
+            let _ = child.wait();
+            break None;
+        }
+        if let Some(status) = child
+            .wait_timeout(POLL_INTERVAL)
+            .map_err(|e| ToolError::io("Wait error", e))?
+        {
+            break Some(status);
         }
-        None => {
+        if Instant::now() >= deadline {
             // Timeout reached, kill the process
-            child.kill().map_err(|e| format!("Failed to kill: {}", e))?;
-            Err(format!(
+            child
+                .kill()
+                .map_err(|e| ToolError::io("Failed to kill", e))?;
+            let _ = child.wait();
+            reaped.store(true, Ordering::SeqCst);
+            return Err(ToolError::Timeout(format!(
                 "Command timed out after {} seconds",
-                TIMEOUT_SECONDS
-            ))
+                timeout.as_secs()
+            )));
+        }
+    };
+    reaped.store(true, Ordering::SeqCst);
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+
+    Ok(CommandOutput {
+        success: status.is_some_and(|s| s.success()),
+        code: status.and_then(|s| s.code()),
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        killed: status.is_none(),
+        // The cap can also be hit by a command that exits on its own right after
+        stdout_truncated: over_cap(&out_bytes),
+        stderr_truncated: over_cap(&err_bytes),
+    })
+}
+
+// A leading `cd <dir>` and whatever follows it after `&&` or `;`
+fn split_cd(command: &str) -> Option<(&str, Option<&str>)> {
+    let command = command.trim();
+    let rest = command.strip_prefix("cd")?;
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    let (target, tail) = match rest.find("&&").into_iter().chain(rest.find(';')).min() {
+        Some(at) => {
+            let sep = if rest[at..].starts_with("&&") { 2 } else { 1 };
+            (&rest[..at], Some(rest[at + sep..].trim()))
         }
+        None => (rest, None),
+    };
+    let target = target.trim().trim_matches(['"', '\'']);
+    Some((target, tail.filter(|t| !t.is_empty())))
+}
+
+// Resolve `target` against `cwd`; an empty target resets to termx's own
+fn change_dir(cwd: Option<&Path>, target: &str) -> Result<Option<PathBuf>, ToolError> {
+    if target.is_empty() {
+        return Ok(None);
+    }
+    let dir = match cwd {
+        Some(base) => base.join(target),
+        None => PathBuf::from(target),
+    };
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| ToolError::io(format!("cd: {}", target), e))?;
+    if !dir.is_dir() {
+        return Err(ToolError::InvalidArgument(format!(
+            "cd: {}: not a directory",
+            target
+        )));
+    }
+    Ok(Some(dir))
+}
+
+pub(super) fn is_denied(command: &str) -> Result<bool, ToolError> {
+    let mut parts = command.split_whitespace();
+    let command_name = parts
+        .next()
+        .ok_or(ToolError::InvalidArgument("Empty command".to_string()))?;
+
+    if DENIED_COMMANDS.contains(&command_name) {
+        return Ok(true);
+    }
+    if cfg!(target_os = "windows") {
+        // cmd.exe builtins are case-insensitive
+        let name = command_name.to_ascii_lowercase();
+        if DENIED_WINDOWS_COMMANDS.contains(&name.as_str()) {
+            return Ok(true);
+        }
+        // Recursive directory removal: `rmdir /s` / `rd /s`
+        if (name == "rmdir" || name == "rd") && parts.any(|p| p.eq_ignore_ascii_case("/s")) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Reads `pipe` until EOF, keeping at most `max_output` bytes. Past the cap the
+// rest is read and discarded so the writer never blocks on a full pipe, until
+// the child has been `reaped`; then a leftover grandchild still holding the
+// pipe is cut off rather than drained forever.
+fn spawn_capped_reader<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    max_output: usize,
+    captured: Arc<AtomicUsize>,
+    reaped: Arc<AtomicBool>,
+) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        let Some(mut pipe) = pipe else {
+            return out;
+        };
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = match pipe.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let total = captured.fetch_add(n, Ordering::SeqCst) + n;
+            let keep = n.saturating_sub(total.saturating_sub(max_output));
+            out.extend_from_slice(&buf[..keep]);
+            if keep < n && reaped.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+        out
+    })
+}
+
+fn format_size(bytes: usize) -> String {
+    const MB: usize = 1024 * 1024;
+    if bytes >= MB && bytes.is_multiple_of(MB) {
+        format!("{}MB", bytes / MB)
+    } else if bytes >= 1024 && bytes.is_multiple_of(1024) {
+        format!("{}KB", bytes / 1024)
+    } else {
+        format!("{} bytes", bytes)
     }
 }
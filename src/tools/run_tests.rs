@@ -0,0 +1,138 @@
+use super::ToolError;
+use super::project::detect_project;
+use super::run_shell::{is_denied, run_command};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+const TIMEOUT_SECONDS: u64 = 300;
+const MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+const MAX_LISTED_FAILURES: usize = 20;
+
+/// Runs the project's test suite and returns a concise pass/fail summary.
+///
+/// # Arguments
+///
+/// * `command` - Test command to run. When `None` it is derived from the
+///   project manifest in the current directory (Cargo, npm or pytest).
+///
+pub fn run_tests(command: Option<&str>) -> Result<String, ToolError> {
+    let command = match command {
+        Some(cmd) => cmd.to_string(),
+        None => detect_project(Path::new("."))
+            .map(|kind| kind.test_command().to_string())
+            .ok_or(ToolError::InvalidArgument(
+                "Could not detect project type; pass an explicit test command".to_string(),
+            ))?,
+    };
+
+    if is_denied(&command)? {
+        return Err(ToolError::Blocked("Denied command".to_string()));
+    }
+
+    let output = run_command(
+        &command,
+        MAX_OUTPUT_BYTES,
+        Duration::from_secs(TIMEOUT_SECONDS),
+        &HashMap::new(),
+        false,
+        None,
+        None,
+    )?;
+    let combined = format!("{}\n{}", output.stdout, output.stderr);
+
+    match summarize_test_output(&combined) {
+        Some(summary) => Ok(format!(
+            "Tests {} ({}): {}",
+            if output.success { "PASSED" } else { "FAILED" },
+            command,
+            summary
+        )),
+        // Unknown runner: hand back the raw output
+        None if output.success => Ok(combined),
+        None => Err(ToolError::Io(combined)),
+    }
+}
+
+/// Extracts pass/fail counts and failing test names from cargo, pytest or
+/// jest-style output. Returns `None` when the format isn't recognised.
+pub fn summarize_test_output(output: &str) -> Option<String> {
+    let mut passed = 0usize;
+    let mut failed = 0usize;
+    let mut recognised = false;
+    let mut failures: Vec<String> = Vec::new();
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+
+        // cargo: "test result: FAILED. 3 passed; 1 failed; 0 ignored; ..."
+        if let Some(rest) = trimmed.strip_prefix("test result:") {
+            recognised = true;
+            passed += count_before(rest, "passed");
+            failed += count_before(rest, "failed");
+        }
+        // cargo: "test tests::foo ... FAILED"
+        if let Some(name) = trimmed
+            .strip_prefix("test ")
+            .and_then(|rest| rest.strip_suffix(" ... FAILED"))
+        {
+            failures.push(name.to_string());
+        }
+        // pytest: "==== 2 failed, 10 passed in 0.31s ===="
+        if trimmed.starts_with('=')
+            && trimmed.contains(" in ")
+            && (trimmed.contains("passed") || trimmed.contains("failed"))
+        {
+            recognised = true;
+            passed += count_before(trimmed, "passed");
+            failed += count_before(trimmed, "failed");
+        }
+        // pytest: "FAILED tests/test_x.py::test_y - AssertionError"
+        if let Some(rest) = trimmed.strip_prefix("FAILED ") {
+            let name = rest.split(" - ").next().unwrap_or(rest);
+            failures.push(name.to_string());
+        }
+        // jest: "Tests:       1 failed, 5 passed, 6 total"
+        if let Some(rest) = trimmed.strip_prefix("Tests:") {
+            recognised = true;
+            passed += count_before(rest, "passed");
+            failed += count_before(rest, "failed");
+        }
+        // jest: "✕ adds numbers (3 ms)"
+        if let Some(rest) = trimmed.strip_prefix("✕ ") {
+            failures.push(rest.to_string());
+        }
+    }
+
+    if !recognised {
+        return None;
+    }
+
+    let mut summary = format!("{} passed, {} failed", passed, failed);
+    if !failures.is_empty() {
+        summary.push_str("\nFailing tests:");
+        for name in failures.iter().take(MAX_LISTED_FAILURES) {
+            summary.push_str(&format!("\n  - {}", name));
+        }
+        if failures.len() > MAX_LISTED_FAILURES {
+            summary.push_str(&format!(
+                "\n  … and {} more",
+                failures.len() - MAX_LISTED_FAILURES
+            ));
+        }
+    }
+    Some(summary)
+}
+
+// Number immediately preceding `label`, e.g. "3 passed" -> 3
+fn count_before(text: &str, label: &str) -> usize {
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';' || c == '.')
+        .filter(|w| !w.is_empty())
+        .collect();
+    words
+        .windows(2)
+        .filter(|w| w[1] == label)
+        .filter_map(|w| w[0].parse::<usize>().ok())
+        .sum()
+}
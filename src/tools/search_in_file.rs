@@ -1,20 +1,43 @@
+use super::ToolError;
 use std::fs;
 use std::path::Path;
 
+// Lines of a multiline match shown under its first line
+const SNIPPET_LINES: usize = 5;
+// Characters shown per snippet line
+const SNIPPET_LINE_CHARS: usize = 200;
+
 // Search a path (file or dir) for `pattern`.
 // If path is a dir we walk it recursively (max 10k matches, 100 file-open limit).
 // Uses case-insensitive regex when `case_sensitive==Some(false)`.
+// `word_boundary` wraps the pattern in `\b…\b` so `get` skips `target`;
+// lines shorter than `min_line_len` (after trimming) are ignored.
+// With `multiline`, each file is matched as a whole (`^`/`$` match at line
+// ends and `.` matches newlines), so a pattern can span lines; each match is
+// reported at its first line with a short snippet of the lines it covers.
 pub fn search_in_files(
     pattern: &str,
     path: &str,
     case_sensitive: Option<bool>,
-) -> Result<String, String> {
+    word_boundary: bool,
+    min_line_len: Option<usize>,
+    multiline: bool,
+) -> Result<String, ToolError> {
+    let pattern = if word_boundary {
+        format!(r"\b(?:{})\b", pattern)
+    } else {
+        pattern.to_string()
+    };
+    let min_line_len = min_line_len.unwrap_or(0);
     let regex = {
-        let mut builder = regex::RegexBuilder::new(pattern);
+        let mut builder = regex::RegexBuilder::new(&pattern);
         builder.case_insensitive(case_sensitive == Some(false));
+        builder
+            .multi_line(multiline)
+            .dot_matches_new_line(multiline);
         builder
             .build()
-            .map_err(|e| format!("Invalid regex: {}", e))?
+            .map_err(|e| ToolError::InvalidArgument(format!("Invalid regex: {}", e)))?
     };
 
     let root = Path::new(path);
@@ -23,11 +46,37 @@ pub fn search_in_files(
     let mut checked = 0usize;
 
     // helper: push matches of a single file.
-    fn check_file(p: &Path, re: &regex::Regex, hits: &mut Vec<String>) -> Result<(), String> {
-        let buf =
-            fs::read_to_string(p).map_err(|_| format!("binary or unreadable: {}", p.display()))?;
+    fn check_file(
+        p: &Path,
+        re: &regex::Regex,
+        min_line_len: usize,
+        multiline: bool,
+        hits: &mut Vec<String>,
+    ) -> Result<(), ToolError> {
+        let buf = fs::read_to_string(p)
+            .map_err(|e| ToolError::io(format!("binary or unreadable: {}", p.display()), e))?;
+        if multiline {
+            for m in re.find_iter(&buf) {
+                if m.as_str().trim().len() < min_line_len {
+                    continue;
+                }
+                // From the start of the first matched line to the match end
+                let line_start = buf[..m.start()].rfind('\n').map_or(0, |i| i + 1);
+                let line_no = buf[..m.start()].matches('\n').count() + 1;
+                hits.push(format!(
+                    "{}:{}:{}",
+                    p.display(),
+                    line_no,
+                    snippet(&buf[line_start..m.end()])
+                ));
+                if hits.len() >= 10_000 {
+                    return Ok(());
+                }
+            }
+            return Ok(());
+        }
         for (idx, line) in buf.lines().enumerate() {
-            if re.is_match(line) {
+            if line.trim().len() >= min_line_len && re.is_match(line) {
                 hits.push(format!("{}:{}:{}", p.display(), idx + 1, line.trim_end()));
                 if hits.len() >= 10_000 {
                     return Ok(()); // safety cap
@@ -40,16 +89,17 @@ pub fn search_in_files(
     // actual walk
     for entry in walkdir::WalkDir::new(root)
         .into_iter()
-        .filter_entry(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        // Skip hidden entries, but never the root the caller asked for
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
     {
         if opened >= 100 {
             break;
         }
-        let entry = entry.map_err(|e| format!("walk error: {}", e))?;
+        let entry = entry.map_err(|e| ToolError::io("walk error", e.into()))?;
         if entry.file_type().is_file() {
             opened += 1;
             checked += 1;
-            check_file(entry.path(), &regex, &mut hits)?;
+            check_file(entry.path(), &regex, min_line_len, multiline, &mut hits)?;
             if hits.len() >= 10_000 {
                 break;
             }
@@ -57,7 +107,7 @@ pub fn search_in_files(
     }
 
     match (hits.len(), checked) {
-        (0, _) => Err("no matches found".to_string()),
+        (0, _) => Err(ToolError::NotFound("no matches found".to_string())),
         (_, _) => Ok(format!(
             "Found {} matches in {} files:\n{}",
             hits.len(),
@@ -66,3 +116,29 @@ pub fn search_in_files(
         )),
     }
 }
+
+// First line of a match, then up to SNIPPET_LINES more lines indented below
+// it, each clipped; the count of any remaining lines ends the snippet
+fn snippet(text: &str) -> String {
+    let clip_line = |line: &str| {
+        let line = line.trim_end();
+        if line.chars().count() > SNIPPET_LINE_CHARS {
+            let head: String = line.chars().take(SNIPPET_LINE_CHARS).collect();
+            format!("{}…", head)
+        } else {
+            line.to_string()
+        }
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = lines.first().map_or_else(String::new, |l| clip_line(l));
+    for line in lines.iter().skip(1).take(SNIPPET_LINES) {
+        out.push_str(&format!("\n    | {}", clip_line(line)));
+    }
+    if lines.len() > SNIPPET_LINES + 1 {
+        out.push_str(&format!(
+            "\n    | … {} more lines",
+            lines.len() - SNIPPET_LINES - 1
+        ));
+    }
+    out
+}
@@ -0,0 +1,143 @@
+use super::ToolError;
+use crate::workspace::{AGENT_DIR, TRASH_DIR, ensure_subdir};
+use chrono::Utc;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Deletes the file at `path`. With `safe_delete` it is moved into
+/// `<root>/.termx/trash/<timestamp>/` under its path relative to `root`, so
+/// `restore_from_trash` can put it back.
+///
+/// # Arguments
+///
+/// * `path` - File to delete; with `safe_delete` it must lie under `root`.
+/// * `safe_delete` - Move to the trash instead of removing permanently.
+/// * `root` - Project root holding the `.termx` directory.
+///
+pub fn delete_file(path: &str, safe_delete: bool, root: &Path) -> Result<String, ToolError> {
+    let file = Path::new(path);
+    if !file.is_file() {
+        return Err(ToolError::NotFound(format!("Not a file: {}", path)));
+    }
+    if !safe_delete {
+        fs::remove_file(file)
+            .map_err(|e| ToolError::io(format!("Failed to delete {}", path), e))?;
+        return Ok(format!("Permanently deleted {}", path));
+    }
+
+    let rel = relative_to_root(file, root)?;
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%.6f").to_string();
+    let trash = ensure_subdir(root, TRASH_DIR)
+        .map_err(|e| ToolError::io("Failed to create trash directory", e))?;
+    let dest = trash.join(&stamp).join(&rel);
+    move_file(file, &dest)
+        .map_err(|e| ToolError::io(format!("Failed to move {} to trash", path), e))?;
+
+    Ok(format!(
+        "Moved {} to trash as {}/{} (restore with restore_file)",
+        path,
+        stamp,
+        slash_path(&rel)
+    ))
+}
+
+/// Trashed files as `<timestamp>/<relative path>` ids, newest first.
+pub fn list_trash(root: &Path) -> Result<Vec<String>, ToolError> {
+    let trash = root.join(AGENT_DIR).join(TRASH_DIR);
+    if !trash.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut ids: Vec<String> = walkdir::WalkDir::new(&trash)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.path().strip_prefix(&trash).ok().map(slash_path))
+        .collect();
+    ids.sort_by(|a, b| b.cmp(a));
+    Ok(ids)
+}
+
+/// Moves the trashed file `id` (as returned by `list_trash`) back to its
+/// original path under `root`. Refuses to overwrite an existing file.
+pub fn restore_from_trash(id: &str, root: &Path) -> Result<String, ToolError> {
+    let invalid = || ToolError::InvalidArgument(format!("Invalid trash id: {}", id));
+    let id_path = Path::new(id);
+    if id_path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return Err(invalid());
+    }
+    let mut parts = id_path.components();
+    let stamp = parts.next().ok_or_else(invalid)?;
+    let rel = parts.as_path();
+    if rel.as_os_str().is_empty() {
+        return Err(invalid());
+    }
+
+    let stamp_dir = root.join(AGENT_DIR).join(TRASH_DIR).join(stamp);
+    let source = stamp_dir.join(rel);
+    if !source.is_file() {
+        return Err(ToolError::NotFound(format!("No trashed file {}", id)));
+    }
+    let dest = root.join(rel);
+    if dest.exists() {
+        return Err(ToolError::InvalidArgument(format!(
+            "{} already exists; move it away before restoring",
+            dest.display()
+        )));
+    }
+    move_file(&source, &dest).map_err(|e| ToolError::io(format!("Failed to restore {}", id), e))?;
+    prune_empty_dirs(source.parent(), &stamp_dir);
+
+    Ok(format!("Restored {}", slash_path(rel)))
+}
+
+fn relative_to_root(file: &Path, root: &Path) -> Result<PathBuf, ToolError> {
+    let file = file
+        .canonicalize()
+        .map_err(|e| ToolError::io(format!("Failed to resolve {}", file.display()), e))?;
+    let root = root
+        .canonicalize()
+        .map_err(|e| ToolError::io(format!("Failed to resolve {}", root.display()), e))?;
+    let rel = file.strip_prefix(&root).map_err(|_| {
+        ToolError::Blocked(format!(
+            "{} is outside the project; only project files can be trashed",
+            file.display()
+        ))
+    })?;
+    if rel.starts_with(AGENT_DIR) {
+        return Err(ToolError::Blocked(format!(
+            "Refusing to trash termx state under {}",
+            AGENT_DIR
+        )));
+    }
+    Ok(rel.to_path_buf())
+}
+
+// Rename, falling back to copy + remove across filesystems
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+// Remove directories left empty by a restore, up to and including `stop`
+fn prune_empty_dirs(mut dir: Option<&Path>, stop: &Path) {
+    while let Some(d) = dir {
+        if !d.starts_with(stop) || fs::remove_dir(d).is_err() {
+            return;
+        }
+        dir = d.parent();
+    }
+}
+
+fn slash_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
@@ -0,0 +1,81 @@
+use super::ToolError;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_MAX_DEPTH: usize = 3;
+const DEFAULT_MAX_ENTRIES: usize = 20;
+
+/// Renders an indented, `tree`-style overview of `path` using relative
+/// names. Hidden and gitignored entries are skipped.
+///
+/// # Arguments
+///
+/// * `path` - The directory to render.
+/// * `max_depth` - How many levels below `path` to show (default 3).
+/// * `max_entries` - Entries shown per directory before "… (N more)" (default 20).
+///
+pub fn tree(
+    path: &str,
+    max_depth: Option<usize>,
+    max_entries: Option<usize>,
+) -> Result<String, ToolError> {
+    let root = Path::new(path);
+    if !root.is_dir() {
+        return Err(ToolError::NotFound(format!("Not a directory: {}", path)));
+    }
+    let max_depth = max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+    let max_entries = max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+
+    // Children of every directory, keyed by path relative to the root
+    let mut children: BTreeMap<PathBuf, Vec<(String, bool)>> = BTreeMap::new();
+    let walker = ignore::WalkBuilder::new(root)
+        .max_depth(Some(max_depth))
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    for entry in walker {
+        let entry = entry.map_err(|e| ToolError::Io(format!("walk error: {}", e)))?;
+        if entry.depth() == 0 {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let parent = rel.parent().unwrap_or(Path::new("")).to_path_buf();
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let name = entry.file_name().to_string_lossy().to_string();
+        children.entry(parent).or_default().push((name, is_dir));
+    }
+
+    let mut lines = vec![format!("{}/", path.trim_end_matches('/'))];
+    render(&children, Path::new(""), 1, max_entries, &mut lines);
+    Ok(lines.join("\n"))
+}
+
+fn render(
+    children: &BTreeMap<PathBuf, Vec<(String, bool)>>,
+    dir: &Path,
+    depth: usize,
+    max_entries: usize,
+    lines: &mut Vec<String>,
+) {
+    let Some(entries) = children.get(dir) else {
+        return;
+    };
+    let indent = "  ".repeat(depth);
+    for (name, is_dir) in entries.iter().take(max_entries) {
+        if *is_dir {
+            lines.push(format!("{}{}/", indent, name));
+            render(children, &dir.join(name), depth + 1, max_entries, lines);
+        } else {
+            lines.push(format!("{}{}", indent, name));
+        }
+    }
+    if entries.len() > max_entries {
+        lines.push(format!(
+            "{}… ({} more)",
+            indent,
+            entries.len() - max_entries
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+use super::ToolError;
+use regex::Regex;
+use reqwest::Url;
+use serde_json::Value;
+use std::sync::OnceLock;
+use tokio::time::Duration;
+
+const TIMEOUT_SECONDS: u64 = 20;
+const DEFAULT_MAX_RESULTS: usize = 5;
+const MAX_RESULTS: usize = 10;
+const SNIPPET_CHARS: usize = 300;
+const ERROR_BODY_CHARS: usize = 500;
+
+static CONFIG: OnceLock<WebSearchConfig> = OnceLock::new();
+
+/// The search API `web_search` queries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSearchConfig {
+    /// Called as `GET endpoint?q=<query>&count=<n>`
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    /// Header carrying the key; `Authorization: Bearer <key>` when unset
+    pub key_header: Option<String>,
+}
+
+/// One search hit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+/// Sets the search API for the rest of the process. Only the first call
+/// takes effect.
+pub fn configure_web_search(config: WebSearchConfig) {
+    let _ = CONFIG.set(config);
+}
+
+/// Searches the web and returns the top results' titles, URLs and snippets.
+///
+/// # Arguments
+///
+/// * `query` - What to search for.
+/// * `max_results` - Results to return (default 5, at most 10).
+///
+pub async fn web_search(query: &str, max_results: Option<usize>) -> Result<String, ToolError> {
+    let config = CONFIG.get().ok_or_else(|| {
+        ToolError::InvalidArgument(
+            "web_search is not configured; set TERMX_WEB_SEARCH_URL".to_string(),
+        )
+    })?;
+    search_with(config, query, max_results).await
+}
+
+/// `web_search` against an explicit API rather than the configured one.
+pub async fn search_with(
+    config: &WebSearchConfig,
+    query: &str,
+    max_results: Option<usize>,
+) -> Result<String, ToolError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err(ToolError::InvalidArgument(
+            "query must not be empty".to_string(),
+        ));
+    }
+    let count = max_results
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .clamp(1, MAX_RESULTS);
+
+    let mut url = Url::parse(&config.endpoint)
+        .map_err(|e| ToolError::InvalidArgument(format!("Invalid search endpoint: {}", e)))?;
+    url.query_pairs_mut()
+        .append_pair("q", query)
+        .append_pair("count", &count.to_string());
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECONDS))
+        .build()
+        .map_err(|e| request_error("Failed to build HTTP client", e))?;
+    let mut request = client.get(url).header("Accept", "application/json");
+    if let Some(key) = &config.api_key {
+        request = match &config.key_header {
+            Some(header) => request.header(header.as_str(), key),
+            None => request.bearer_auth(key),
+        };
+    }
+    let resp = request
+        .send()
+        .await
+        .map_err(|e| request_error("Search request failed", e))?;
+
+    let status = resp.status();
+    let body = resp
+        .text()
+        .await
+        .map_err(|e| request_error("Failed to read search response", e))?;
+    if !status.is_success() {
+        let body: String = body.chars().take(ERROR_BODY_CHARS).collect();
+        return Err(ToolError::Io(format!(
+            "Search API returned HTTP {}: {}",
+            status, body
+        )));
+    }
+    let value: Value = serde_json::from_str(&body)
+        .map_err(|e| ToolError::Io(format!("Search API did not return JSON: {}", e)))?;
+
+    let results = parse_search_results(&value);
+    if results.is_empty() {
+        return Ok(format!("No results for '{}'", query));
+    }
+    Ok(format_search_results(
+        query,
+        &results[..results.len().min(count)],
+    ))
+}
+
+/// Pulls results out of the common search API response shapes: Brave
+/// (`web.results`), SearXNG (`results`), Google CSE (`items`) and SerpAPI
+/// (`organic_results`).
+pub fn parse_search_results(value: &Value) -> Vec<SearchResult> {
+    let list = [
+        &value["web"]["results"],
+        &value["results"],
+        &value["items"],
+        &value["organic_results"],
+    ]
+    .into_iter()
+    .find_map(Value::as_array);
+
+    let first_str = |item: &Value, keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| item[*key].as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    list.into_iter()
+        .flatten()
+        .map(|item| SearchResult {
+            title: strip_tags(&first_str(item, &["title", "name"])),
+            url: first_str(item, &["url", "link"]),
+            snippet: strip_tags(&first_str(item, &["description", "snippet", "content"])),
+        })
+        .filter(|result| !result.url.is_empty())
+        .collect()
+}
+
+fn format_search_results(query: &str, results: &[SearchResult]) -> String {
+    let mut out = format!("Top {} result(s) for '{}':", results.len(), query);
+    for (i, result) in results.iter().enumerate() {
+        let title = if result.title.is_empty() {
+            "(untitled)"
+        } else {
+            &result.title
+        };
+        out.push_str(&format!("\n\n{}. {}\n   {}", i + 1, title, result.url));
+        if !result.snippet.is_empty() {
+            let snippet: String = result.snippet.chars().take(SNIPPET_CHARS).collect();
+            let ellipsis = if result.snippet.chars().count() > SNIPPET_CHARS {
+                "…"
+            } else {
+                ""
+            };
+            out.push_str(&format!("\n   {}{}", snippet, ellipsis));
+        }
+    }
+    out
+}
+
+// Snippets often carry <strong> highlights and entities
+fn strip_tags(text: &str) -> String {
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    tags.replace_all(text, "")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn request_error(context: &str, e: reqwest::Error) -> ToolError {
+    let message = format!("{}: {}", context, e);
+    if e.is_timeout() {
+        ToolError::Timeout(message)
+    } else {
+        ToolError::Io(message)
+    }
+}
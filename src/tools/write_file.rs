@@ -1,8 +1,101 @@
-use std::fs;
+use super::ToolError;
+use crate::utils::unified_diff;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+const MAX_DIFF_LINES: usize = 40;
+
+/// Writes `content` to `path`, replacing any existing file. With
+/// `create_only` it refuses to touch a file that already exists.
+pub fn write_file(path: &str, content: &str, create_only: bool) -> Result<String, ToolError> {
+    if create_only {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => ToolError::InvalidArgument(format!(
+                    "{} already exists (create_only is set); read and edit it instead",
+                    path
+                )),
+                _ => ToolError::io("Failed to write file", e),
+            })?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| ToolError::io("Failed to write file", e))?;
+        return Ok(format!("Created {}", path));
+    }
 
-pub fn write_file(path: &str, content: &str) -> Result<String, String> {
     // Write content to file
-    fs::write(path, content).map_err(|e| format!("Failed to write file: {}", e))?;
+    fs::write(path, content).map_err(|e| ToolError::io("Failed to write file", e))?;
 
     Ok(format!("Successfully wrote to {}", path))
 }
+
+/// Line endings `write_file` content is converted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEndings {
+    #[default]
+    Keep,
+    Lf,
+    Crlf,
+}
+
+impl LineEndings {
+    /// Parses `keep`, `lf` or `crlf` (any case).
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "keep" => Some(Self::Keep),
+            "lf" => Some(Self::Lf),
+            "crlf" => Some(Self::Crlf),
+            _ => None,
+        }
+    }
+}
+
+/// How `write_file` tidies the end and line endings of what it writes.
+/// The default writes content byte-for-byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextFormat {
+    pub ensure_trailing_newline: bool,
+    pub line_endings: LineEndings,
+}
+
+impl TextFormat {
+    /// `content` with line endings converted and, if asked for, a single
+    /// final newline added. Empty content stays empty.
+    pub fn apply(&self, content: &str) -> String {
+        let mut text = match self.line_endings {
+            LineEndings::Keep => content.to_string(),
+            LineEndings::Lf => content.replace("\r\n", "\n"),
+            LineEndings::Crlf => content.replace("\r\n", "\n").replace('\n', "\r\n"),
+        };
+        if self.ensure_trailing_newline && !text.is_empty() && !text.ends_with('\n') {
+            let crlf = self.line_endings == LineEndings::Crlf
+                || (self.line_endings == LineEndings::Keep && text.contains("\r\n"));
+            text.push_str(if crlf { "\r\n" } else { "\n" });
+        }
+        text
+    }
+}
+
+/// If writing `content` to `path` would replace an existing file with
+/// different contents, returns a diff of the change. New files and
+/// identical rewrites return None.
+pub fn overwrite_diff(path: &str, content: &str) -> Option<String> {
+    if !Path::new(path).is_file() {
+        return None;
+    }
+    let Ok(existing) = fs::read_to_string(path) else {
+        return Some("(existing file is not valid UTF-8)".to_string());
+    };
+    if existing == content {
+        return None;
+    }
+    let diff = unified_diff(&existing, content, MAX_DIFF_LINES);
+    Some(if diff.is_empty() {
+        "(whitespace or line-ending changes only)".to_string()
+    } else {
+        diff
+    })
+}
@@ -0,0 +1,170 @@
+use crate::types::{FunctionCall, Message, ToolCall};
+use serde_json::Value;
+
+/// Parses a conversation exported from another tool into our messages.
+///
+/// Accepts a bare messages array, or an object with a `messages` array
+/// (plus an optional top-level `system` prompt, as in Anthropic requests).
+/// Messages may use OpenAI chat format (`tool_calls`, `tool` results) or
+/// Anthropic format (`tool_use` / `tool_result` content blocks). Roles we
+/// don't know are kept as user messages labelled with the original role.
+pub fn parse_transcript(json: &str) -> Result<Vec<Message>, String> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| format!("Invalid transcript JSON: {}", e))?;
+    let (system, raw) = match &value {
+        Value::Array(items) => (None, items),
+        Value::Object(obj) => match obj.get("messages") {
+            Some(Value::Array(items)) => (obj.get("system"), items),
+            _ => return Err("Transcript has no 'messages' array".to_string()),
+        },
+        _ => return Err("Transcript must be a messages array or an object".to_string()),
+    };
+
+    let mut messages = Vec::new();
+    if let Some(system) = system.map(text_of).filter(|s| !s.is_empty()) {
+        messages.push(message("system", Some(system)));
+    }
+    for (i, item) in raw.iter().enumerate() {
+        let role = item["role"]
+            .as_str()
+            .ok_or(format!("Message {} has no role", i + 1))?;
+        match role {
+            "system" | "developer" => {
+                messages.push(message("system", Some(text_of(&item["content"]))))
+            }
+            "user" => messages.extend(user_messages(&item["content"])),
+            "assistant" => messages.push(assistant_message(item, i)),
+            "tool" => {
+                let mut result = message("tool", Some(text_of(&item["content"])));
+                result.tool_call_id = item["tool_call_id"].as_str().map(String::from);
+                result.name = item["name"].as_str().map(String::from);
+                messages.push(result);
+            }
+            other => messages.push(message(
+                "user",
+                Some(format!("[{}] {}", other, text_of(&item["content"]))),
+            )),
+        }
+    }
+    fill_tool_names(&mut messages);
+    Ok(messages)
+}
+
+// A user turn; Anthropic puts tool results here as content blocks, which
+// become separate tool messages ahead of any remaining text
+fn user_messages(content: &Value) -> Vec<Message> {
+    let Value::Array(blocks) = content else {
+        return vec![message("user", Some(text_of(content)))];
+    };
+    let mut out = Vec::new();
+    let mut text = Vec::new();
+    for block in blocks {
+        if block["type"] == "tool_result" {
+            let mut result = message("tool", Some(text_of(&block["content"])));
+            result.tool_call_id = block["tool_use_id"].as_str().map(String::from);
+            out.push(result);
+        } else {
+            text.push(block);
+        }
+    }
+    if !text.is_empty() {
+        let text = text_of(&Value::Array(text.into_iter().cloned().collect()));
+        out.push(message("user", Some(text)));
+    }
+    out
+}
+
+fn assistant_message(item: &Value, index: usize) -> Message {
+    let mut calls = Vec::new();
+    // OpenAI: "tool_calls": [{"id", "function": {"name", "arguments"}}]
+    for (n, call) in item["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        calls.push(tool_call(
+            call["id"].as_str(),
+            &call["function"]["name"],
+            &call["function"]["arguments"],
+            (index, n),
+        ));
+    }
+    // Anthropic: {"type": "tool_use", "id", "name", "input"} content blocks
+    let blocks = item["content"].as_array().into_iter().flatten();
+    for (n, block) in blocks.filter(|b| b["type"] == "tool_use").enumerate() {
+        calls.push(tool_call(
+            block["id"].as_str(),
+            &block["name"],
+            &block["input"],
+            (index, n),
+        ));
+    }
+
+    let text = text_of(&item["content"]);
+    let mut msg = message("assistant", (!text.is_empty()).then_some(text));
+    msg.tool_calls = (!calls.is_empty()).then_some(calls);
+    msg
+}
+
+fn tool_call(id: Option<&str>, name: &Value, arguments: &Value, at: (usize, usize)) -> ToolCall {
+    ToolCall {
+        id: id.map_or_else(|| format!("imported_{}_{}", at.0, at.1), String::from),
+        call_type: "function".to_string(),
+        function: FunctionCall {
+            name: name.as_str().unwrap_or("unknown").to_string(),
+            // Arguments are a JSON string in OpenAI exports, an object elsewhere
+            arguments: match arguments {
+                Value::String(s) => s.clone(),
+                Value::Null => "{}".to_string(),
+                other => other.to_string(),
+            },
+        },
+    }
+}
+
+// Tool results in Anthropic transcripts don't name their tool
+fn fill_tool_names(messages: &mut [Message]) {
+    let names: Vec<(String, String)> = messages
+        .iter()
+        .filter_map(|m| m.tool_calls.as_ref())
+        .flatten()
+        .map(|c| (c.id.clone(), c.function.name.clone()))
+        .collect();
+    for m in messages
+        .iter_mut()
+        .filter(|m| m.role == "tool" && m.name.is_none())
+    {
+        m.name = names
+            .iter()
+            .find(|(id, _)| Some(id) == m.tool_call_id.as_ref())
+            .map(|(_, name)| name.clone());
+    }
+}
+
+// Plain text of a content value: a string, or the text blocks of an array
+fn text_of(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                Value::String(s) => Some(s.as_str()),
+                _ => b["text"].as_str(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn message(role: &str, content: Option<String>) -> Message {
+    Message {
+        role: role.to_string(),
+        content,
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+        created_at: None,
+    }
+}
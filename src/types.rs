@@ -23,6 +23,12 @@ pub struct Message {
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+    // Function name on "tool" messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Local bookkeeping only; stripped from provider requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<chrono::DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -33,4 +39,28 @@ pub struct Session {
     pub updated_at: chrono::DateTime<Utc>,
     pub title: Option<String>,
     pub model: Option<String>,
+    // Files whose current contents are re-sent every turn (see /pin)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_files: Vec<String>,
+    // Directory run_shell runs in after a `cd`; None is termx's own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell_cwd: Option<String>,
+    // File mutations so far, checked against the agent's write limits
+    #[serde(skip)]
+    pub write_stats: WriteStats,
+    // Files changed and commands run, for the /summary report
+    #[serde(skip)]
+    pub changes: crate::changes::ChangeLog,
+    // Where and when the session is saved as it changes (see enable_autosave)
+    #[serde(skip)]
+    pub autosave: Option<crate::session::Autosave>,
+    // Plain-text log appended as messages arrive (see enable_transcript)
+    #[serde(skip)]
+    pub transcript: Option<std::path::PathBuf>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WriteStats {
+    pub files: std::collections::HashSet<String>,
+    pub bytes: usize,
 }
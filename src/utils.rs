@@ -1,3 +1,5 @@
+use crate::style::{CYAN, GRAY, GREEN, RED, paint};
+
 pub fn clip(s: &str, max: usize) -> String {
     if s.len() <= max {
         return s.to_string();
@@ -7,6 +9,29 @@ pub fn clip(s: &str, max: usize) -> String {
     out
 }
 
+const TRUNCATION_HEADER: &str = "[truncated: showing ";
+
+/// Clips a tool observation to `max` characters for the model's context,
+/// prefixing a header with the original length (e.g. "[truncated: showing
+/// 4000 of 51234 chars]") so the model knows how much it didn't see and can
+/// ask for a narrower view. Clipping text that already has the header again
+/// only shortens it further, keeping the original length.
+pub fn clip_observation(s: &str, max: usize) -> String {
+    let (total, body) = clipped_parts(s).unwrap_or((s.chars().count(), s));
+    if body.chars().count() <= max {
+        return s.to_string();
+    }
+    let head: String = body.chars().take(max).collect();
+    format!("{}{} of {} chars]\n{}", TRUNCATION_HEADER, max, total, head)
+}
+
+// Original length and kept text of an observation clipped above
+fn clipped_parts(s: &str) -> Option<(usize, &str)> {
+    let (meta, body) = s.strip_prefix(TRUNCATION_HEADER)?.split_once("]\n")?;
+    let total = meta.split_once(" of ")?.1.strip_suffix(" chars")?;
+    Some((total.parse().ok()?, body))
+}
+
 pub fn display_diff_side_by_side(old_str: &str, new_str: &str) {
     let old_lines: Vec<&str> = old_str.lines().collect();
     let new_lines: Vec<&str> = new_str.lines().collect();
@@ -16,21 +41,24 @@ pub fn display_diff_side_by_side(old_str: &str, new_str: &str) {
     // Calculate max width for left column (cap at 50 for readability)
     let left_width = old_lines.iter().map(|l| l.len()).max().unwrap_or(0).min(50);
 
-    println!("\u{001b}[36m╭─ Changes\u{001b}[0m");
+    println!("{}", paint(CYAN, "╭─ Changes"));
     println!(
-        "\u{001b}[90m│ {:width$} │ \u{001b}[0m",
-        "Before",
-        width = left_width
+        "{}",
+        paint(
+            GRAY,
+            &format!("│ {:width$} │ ", "Before", width = left_width)
+        )
     );
     println!(
-        "\u{001b}[90m│ {:width$} │ After\u{001b}[0m",
-        "",
-        width = left_width
+        "{}",
+        paint(
+            GRAY,
+            &format!("│ {:width$} │ After", "", width = left_width)
+        )
     );
     println!(
-        "\u{001b}[36m├─{:─<width$}─┼─\u{001b}[0m",
-        "",
-        width = left_width
+        "{}",
+        paint(CYAN, &format!("├─{:─<width$}─┼─", "", width = left_width))
     );
 
     for i in 0..max_lines {
@@ -51,16 +79,117 @@ pub fn display_diff_side_by_side(old_str: &str, new_str: &str) {
         };
 
         println!(
-            "\u{001b}[31m│ {:width$}\u{001b}[0m \u{001b}[90m│\u{001b}[0m \u{001b}[32m{}\u{001b}[0m",
-            old_display,
-            new_display,
-            width = left_width
+            "{} {} {}",
+            paint(
+                RED,
+                &format!("│ {:width$}", old_display, width = left_width)
+            ),
+            paint(GRAY, "│"),
+            paint(GREEN, &new_display)
         );
     }
 
     if old_lines.len() > max_lines || new_lines.len() > max_lines {
-        println!("\u{001b}[90m│ ... (truncated)\u{001b}[0m");
+        println!("{}", paint(GRAY, "│ ... (truncated)"));
+    }
+
+    println!("{}", paint(CYAN, "╰─"));
+}
+const DIFF_CONTEXT: usize = 3;
+// Above this many cells the LCS table gets expensive; fall back to -/+ blocks
+const DIFF_MAX_CELLS: usize = 1_000_000;
+
+/// Compact single-hunk unified diff of `old` vs `new`, capped at `max_lines`
+/// diff lines. Returns an empty string when nothing changed.
+pub fn unified_diff(old: &str, new: &str, max_lines: usize) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old_lines[prefix..old_lines.len() - suffix];
+    let new_mid = &new_lines[prefix..new_lines.len() - suffix];
+    if old_mid.is_empty() && new_mid.is_empty() {
+        return String::new();
+    }
+
+    let ctx_before = prefix.min(DIFF_CONTEXT);
+    let ctx_after = suffix.min(DIFF_CONTEXT);
+
+    let mut body: Vec<String> = Vec::new();
+    for l in &old_lines[prefix - ctx_before..prefix] {
+        body.push(format!(" {}", l));
+    }
+    body.extend(diff_lines(old_mid, new_mid));
+    for l in &old_lines[old_lines.len() - suffix..old_lines.len() - suffix + ctx_after] {
+        body.push(format!(" {}", l));
+    }
+
+    let start = prefix - ctx_before + 1;
+    let mut out = format!(
+        "@@ -{},{} +{},{} @@",
+        start,
+        ctx_before + old_mid.len() + ctx_after,
+        start,
+        ctx_before + new_mid.len() + ctx_after
+    );
+    let total = body.len();
+    for line in body.into_iter().take(max_lines) {
+        out.push('\n');
+        out.push_str(&line);
+    }
+    if total > max_lines {
+        out.push_str(&format!("\n… ({} more diff lines)", total - max_lines));
+    }
+    out
+}
+
+// Line-level LCS diff of two (small) slices
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<String> {
+    if old.len() * new.len() > DIFF_MAX_CELLS {
+        return old
+            .iter()
+            .map(|l| format!("-{}", l))
+            .chain(new.iter().map(|l| format!("+{}", l)))
+            .collect();
+    }
+
+    // lcs[i][j] = LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
     }
 
-    println!("\u{001b}[36m╰─\u{001b}[0m");
-}
\ No newline at end of file
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push(format!(" {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push(format!("+{}", new[j]));
+            j += 1;
+        } else {
+            out.push(format!("-{}", old[i]));
+            i += 1;
+        }
+    }
+    out
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the per-project directory holding termx runtime artifacts.
+pub const AGENT_DIR: &str = ".termx";
+pub const SESSIONS_DIR: &str = "sessions";
+pub const UNDO_DIR: &str = "undo";
+pub const TRASH_DIR: &str = "trash";
+pub const TRANSCRIPTS_DIR: &str = "transcripts";
+/// File under the agent directory holding prompts from earlier runs.
+pub const HISTORY_FILE: &str = "history";
+
+// Everything under .termx is local state (sessions, backups, logs),
+// so the directory ignores itself entirely.
+const GITIGNORE_CONTENTS: &str = "*\n";
+
+/// Creates `<root>/.termx` with its `.gitignore` and standard subdirectories.
+/// Safe to call on every startup.
+pub fn init_agent_dir(root: &Path) -> io::Result<PathBuf> {
+    let dir = root.join(AGENT_DIR);
+    fs::create_dir_all(&dir)?;
+
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(&gitignore, GITIGNORE_CONTENTS)?;
+    }
+
+    for name in [SESSIONS_DIR, UNDO_DIR, TRASH_DIR] {
+        ensure_subdir(root, name)?;
+    }
+    Ok(dir)
+}
+
+/// Returns `<root>/.termx/<name>`, creating it if needed.
+pub fn ensure_subdir(root: &Path, name: &str) -> io::Result<PathBuf> {
+    let dir = root.join(AGENT_DIR).join(name);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}